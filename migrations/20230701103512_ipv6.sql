@@ -0,0 +1,4 @@
+ALTER TABLE ips
+ADD addr6 BLOB(16);
+
+CREATE UNIQUE INDEX ips_addr6_index ON ips (addr6);
@@ -0,0 +1,4 @@
+ALTER TABLE configs
+ADD deleted_at INTEGER;
+
+UPDATE configs SET deleted_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE deleted = 1;
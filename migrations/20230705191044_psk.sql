@@ -0,0 +1,2 @@
+ALTER TABLE keys
+ADD psk BLOB(32);
@@ -0,0 +1,15 @@
+CREATE TABLE profiles (
+    id BLOB(16) PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    routing INTEGER NOT NULL,
+    allowed_ips TEXT NOT NULL DEFAULT '',
+    dns TEXT NOT NULL DEFAULT '',
+    mtu INTEGER,
+    keepalive INTEGER
+);
+
+INSERT INTO profiles (id, name, routing) VALUES(randomblob(16), 'full-tunnel', 0);
+INSERT INTO profiles (id, name, routing) VALUES(randomblob(16), 'internal-only', 1);
+
+ALTER TABLE configs
+ADD profile_id BLOB(16) REFERENCES profiles(id);
@@ -0,0 +1,14 @@
+CREATE TABLE requests (
+    id BLOB(16) PRIMARY KEY NOT NULL,
+    user_id BLOB(16) NOT NULL,
+    status INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    key TEXT,
+    config_id BLOB(16),
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id),
+    FOREIGN KEY(config_id) REFERENCES configs(id)
+);
+
+CREATE INDEX requests_user_index ON requests (user_id);
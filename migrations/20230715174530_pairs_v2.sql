@@ -0,0 +1,27 @@
+CREATE TABLE pair_codes (
+    id BLOB(16) PRIMARY KEY NOT NULL,
+    config_id BLOB(16) NOT NULL,
+    user_id BLOB(16) NOT NULL,
+    expires_at INTEGER NOT NULL,
+    used_at INTEGER,
+    FOREIGN KEY(config_id) REFERENCES configs(id),
+    FOREIGN KEY(user_id) REFERENCES users(id)
+);
+
+CREATE TABLE pairs (
+    config_id BLOB(16) NOT NULL,
+    user_id BLOB(16) NOT NULL,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY(config_id) REFERENCES configs(id),
+    FOREIGN KEY(user_id) REFERENCES users(id),
+    UNIQUE(config_id, user_id)
+);
+
+-- pairings made before v2 point at an address and a telegram chat
+INSERT OR IGNORE INTO pairs (config_id, user_id, created_at)
+    SELECT ips.config_id, integrations.user_id, CAST(strftime('%s', 'now') AS INTEGER)
+        FROM integration
+            JOIN ips ON ips.addr = integration.ip
+            JOIN integrations ON integrations.telegram_id = integration.telegram_id;
+
+DROP TABLE integration;
@@ -0,0 +1,2 @@
+ALTER TABLE configs
+ADD double_vpn BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+CREATE TABLE exits (
+    id BLOB(16) PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    table_id INTEGER NOT NULL,
+    fwmark INTEGER,
+    priority INTEGER NOT NULL
+);
+
+-- double_vpn is converted to an exit on startup if DVPN_TABLE is set
+ALTER TABLE configs
+ADD exit_id BLOB(16) REFERENCES exits(id);
@@ -0,0 +1,3 @@
+-- configs without an interface are assigned to the primary one on startup
+ALTER TABLE configs
+ADD interface TEXT;
@@ -0,0 +1,22 @@
+-- raw per-interval deltas written by the stats worker
+CREATE TABLE traffic_samples (
+    config_id BLOB(16) NOT NULL,
+    ts INTEGER NOT NULL,
+    tx INTEGER NOT NULL,
+    rx INTEGER NOT NULL,
+    FOREIGN KEY(config_id) REFERENCES configs(id)
+);
+CREATE INDEX traffic_samples_config_ts ON traffic_samples(config_id, ts);
+CREATE INDEX traffic_samples_ts ON traffic_samples(ts);
+
+-- period: 0 hour, 1 day, 2 month; start is the utc unix timestamp the bucket begins at
+CREATE TABLE traffic_rollups (
+    config_id BLOB(16) NOT NULL,
+    period INTEGER NOT NULL,
+    start INTEGER NOT NULL,
+    tx INTEGER NOT NULL,
+    rx INTEGER NOT NULL,
+    PRIMARY KEY(config_id, period, start),
+    FOREIGN KEY(config_id) REFERENCES configs(id)
+);
+CREATE INDEX traffic_rollups_period_start ON traffic_rollups(period, start);
@@ -0,0 +1,14 @@
+-- exactly one of user_id and config_id is set; period is a rollup period (1 day, 2 month)
+CREATE TABLE quotas (
+    user_id BLOB(16) UNIQUE,
+    config_id BLOB(16) UNIQUE,
+    limit_bytes INTEGER NOT NULL,
+    period INTEGER NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id),
+    FOREIGN KEY(config_id) REFERENCES configs(id),
+    CHECK ((user_id IS NULL) != (config_id IS NULL))
+);
+
+-- set while the peer is out of the kernel because a quota is exhausted
+ALTER TABLE configs
+ADD suspended_at INTEGER;
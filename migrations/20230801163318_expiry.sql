@@ -0,0 +1,9 @@
+ALTER TABLE configs
+ADD expires_at INTEGER;
+
+ALTER TABLE configs
+ADD expiry_warned_at INTEGER;
+
+-- set while the peer is out of the kernel because expires_at has passed
+ALTER TABLE configs
+ADD expired_at INTEGER;
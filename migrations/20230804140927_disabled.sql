@@ -0,0 +1,3 @@
+-- set while an admin has taken the config out of the kernel
+ALTER TABLE configs
+ADD disabled_at INTEGER;
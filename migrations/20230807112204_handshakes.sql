@@ -0,0 +1,6 @@
+-- last seen state of the peer, written by the stats worker
+ALTER TABLE stats_v2
+ADD last_handshake INTEGER;
+
+ALTER TABLE stats_v2
+ADD endpoint TEXT;
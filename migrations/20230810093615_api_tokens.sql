@@ -0,0 +1,14 @@
+-- personal tokens for the HTTP API, only the sha256 of the secret is stored
+CREATE TABLE api_tokens (
+    id BLOB(16) PRIMARY KEY NOT NULL,
+    user_id BLOB(16) NOT NULL,
+    name TEXT NOT NULL,
+    hash BLOB(32) NOT NULL UNIQUE,
+    -- comma separated scopes, e.g. read,configs:write
+    scopes TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER,
+    FOREIGN KEY(user_id) REFERENCES users(id)
+);
+
+CREATE INDEX api_tokens_user_id_index ON api_tokens (user_id);
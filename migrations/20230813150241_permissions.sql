@@ -0,0 +1,15 @@
+CREATE TABLE role_permissions (
+    role_id BLOB(16) NOT NULL,
+    permission TEXT NOT NULL,
+    PRIMARY KEY(role_id, permission),
+    FOREIGN KEY(role_id) REFERENCES roles(id)
+);
+
+-- the admin role keeps everything it could do before permissions existed
+INSERT INTO role_permissions (role_id, permission) VALUES
+(X'22129C89706949CE9F4AF85004A7F230', 'configs.create'),
+(X'22129C89706949CE9F4AF85004A7F230', 'configs.manage_all'),
+(X'22129C89706949CE9F4AF85004A7F230', 'requests.approve'),
+(X'22129C89706949CE9F4AF85004A7F230', 'admins.manage'),
+(X'22129C89706949CE9F4AF85004A7F230', 'stats.view_all'),
+(X'22129C89706949CE9F4AF85004A7F230', 'settings.manage');
@@ -0,0 +1,28 @@
+-- who changed what, rows are never updated or removed
+CREATE TABLE audit_log (
+    id BLOB(16) PRIMARY KEY NOT NULL,
+    created_at INTEGER NOT NULL,
+    -- NULL for changes made by the service itself, e.g. expiry
+    actor_id BLOB(16),
+    frontend TEXT NOT NULL,
+    action TEXT NOT NULL,
+    user_id BLOB(16),
+    config_id BLOB(16),
+    before TEXT,
+    after TEXT
+);
+
+CREATE INDEX audit_log_created_at_index ON audit_log (created_at);
+CREATE INDEX audit_log_actor_id_index ON audit_log (actor_id);
+CREATE INDEX audit_log_user_id_index ON audit_log (user_id);
+CREATE INDEX audit_log_config_id_index ON audit_log (config_id);
+
+CREATE TRIGGER audit_log_no_update BEFORE UPDATE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'audit log is append-only');
+END;
+
+CREATE TRIGGER audit_log_no_delete BEFORE DELETE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'audit log is append-only');
+END;
@@ -0,0 +1,6 @@
+-- serialized admin dialogue state per chat, see ui::telegram::storage
+CREATE TABLE telegram_dialogues (
+    chat_id INTEGER PRIMARY KEY NOT NULL,
+    state TEXT NOT NULL,
+    updated_at INTEGER NOT NULL
+);
@@ -0,0 +1,4 @@
+-- tokens from /apitoken carry the epoch they were issued in, bumping it
+-- invalidates all of them
+ALTER TABLE users
+ADD token_epoch INTEGER NOT NULL DEFAULT 0;
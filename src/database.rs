@@ -1,4 +1,7 @@
-use std::{net::Ipv4Addr, str::FromStr};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
 
 use async_trait::async_trait;
 use netlink_packet_wireguard::constants::WG_KEY_LEN;
@@ -7,10 +10,21 @@ use sqlx::{
     SqlitePool,
 };
 use thiserror::Error;
+use time::OffsetDateTime;
 use uuid::Uuid;
 
 use crate::{
-    service::{configs::Config, keys::Key, Association},
+    roles::{Permission, Role},
+    service::{
+        audit::{AuditEntry, AuditFilter},
+        auth::{join_scopes, parse_scopes, ApiToken},
+        configs::Config,
+        exits::Exit,
+        keys::Key,
+        profiles::{join, parse_list, Profile, Routing},
+        quotas::{Quota, QuotaTarget},
+        Association,
+    },
     traits::TelegramDb,
 };
 
@@ -19,6 +33,94 @@ pub struct Stats {
 
     pub tx: u64,
     pub rx: u64,
+    /// Unix timestamp of the latest handshake seen by the stats worker
+    pub last_handshake: Option<i64>,
+    pub endpoint: Option<String>,
+}
+
+/// Lifetime counters of a config that is not deleted
+pub struct ConfigStats {
+    pub config_id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub interface: String,
+    pub tx: u64,
+    pub rx: u64,
+    pub last_handshake: Option<i64>,
+}
+
+/// Peer with a recent handshake, see [`Database::connected_peers`]
+pub struct ConnectedPeer {
+    pub config_id: Uuid,
+    pub name: String,
+    pub ip: Ipv4Addr,
+    pub interface: String,
+    pub last_handshake: i64,
+    pub endpoint: Option<String>,
+}
+
+/// A user with what is shown in the user list of the admin menu
+#[derive(Debug, Clone)]
+pub struct UserSummary {
+    pub id: Uuid,
+    pub telegram_id: Option<i64>,
+    pub roles: Vec<Uuid>,
+    /// Configs that are not deleted
+    pub configs: usize,
+    /// Bytes of all configs, deleted ones included
+    pub tx: u64,
+    pub rx: u64,
+}
+
+/// Rollup bucket size, stored as its discriminant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Period {
+    Hour = 0,
+    Day = 1,
+    Month = 2,
+}
+
+impl Period {
+    /// Unix timestamp of the utc bucket `at` falls into
+    pub fn start(self, at: OffsetDateTime) -> i64 {
+        let at = at.to_offset(time::UtcOffset::UTC);
+        let start = match self {
+            Period::Hour => at.replace_time(time::Time::from_hms(at.hour(), 0, 0).unwrap()),
+            Period::Day => at.replace_time(time::Time::MIDNIGHT),
+            Period::Month => at
+                .replace_time(time::Time::MIDNIGHT)
+                .replace_day(1)
+                .unwrap(),
+        };
+        start.unix_timestamp()
+    }
+}
+
+impl TryFrom<i64> for Period {
+    type Error = DatabaseError;
+
+    fn try_from(value: i64) -> Result<Self> {
+        match value {
+            0 => Ok(Period::Hour),
+            1 => Ok(Period::Day),
+            2 => Ok(Period::Month),
+            _ => Err(DatabaseError::InvalidPeriodData),
+        }
+    }
+}
+
+/// Traffic of a config in one rollup bucket
+pub struct Usage {
+    pub config_id: Uuid,
+    pub user_id: Uuid,
+    pub bytes: u64,
+}
+
+pub struct TrafficPoint {
+    /// Unix timestamp of the sample or the beginning of the bucket
+    pub start: i64,
+    pub tx: u64,
+    pub rx: u64,
 }
 
 pub struct FullConfig {
@@ -26,10 +128,22 @@ pub struct FullConfig {
     pub stats: Stats,
 }
 
+pub struct Pair {
+    pub config_id: Uuid,
+    pub name: String,
+    pub ip: Ipv4Addr,
+}
+
 pub struct Request {
     pub id: Uuid,
+    pub user_id: Uuid,
     pub telegram_id: Option<i64>,
-    pub status: i32,
+    pub status: i64,
+    pub name: String,
+    pub key: Option<String>,
+    pub config_id: Option<Uuid>,
+    pub created_at: i64,
+    pub updated_at: i64,
 }
 
 #[derive(Debug, Error)]
@@ -40,8 +154,20 @@ pub enum DatabaseError {
     Sqlx(#[from] sqlx::Error),
     #[error("invalid pubkey data")]
     InvalidPubkeyData,
+    #[error("invalid preshared key data")]
+    InvalidPskData,
     #[error("invalid uuid data")]
     InvalidUuidData,
+    #[error("invalid period data")]
+    InvalidPeriodData,
+    #[error("invalid address data")]
+    InvalidAddressData,
+    #[error("invalid profile data")]
+    InvalidProfileData,
+    #[error("invalid scope data")]
+    InvalidScopeData,
+    #[error("invalid frontend data")]
+    InvalidFrontendData,
 }
 
 impl From<uuid::Error> for DatabaseError {
@@ -57,6 +183,42 @@ pub struct Database {
 
 type Result<T> = std::result::Result<T, DatabaseError>;
 
+#[allow(clippy::too_many_arguments)]
+fn profile_from_db(
+    id: Vec<u8>,
+    name: String,
+    routing: i64,
+    allowed_ips: String,
+    dns: String,
+    mtu: Option<i64>,
+    keepalive: Option<i64>,
+) -> Result<Profile> {
+    let routing = match routing {
+        0 => Routing::Full,
+        1 => Routing::Internal,
+        _ => Routing::Custom(
+            parse_list(&allowed_ips).map_err(|_| DatabaseError::InvalidProfileData)?,
+        ),
+    };
+    Ok(Profile {
+        id: Uuid::from_slice(&id)?,
+        name,
+        routing,
+        dns: parse_list(&dns).map_err(|_| DatabaseError::InvalidProfileData)?,
+        mtu: mtu.map(|m| m as _),
+        keepalive: keepalive.map(|k| k as _),
+    })
+}
+
+fn ipv6_from_db(addr: Option<Vec<u8>>) -> Result<Option<Ipv6Addr>> {
+    addr.map(|a| {
+        <[u8; 16]>::try_from(a)
+            .map(Ipv6Addr::from)
+            .map_err(|_| DatabaseError::InvalidAddressData)
+    })
+    .transpose()
+}
+
 impl Database {
     pub async fn new(connstr: &str) -> Result<Self> {
         let pool = SqlitePoolOptions::new()
@@ -72,7 +234,7 @@ impl Database {
 
         let t = sqlx::query!(
             // sqlite
-            "SELECT configs.*, ips.*, keys.priv_key
+            "SELECT configs.*, ips.*, keys.priv_key, keys.psk
             FROM configs 
             INNER JOIN ips ON ips.config_id = configs.id
             LEFT JOIN keys ON keys.key = configs.key AND keys.user_id = configs.user_id
@@ -87,13 +249,27 @@ impl Database {
                 id: Uuid::from_slice(&t.id)?,
                 user_id: Uuid::from_slice(&t.user_id)?,
                 ip: Ipv4Addr::from(t.addr as u32),
+                ip6: ipv6_from_db(t.addr6)?,
                 name: t.name,
                 deleted: t.deleted,
+                profile_id: t.profile_id.map(|p| Uuid::from_slice(&p)).transpose()?,
+                exit_id: t.exit_id.map(|e| Uuid::from_slice(&e)).transpose()?,
+                interface: t.interface.unwrap_or_default(),
+                suspended: t.suspended_at.is_some(),
+                disabled: t.disabled_at.is_some(),
+                expires_at: t.expires_at,
+                expiry_warned: t.expiry_warned_at.is_some(),
+                expired: t.expired_at.is_some(),
                 priv_key: t
                     .priv_key
                     .map(|t| t.try_into())
                     .transpose()
                     .map_err(|_| DatabaseError::InvalidPubkeyData)?,
+                psk: t
+                    .psk
+                    .map(|t| t.try_into())
+                    .transpose()
+                    .map_err(|_| DatabaseError::InvalidPskData)?,
                 pub_key: t
                     .key
                     .try_into()
@@ -107,15 +283,19 @@ impl Database {
     pub async fn update_config(&self, c: Config) -> Result<()> {
         let t = &c.id.as_bytes()[..];
         let pk = &c.pub_key[..];
+        let profile_id = c.profile_id.as_ref().map(|p| &p.as_bytes()[..]);
+        let exit_id = c.exit_id.as_ref().map(|e| &e.as_bytes()[..]);
 
         sqlx::query!(
             // sqlite
             "UPDATE configs
-            SET key=$2, name=$3
+            SET key=$2, name=$3, profile_id=$4, exit_id=$5
             WHERE id = $1",
             t,
             pk,
             c.name,
+            profile_id,
+            exit_id,
         )
         .execute(&self.pool)
         .await?;
@@ -124,13 +304,15 @@ impl Database {
 
     pub async fn rm_config(&self, id: Uuid) -> Result<()> {
         let t = &id.as_bytes()[..];
+        let now = OffsetDateTime::now_utc().unix_timestamp();
 
         sqlx::query!(
             // sqlite
             "UPDATE configs 
-            SET deleted = 1
+            SET deleted = 1, deleted_at = $2
             WHERE configs.id = $1",
-            t
+            t,
+            now
         )
         .execute(&self.pool)
         .await?;
@@ -142,7 +324,8 @@ impl Database {
 
         let t = sqlx::query!(
             // sqlite
-            "SELECT configs.*, ips.*, stats_v2.tx, stats_v2.rx, keys.priv_key
+            "SELECT configs.*, ips.*, stats_v2.tx, stats_v2.rx, stats_v2.last_handshake, stats_v2.endpoint,
+            keys.priv_key, keys.psk
             FROM configs 
             INNER JOIN ips ON ips.config_id = configs.id
             LEFT JOIN keys ON keys.key = configs.key AND keys.user_id = configs.user_id
@@ -158,13 +341,27 @@ impl Database {
                 id: Uuid::from_slice(&t.id)?,
                 user_id: Uuid::from_slice(&t.user_id)?,
                 ip: Ipv4Addr::from(t.addr as u32),
+                ip6: ipv6_from_db(t.addr6)?,
                 name: t.name,
                 deleted: t.deleted,
+                profile_id: t.profile_id.map(|p| Uuid::from_slice(&p)).transpose()?,
+                exit_id: t.exit_id.map(|e| Uuid::from_slice(&e)).transpose()?,
+                interface: t.interface.unwrap_or_default(),
+                suspended: t.suspended_at.is_some(),
+                disabled: t.disabled_at.is_some(),
+                expires_at: t.expires_at,
+                expiry_warned: t.expiry_warned_at.is_some(),
+                expired: t.expired_at.is_some(),
                 priv_key: t
                     .priv_key
                     .map(|t| t.try_into())
                     .transpose()
                     .map_err(|_| DatabaseError::InvalidPubkeyData)?,
+                psk: t
+                    .psk
+                    .map(|t| t.try_into())
+                    .transpose()
+                    .map_err(|_| DatabaseError::InvalidPskData)?,
                 pub_key: t
                     .key
                     .try_into()
@@ -174,6 +371,8 @@ impl Database {
                 pub_key: config.pub_key,
                 tx: t.tx.unwrap_or_default() as _,
                 rx: t.rx.unwrap_or_default() as _,
+                last_handshake: t.last_handshake,
+                endpoint: t.endpoint,
             };
             Ok(FullConfig { config, stats })
         })
@@ -183,7 +382,7 @@ impl Database {
     pub async fn configs(&self) -> Result<Vec<Config>> {
         let t = sqlx::query!(
             // sqlite
-            "SELECT configs.*, ips.*, keys.priv_key
+            "SELECT configs.*, ips.*, keys.priv_key, keys.psk
             FROM configs 
             LEFT JOIN keys ON keys.key = configs.key AND keys.user_id = configs.user_id
             INNER JOIN ips ON ips.config_id = configs.id",
@@ -197,13 +396,27 @@ impl Database {
                     id: Uuid::from_slice(&t.id)?,
                     user_id: Uuid::from_slice(&t.user_id)?,
                     ip: Ipv4Addr::from(t.addr as u32),
+                    ip6: ipv6_from_db(t.addr6)?,
                     name: t.name,
                     deleted: t.deleted,
+                    profile_id: t.profile_id.map(|p| Uuid::from_slice(&p)).transpose()?,
+                    exit_id: t.exit_id.map(|e| Uuid::from_slice(&e)).transpose()?,
+                    interface: t.interface.unwrap_or_default(),
+                    suspended: t.suspended_at.is_some(),
+                    disabled: t.disabled_at.is_some(),
+                    expires_at: t.expires_at,
+                    expiry_warned: t.expiry_warned_at.is_some(),
+                    expired: t.expired_at.is_some(),
                     priv_key: t
                         .priv_key
                         .map(|t| t.try_into())
                         .transpose()
                         .map_err(|_| DatabaseError::InvalidPubkeyData)?,
+                    psk: t
+                        .psk
+                        .map(|t| t.try_into())
+                        .transpose()
+                        .map_err(|_| DatabaseError::InvalidPskData)?,
                     pub_key: t
                         .key
                         .try_into()
@@ -215,27 +428,31 @@ impl Database {
             .collect()
     }
 
-    pub async fn add_config(&self, config: Config) -> Result<()> {
+    pub async fn add_config(&self, config: &Config) -> Result<()> {
         let ip: u32 = config.ip.into();
+        let ip6 = config.ip6.map(|a| a.octets().to_vec());
         let pk = config.pub_key.to_vec();
         let id = &config.id.as_bytes()[..];
         let user_id = &config.user_id.as_bytes()[..];
         let mut tx = self.pool.begin().await?;
         sqlx::query!(
             // sqlite
-            "INSERT INTO configs(id, user_id, key, name) VALUES($1, $2, $3, $4)",
+            "INSERT INTO configs(id, user_id, key, name, interface, expires_at) VALUES($1, $2, $3, $4, $5, $6)",
             id,
             user_id,
             pk,
-            config.name
+            config.name,
+            config.interface,
+            config.expires_at
         )
         .execute(&mut tx)
         .await?;
         sqlx::query!(
             // sqlite
-            "INSERT INTO ips(config_id, addr) VALUES($1, $2)",
+            "INSERT INTO ips(config_id, addr, addr6) VALUES($1, $2, $3)",
             id,
-            ip
+            ip,
+            ip6
         )
         .execute(&mut tx)
         .await?;
@@ -243,216 +460,1707 @@ impl Database {
         Ok(())
     }
 
-    pub async fn configs_count(&self) -> Result<usize> {
-        Ok(sqlx::query!(
+    pub async fn used_addrs(&self) -> Result<(Vec<Ipv4Addr>, Vec<Ipv6Addr>)> {
+        let rows = sqlx::query!(
             // sqlite
-            "SELECT COUNT(*) as count 
-            FROM configs"
+            "SELECT addr, addr6 FROM ips"
         )
-        .fetch_one(&self.pool)
-        .await?
-        .count as _)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut v4 = Vec::with_capacity(rows.len());
+        let mut v6 = Vec::new();
+        for r in rows {
+            v4.push(Ipv4Addr::from(r.addr as u32));
+            if let Some(a) = ipv6_from_db(r.addr6)? {
+                v6.push(a);
+            }
+        }
+        Ok((v4, v6))
     }
 
-    pub async fn is_paired(&self, uid: i64, ip: Ipv4Addr) -> Result<bool> {
-        let ip: u32 = ip.into();
+    pub async fn set_addr6(&self, config_id: Uuid, addr6: Ipv6Addr) -> Result<()> {
+        let config_id = &config_id.as_bytes()[..];
+        let addr6 = &addr6.octets()[..];
+        sqlx::query!(
+            // sqlite
+            "UPDATE ips SET addr6 = $2 WHERE config_id = $1",
+            config_id,
+            addr6
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Frees addresses of configs deleted before `deleted_before` (unix time).
+    pub async fn release_addrs(&self, deleted_before: i64) -> Result<u64> {
         Ok(sqlx::query!(
             // sqlite
-            "SELECT COUNT(*) as count FROM integration
-            WHERE ip = $1 AND telegram_id = $2 LIMIT 1",
-            ip,
-            uid
+            "DELETE FROM ips WHERE config_id IN (
+                SELECT id FROM configs
+                WHERE deleted = 1 AND deleted_at < $1
+            )",
+            deleted_before
         )
-        .fetch_one(&self.pool)
+        .execute(&self.pool)
         .await?
-        .count
-            > 0)
+        .rows_affected())
     }
 
-    pub async fn update_peers_stats(&self, delta: Vec<([u8; WG_KEY_LEN], u64, u64)>) -> Result<()> {
-        let mut trans = self.pool.begin().await?;
-        for (id, tx, rx) in delta {
-            let tx = tx as i64;
-            let rx = rx as i64;
-            let id = &id[..];
-
-            sqlx::query!(
-                // sqlite
-                "INSERT INTO stats_v2 VALUES($3, $1, $2) 
-                ON CONFLICT(key) DO UPDATE SET 
-                tx = tx + excluded.tx,
-                rx = rx + excluded.rx",
-                tx,
-                rx,
-                id,
+    pub async fn profiles(&self) -> Result<Vec<Profile>> {
+        sqlx::query!(
+            // sqlite
+            "SELECT * FROM profiles ORDER BY name"
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|p| {
+            profile_from_db(
+                p.id,
+                p.name,
+                p.routing,
+                p.allowed_ips,
+                p.dns,
+                p.mtu,
+                p.keepalive,
             )
-            .execute(&mut trans)
-            .await?;
-        }
-        trans.commit().await?;
+        })
+        .collect()
+    }
 
-        Ok(())
+    pub async fn profile(&self, id: Uuid) -> Result<Option<Profile>> {
+        let id = &id.as_bytes()[..];
+        sqlx::query!(
+            // sqlite
+            "SELECT * FROM profiles WHERE id = $1",
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(|p| {
+            profile_from_db(
+                p.id,
+                p.name,
+                p.routing,
+                p.allowed_ips,
+                p.dns,
+                p.mtu,
+                p.keepalive,
+            )
+        })
+        .transpose()
     }
 
-    pub async fn user_id(&self, association: Association) -> Result<Uuid> {
-        let uid = match association {
-            Association::Telegram(uid) => {
-                Uuid::from_bytes(
-                    sqlx::query!(
-                        // sqlite
-                        "SELECT user_id FROM integrations WHERE telegram_id = $1",
-                        uid
-                    )
-                    .fetch_one(&self.pool)
-                    .await?
-                    .user_id
-                    .try_into()
-                    .unwrap(),
-                )
-            }
+    pub async fn add_profile(&self, p: &Profile) -> Result<()> {
+        let id = &p.id.as_bytes()[..];
+        let routing = p.routing.kind();
+        let allowed_ips = match &p.routing {
+            Routing::Custom(ips) => join(ips, ","),
+            _ => String::new(),
         };
+        let dns = join(&p.dns, ",");
+        let mtu = p.mtu.map(|m| m as i64);
+        let keepalive = p.keepalive.map(|k| k as i64);
 
-        Ok(uid)
-    }
-
-    pub async fn rm_user_role(&self, uid: Uuid, role_id: Uuid) -> Result<()> {
-        let id = uid.as_bytes().as_slice();
-        let role = role_id.as_bytes().as_slice();
         sqlx::query!(
             // sqlite
-            "DELETE FROM user_roles WHERE user_id=$1 AND role_id=$2",
+            "INSERT INTO profiles(id, name, routing, allowed_ips, dns, mtu, keepalive)
+            VALUES($1, $2, $3, $4, $5, $6, $7)",
             id,
-            role
+            p.name,
+            routing,
+            allowed_ips,
+            dns,
+            mtu,
+            keepalive
         )
         .execute(&self.pool)
         .await?;
         Ok(())
     }
 
-    pub async fn add_user_role(&self, uid: Uuid, role_id: Uuid) -> Result<()> {
-        let id = uid.as_bytes().as_slice();
-        let role = role_id.as_bytes().as_slice();
+    pub async fn rm_profile(&self, id: Uuid) -> Result<()> {
+        let id = &id.as_bytes()[..];
+        let mut tx = self.pool.begin().await?;
         sqlx::query!(
             // sqlite
-            "INSERT INTO user_roles(user_id,role_id) VALUES($1, $2)",
-            id,
-            role
+            "UPDATE configs SET profile_id = NULL WHERE profile_id = $1",
+            id
         )
-        .execute(&self.pool)
+        .execute(&mut tx)
         .await?;
-        Ok(())
-    }
-
-    pub async fn user_roles(&self, uid: Uuid) -> Result<Vec<Uuid>> {
-        let id = uid.as_bytes().as_slice();
-        Ok(sqlx::query!(
+        sqlx::query!(
             // sqlite
-            "SELECT role_id FROM user_roles WHERE user_id = $1",
+            "DELETE FROM profiles WHERE id = $1",
             id
         )
-        .fetch_all(&self.pool)
-        .await?
-        .into_iter()
-        .map(|a| Uuid::from_slice(&a.role_id).unwrap())
-        .collect())
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
     }
 
-    pub async fn configs_by_uid(&self, user_id: Uuid) -> Result<Vec<Config>> {
-        let user_id = &user_id.as_bytes()[..];
+    pub async fn exits(&self) -> Result<Vec<Exit>> {
         sqlx::query!(
             // sqlite
-            "SELECT configs.*, keys.priv_key FROM users
-                    INNER JOIN configs ON configs.user_id = users.id
-                    LEFT JOIN keys ON keys.key = configs.key AND keys.user_id = configs.user_id
-                    WHERE users.id = $1 AND deleted = 0",
-            user_id
+            "SELECT * FROM exits ORDER BY name"
         )
         .fetch_all(&self.pool)
         .await?
         .into_iter()
-        .map(|f| {
-            Ok(Config {
-                id: Uuid::from_slice(&f.id).unwrap(),
-                user_id: Uuid::from_slice(&f.user_id).unwrap(),
-                name: f.name,
-                ip: Ipv4Addr::new(0, 0, 0, 0),
-                priv_key: f
-                    .priv_key
-                    .map(|t| t.try_into())
-                    .transpose()
-                    .map_err(|_| DatabaseError::InvalidPubkeyData)?,
-                pub_key: f.key.try_into().unwrap(),
-                deleted: f.deleted,
+        .map(|e| {
+            Ok(Exit {
+                id: Uuid::from_slice(&e.id)?,
+                name: e.name,
+                table: e.table_id as u32,
+                fwmark: e.fwmark.map(|m| m as u32),
+                priority: e.priority as u32,
             })
         })
-        .collect::<Result<Vec<_>>>()
+        .collect()
     }
 
-    pub async fn keys(&self, user_id: Uuid) -> Result<Vec<crate::service::keys::Key>> {
-        let key = &user_id.as_bytes().as_slice();
-
-        Ok(sqlx::query!(
+    pub async fn exit(&self, id: Uuid) -> Result<Option<Exit>> {
+        let id = &id.as_bytes()[..];
+        sqlx::query!(
             // sqlite
-            "SELECT * FROM keys WHERE user_id = $1",
-            key
+            "SELECT * FROM exits WHERE id = $1",
+            id
         )
-        .fetch_all(&self.pool)
+        .fetch_optional(&self.pool)
         .await?
-        .into_iter()
-        .map(|f| Key {
-            key: f.key.try_into().unwrap(),
-            name: f.name,
-            user_id: Uuid::from_slice(&f.user_id).unwrap(),
+        .map(|e| {
+            Ok(Exit {
+                id: Uuid::from_slice(&e.id)?,
+                name: e.name,
+                table: e.table_id as u32,
+                fwmark: e.fwmark.map(|m| m as u32),
+                priority: e.priority as u32,
+            })
         })
-        .collect())
+        .transpose()
     }
 
-    pub async fn key(&self, k: [u8; WG_KEY_LEN]) -> Result<Option<crate::service::keys::Key>> {
-        let key = &k.as_slice();
-
-        Ok(sqlx::query!(
+    pub async fn add_exit(&self, e: &Exit) -> Result<()> {
+        let id = &e.id.as_bytes()[..];
+        let fwmark = e.fwmark.map(|m| m as i64);
+        sqlx::query!(
             // sqlite
-            "SELECT * FROM keys WHERE key = $1",
-            key
+            "INSERT INTO exits(id, name, table_id, fwmark, priority) VALUES($1, $2, $3, $4, $5)",
+            id,
+            e.name,
+            e.table,
+            fwmark,
+            e.priority
         )
-        .fetch_optional(&self.pool)
-        .await?
-        .map(|f| Key {
-            key: f.key.try_into().unwrap(),
-            name: f.name,
-            user_id: Uuid::from_slice(&f.user_id).unwrap(),
-        }))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
     }
 
-    pub async fn add_key(
-        &self,
-        user_id: Uuid,
-        pb: [u8; WG_KEY_LEN],
-        prv: Option<[u8; WG_KEY_LEN]>,
-    ) -> Result<()> {
-        let pub_key = &pb.as_slice();
-        let priv_key = &prv.as_ref().map(|k| k.as_slice());
-        let uid = &user_id.as_bytes().as_slice();
+    pub async fn rm_exit(&self, id: Uuid) -> Result<()> {
+        let id = &id.as_bytes()[..];
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            // sqlite
+            "UPDATE configs SET exit_id = NULL WHERE exit_id = $1",
+            id
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            // sqlite
+            "DELETE FROM exits WHERE id = $1",
+            id
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
 
+    pub async fn add_api_token(&self, t: &ApiToken, hash: &[u8; 32]) -> Result<()> {
+        let id = &t.id.as_bytes()[..];
+        let user_id = &t.user_id.as_bytes()[..];
+        let hash = &hash[..];
+        let scopes = join_scopes(&t.scopes);
         sqlx::query!(
             // sqlite
-            "INSERT INTO keys(key,priv_key,name,user_id) VALUES($1, $2, '', $3)",
-            pub_key,
-            priv_key,
-            uid
+            "INSERT INTO api_tokens(id, user_id, name, hash, scopes, created_at, expires_at)
+            VALUES($1, $2, $3, $4, $5, $6, $7)",
+            id,
+            user_id,
+            t.name,
+            hash,
+            scopes,
+            t.created_at,
+            t.expires_at
         )
         .execute(&self.pool)
         .await?;
         Ok(())
     }
-}
 
-#[async_trait]
-impl TelegramDb for Database {
-    async fn is_admin(
-        &self,
-        uid: i64,
-    ) -> std::result::Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    /// Epoch of the tokens from `/apitoken` of the user, see [`Database::bump_token_epoch`]
+    pub async fn token_epoch(&self, user_id: Uuid) -> Result<i64> {
+        let user_id = &user_id.as_bytes()[..];
+        Ok(sqlx::query!(
+            // sqlite
+            "SELECT token_epoch FROM users WHERE id = $1",
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map_or(0, |t| t.token_epoch))
+    }
+
+    /// Invalidates the tokens issued before, false for an unknown user
+    pub async fn bump_token_epoch(&self, user_id: Uuid) -> Result<bool> {
+        let user_id = &user_id.as_bytes()[..];
+        Ok(sqlx::query!(
+            // sqlite
+            "UPDATE users SET token_epoch = token_epoch + 1 WHERE id = $1",
+            user_id
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected()
+            > 0)
+    }
+
+    /// Tokens of `user_id`, all tokens when `None`
+    pub async fn api_tokens(&self, user_id: Option<Uuid>) -> Result<Vec<ApiToken>> {
+        let user_id = user_id.map(|u| u.as_bytes().to_vec());
+        sqlx::query!(
+            // sqlite
+            "SELECT id, user_id, name, scopes, created_at, expires_at FROM api_tokens
+            WHERE $1 IS NULL OR user_id = $1
+            ORDER BY created_at",
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|t| {
+            Ok(ApiToken {
+                id: Uuid::from_slice(&t.id)?,
+                user_id: Uuid::from_slice(&t.user_id)?,
+                name: t.name,
+                scopes: parse_scopes(&t.scopes).map_err(|_| DatabaseError::InvalidScopeData)?,
+                created_at: t.created_at,
+                expires_at: t.expires_at,
+            })
+        })
+        .collect()
+    }
+
+    pub async fn api_token(&self, id: Uuid) -> Result<Option<ApiToken>> {
+        let id = &id.as_bytes()[..];
+        sqlx::query!(
+            // sqlite
+            "SELECT id, user_id, name, scopes, created_at, expires_at FROM api_tokens WHERE id = $1",
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(|t| {
+            Ok(ApiToken {
+                id: Uuid::from_slice(&t.id)?,
+                user_id: Uuid::from_slice(&t.user_id)?,
+                name: t.name,
+                scopes: parse_scopes(&t.scopes).map_err(|_| DatabaseError::InvalidScopeData)?,
+                created_at: t.created_at,
+                expires_at: t.expires_at,
+            })
+        })
+        .transpose()
+    }
+
+    pub async fn api_token_by_hash(&self, hash: &[u8; 32]) -> Result<Option<ApiToken>> {
+        let hash = &hash[..];
+        sqlx::query!(
+            // sqlite
+            "SELECT id, user_id, name, scopes, created_at, expires_at FROM api_tokens WHERE hash = $1",
+            hash
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(|t| {
+            Ok(ApiToken {
+                id: Uuid::from_slice(&t.id)?,
+                user_id: Uuid::from_slice(&t.user_id)?,
+                name: t.name,
+                scopes: parse_scopes(&t.scopes).map_err(|_| DatabaseError::InvalidScopeData)?,
+                created_at: t.created_at,
+                expires_at: t.expires_at,
+            })
+        })
+        .transpose()
+    }
+
+    pub async fn rm_api_token(&self, id: Uuid) -> Result<bool> {
+        let id = &id.as_bytes()[..];
+        Ok(sqlx::query!(
+            // sqlite
+            "DELETE FROM api_tokens WHERE id = $1",
+            id
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected()
+            > 0)
+    }
+
+    /// Appends an entry to the audit log
+    pub async fn add_audit_entry(&self, e: &AuditEntry) -> Result<()> {
+        let id = &e.id.as_bytes()[..];
+        let actor_id = e.actor_id.map(|u| u.as_bytes().to_vec());
+        let frontend = e.frontend.to_string();
+        let user_id = e.user_id.map(|u| u.as_bytes().to_vec());
+        let config_id = e.config_id.map(|u| u.as_bytes().to_vec());
+        sqlx::query!(
+            // sqlite
+            "INSERT INTO audit_log(id, created_at, actor_id, frontend, action, user_id, config_id, before, after)
+            VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+            id,
+            e.created_at,
+            actor_id,
+            frontend,
+            e.action,
+            user_id,
+            config_id,
+            e.before,
+            e.after
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn audit_log(
+        &self,
+        filter: AuditFilter,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<AuditEntry>> {
+        let user_id = filter.user_id.map(|u| u.as_bytes().to_vec());
+        let config_id = filter.config_id.map(|u| u.as_bytes().to_vec());
+        sqlx::query!(
+            // sqlite
+            "SELECT id, created_at, actor_id, frontend, action, user_id, config_id, before, after
+            FROM audit_log
+            WHERE ($1 IS NULL OR actor_id = $1 OR user_id = $1) AND ($2 IS NULL OR config_id = $2)
+            ORDER BY created_at DESC, rowid DESC
+            LIMIT $3 OFFSET $4",
+            user_id,
+            config_id,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|e| {
+            Ok(AuditEntry {
+                id: Uuid::from_slice(&e.id)?,
+                created_at: e.created_at,
+                actor_id: e.actor_id.map(|u| Uuid::from_slice(&u)).transpose()?,
+                frontend: e
+                    .frontend
+                    .parse()
+                    .map_err(|_| DatabaseError::InvalidFrontendData)?,
+                action: e.action,
+                user_id: e.user_id.map(|u| Uuid::from_slice(&u)).transpose()?,
+                config_id: e.config_id.map(|u| Uuid::from_slice(&u)).transpose()?,
+                before: e.before,
+                after: e.after,
+            })
+        })
+        .collect()
+    }
+
+    /// Assigns configs created before interfaces were recorded to `interface`
+    pub async fn adopt_configs(&self, interface: &str) -> Result<u64> {
+        Ok(sqlx::query!(
+            // sqlite
+            "UPDATE configs SET interface = $1 WHERE interface IS NULL",
+            interface
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected())
+    }
+
+    pub async fn quotas(&self) -> Result<Vec<Quota>> {
+        sqlx::query!(
+            // sqlite
+            "SELECT * FROM quotas"
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|q| {
+            let target = match (q.user_id, q.config_id) {
+                (Some(u), _) => QuotaTarget::User(Uuid::from_slice(&u)?),
+                (None, Some(c)) => QuotaTarget::Config(Uuid::from_slice(&c)?),
+                (None, None) => return Err(DatabaseError::InvalidUuidData),
+            };
+            Ok(Quota {
+                target,
+                limit: q.limit_bytes as u64,
+                period: Period::try_from(q.period)?,
+            })
+        })
+        .collect()
+    }
+
+    /// Replaces the quota of the target, removes it when `limit` is `None`
+    pub async fn set_quota(&self, target: QuotaTarget, limit: Option<(u64, Period)>) -> Result<()> {
+        let (user_id, config_id) = match &target {
+            QuotaTarget::User(u) => (Some(&u.as_bytes()[..]), None),
+            QuotaTarget::Config(c) => (None, Some(&c.as_bytes()[..])),
+        };
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            // sqlite
+            "DELETE FROM quotas WHERE user_id = $1 OR config_id = $2",
+            user_id,
+            config_id
+        )
+        .execute(&mut tx)
+        .await?;
+        if let Some((limit, period)) = limit {
+            let limit = limit as i64;
+            let period = period as i64;
+            sqlx::query!(
+                // sqlite
+                "INSERT INTO quotas(user_id, config_id, limit_bytes, period) VALUES($1, $2, $3, $4)",
+                user_id,
+                config_id,
+                limit,
+                period
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Traffic of every config in the bucket of `period` starting at `start`
+    pub async fn period_usage(&self, period: Period, start: i64) -> Result<Vec<Usage>> {
+        let period = period as i64;
+        sqlx::query!(
+            // sqlite
+            "SELECT configs.id, configs.user_id, traffic_rollups.tx, traffic_rollups.rx
+            FROM traffic_rollups
+            INNER JOIN configs ON configs.id = traffic_rollups.config_id
+            WHERE traffic_rollups.period = $1 AND traffic_rollups.start = $2",
+            period,
+            start
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|u| {
+            Ok(Usage {
+                config_id: Uuid::from_slice(&u.id)?,
+                user_id: Uuid::from_slice(&u.user_id)?,
+                bytes: (u.tx + u.rx) as u64,
+            })
+        })
+        .collect()
+    }
+
+    /// Changes the expiry of a config, a config that expired before counts
+    /// as not expired and not warned anymore
+    pub async fn set_expiry(&self, config_id: Uuid, expires_at: Option<i64>) -> Result<()> {
+        let id = &config_id.as_bytes()[..];
+        sqlx::query!(
+            // sqlite
+            "UPDATE configs SET expires_at = $2, expiry_warned_at = NULL WHERE id = $1",
+            id,
+            expires_at
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn set_expiry_warned(&self, config_id: Uuid, warned_at: i64) -> Result<()> {
+        let id = &config_id.as_bytes()[..];
+        sqlx::query!(
+            // sqlite
+            "UPDATE configs SET expiry_warned_at = $2 WHERE id = $1",
+            id,
+            warned_at
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn set_expired(&self, config_id: Uuid, expired_at: Option<i64>) -> Result<()> {
+        let id = &config_id.as_bytes()[..];
+        sqlx::query!(
+            // sqlite
+            "UPDATE configs SET expired_at = $2 WHERE id = $1",
+            id,
+            expired_at
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn set_suspended(&self, config_id: Uuid, suspended_at: Option<i64>) -> Result<()> {
+        let id = &config_id.as_bytes()[..];
+        sqlx::query!(
+            // sqlite
+            "UPDATE configs SET suspended_at = $2 WHERE id = $1",
+            id,
+            suspended_at
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn set_disabled(&self, config_id: Uuid, disabled_at: Option<i64>) -> Result<()> {
+        let id = &config_id.as_bytes()[..];
+        sqlx::query!(
+            // sqlite
+            "UPDATE configs SET disabled_at = $2 WHERE id = $1",
+            id,
+            disabled_at
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Moves configs with the old `double_vpn` flag to `exit`, creating it if
+    /// there is no exit with its name yet. Returns the number of moved configs.
+    pub async fn import_double_vpn(&self, exit: &Exit) -> Result<u64> {
+        let id = &exit.id.as_bytes()[..];
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            // sqlite
+            "INSERT INTO exits(id, name, table_id, fwmark, priority) VALUES($1, $2, $3, NULL, $4)
+            ON CONFLICT(name) DO NOTHING",
+            id,
+            exit.name,
+            exit.table,
+            exit.priority
+        )
+        .execute(&mut tx)
+        .await?;
+        let moved = sqlx::query!(
+            // sqlite
+            "UPDATE configs SET double_vpn = 0,
+                exit_id = (SELECT id FROM exits WHERE name = $1)
+            WHERE double_vpn = 1",
+            exit.name
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        tx.commit().await?;
+        Ok(moved)
+    }
+
+    /// Configs still marked with the old `double_vpn` flag
+    pub async fn double_vpn_configs(&self) -> Result<i64> {
+        Ok(sqlx::query!(
+            // sqlite
+            r#"SELECT COUNT(*) AS "count!: i64" FROM configs WHERE double_vpn = 1 AND deleted = 0"#
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .count)
+    }
+
+    pub async fn add_request(&self, r: Request) -> Result<()> {
+        let id = &r.id.as_bytes()[..];
+        let user_id = &r.user_id.as_bytes()[..];
+
+        sqlx::query!(
+            // sqlite
+            "INSERT INTO requests(id, user_id, status, name, key, created_at, updated_at)
+            VALUES($1, $2, $3, $4, $5, $6, $7)",
+            id,
+            user_id,
+            r.status,
+            r.name,
+            r.key,
+            r.created_at,
+            r.updated_at
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn request(&self, id: Uuid) -> Result<Option<Request>> {
+        let id = &id.as_bytes()[..];
+
+        sqlx::query!(
+            // sqlite
+            "SELECT requests.*, integrations.telegram_id FROM requests
+            LEFT JOIN integrations ON integrations.user_id = requests.user_id
+            WHERE requests.id = $1",
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(|r| {
+            Ok(Request {
+                id: Uuid::from_slice(&r.id)?,
+                user_id: Uuid::from_slice(&r.user_id)?,
+                telegram_id: r.telegram_id,
+                status: r.status,
+                name: r.name,
+                key: r.key,
+                config_id: r.config_id.map(|c| Uuid::from_slice(&c)).transpose()?,
+                created_at: r.created_at,
+                updated_at: r.updated_at,
+            })
+        })
+        .transpose()
+    }
+
+    pub async fn requests(&self, status: i64) -> Result<Vec<Request>> {
+        sqlx::query!(
+            // sqlite
+            "SELECT requests.*, integrations.telegram_id FROM requests
+            LEFT JOIN integrations ON integrations.user_id = requests.user_id
+            WHERE requests.status = $1
+            ORDER BY requests.created_at",
+            status
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|r| {
+            Ok(Request {
+                id: Uuid::from_slice(&r.id)?,
+                user_id: Uuid::from_slice(&r.user_id)?,
+                telegram_id: r.telegram_id,
+                status: r.status,
+                name: r.name,
+                key: r.key,
+                config_id: r.config_id.map(|c| Uuid::from_slice(&c)).transpose()?,
+                created_at: r.created_at,
+                updated_at: r.updated_at,
+            })
+        })
+        .collect()
+    }
+
+    pub async fn requests_by_uid(&self, user_id: Uuid) -> Result<Vec<Request>> {
+        let user_id = &user_id.as_bytes()[..];
+
+        sqlx::query!(
+            // sqlite
+            "SELECT requests.*, integrations.telegram_id FROM requests
+            LEFT JOIN integrations ON integrations.user_id = requests.user_id
+            WHERE requests.user_id = $1
+            ORDER BY requests.created_at",
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|r| {
+            Ok(Request {
+                id: Uuid::from_slice(&r.id)?,
+                user_id: Uuid::from_slice(&r.user_id)?,
+                telegram_id: r.telegram_id,
+                status: r.status,
+                name: r.name,
+                key: r.key,
+                config_id: r.config_id.map(|c| Uuid::from_slice(&c)).transpose()?,
+                created_at: r.created_at,
+                updated_at: r.updated_at,
+            })
+        })
+        .collect()
+    }
+
+    /// Returns `false` if the request is not in the `from` status
+    pub async fn update_request(
+        &self,
+        id: Uuid,
+        from: i64,
+        to: i64,
+        config_id: Option<Uuid>,
+        updated_at: i64,
+    ) -> Result<bool> {
+        let id = &id.as_bytes()[..];
+        let config_id = config_id.as_ref().map(|c| &c.as_bytes()[..]);
+
+        Ok(sqlx::query!(
+            // sqlite
+            "UPDATE requests
+            SET status = $3, config_id = COALESCE($4, config_id), updated_at = $5
+            WHERE id = $1 AND status = $2",
+            id,
+            from,
+            to,
+            config_id,
+            updated_at
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected()
+            > 0)
+    }
+
+    pub async fn telegram_id(&self, user_id: Uuid) -> Result<Option<i64>> {
+        let user_id = &user_id.as_bytes()[..];
+
+        Ok(sqlx::query!(
+            // sqlite
+            "SELECT telegram_id FROM integrations WHERE user_id = $1",
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .and_then(|r| r.telegram_id))
+    }
+
+    pub async fn telegram_ids_with_permission(&self, permission: Permission) -> Result<Vec<i64>> {
+        let permission = permission.name();
+
+        Ok(sqlx::query!(
+            // sqlite
+            "SELECT DISTINCT integrations.telegram_id FROM user_roles
+            INNER JOIN role_permissions ON role_permissions.role_id = user_roles.role_id
+            INNER JOIN integrations ON integrations.user_id = user_roles.user_id
+            WHERE role_permissions.permission = $1",
+            permission
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .filter_map(|r| r.telegram_id)
+        .collect())
+    }
+
+    pub async fn telegram_ids_with_role(&self, role_id: Uuid) -> Result<Vec<i64>> {
+        let role_id = &role_id.as_bytes()[..];
+
+        Ok(sqlx::query!(
+            // sqlite
+            "SELECT integrations.telegram_id FROM user_roles
+            INNER JOIN integrations ON integrations.user_id = user_roles.user_id
+            WHERE user_roles.role_id = $1",
+            role_id
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .filter_map(|r| r.telegram_id)
+        .collect())
+    }
+
+    pub async fn configs_count(&self) -> Result<usize> {
+        Ok(sqlx::query!(
+            // sqlite
+            "SELECT COUNT(*) as count 
+            FROM configs"
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .count as _)
+    }
+
+    /// Whether the non-deleted config with `ip` is owned by or paired with the user
+    pub async fn is_paired(&self, user_id: Uuid, ip: Ipv4Addr) -> Result<bool> {
+        let uid = &user_id.as_bytes()[..];
+        let ip: u32 = ip.into();
+        Ok(sqlx::query!(
+            // sqlite
+            "SELECT COUNT(*) as count FROM configs
+            INNER JOIN ips ON ips.config_id = configs.id
+            LEFT JOIN pairs ON pairs.config_id = configs.id AND pairs.user_id = $2
+            WHERE ips.addr = $1 AND configs.deleted = false
+                AND (configs.user_id = $2 OR pairs.user_id IS NOT NULL)",
+            ip,
+            uid
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .count
+            > 0)
+    }
+
+    /// Config with `ip` as its IPv4 or IPv6 address
+    pub async fn config_by_addr(&self, ip: IpAddr) -> Result<Option<Config>> {
+        let (ip4, ip6) = match ip {
+            IpAddr::V4(a) => (Some(u32::from(a)), None),
+            IpAddr::V6(a) => (None, Some(a.octets().to_vec())),
+        };
+        let id = sqlx::query!(
+            // sqlite
+            "SELECT configs.id FROM configs
+            INNER JOIN ips ON ips.config_id = configs.id
+            WHERE (ips.addr = $1 OR ips.addr6 = $2) AND configs.deleted = false",
+            ip4,
+            ip6
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match id {
+            Some(t) => self.config(Uuid::from_slice(&t.id)?).await,
+            None => Ok(None),
+        }
+    }
+
+    pub async fn add_pair_code(
+        &self,
+        id: Uuid,
+        config_id: Uuid,
+        user_id: Uuid,
+        expires_at: i64,
+    ) -> Result<()> {
+        let id = &id.as_bytes()[..];
+        let cid = &config_id.as_bytes()[..];
+        let uid = &user_id.as_bytes()[..];
+        sqlx::query!(
+            // sqlite
+            "INSERT INTO pair_codes(id, config_id, user_id, expires_at) VALUES($1, $2, $3, $4)",
+            id,
+            cid,
+            uid,
+            expires_at
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Marks the code as used, returns false if it is unknown, used or expired
+    pub async fn use_pair_code(&self, id: Uuid, now: i64) -> Result<bool> {
+        let id = &id.as_bytes()[..];
+        let res = sqlx::query!(
+            // sqlite
+            "UPDATE pair_codes SET used_at = $2
+            WHERE id = $1 AND used_at IS NULL AND expires_at >= $2",
+            id,
+            now
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    pub async fn add_pair(&self, config_id: Uuid, user_id: Uuid, created_at: i64) -> Result<()> {
+        let cid = &config_id.as_bytes()[..];
+        let uid = &user_id.as_bytes()[..];
+        sqlx::query!(
+            // sqlite
+            "INSERT INTO pairs(config_id, user_id, created_at) VALUES($1, $2, $3)
+            ON CONFLICT DO NOTHING",
+            cid,
+            uid,
+            created_at
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns false if the user wasn't paired with a config with `ip`
+    pub async fn rm_pair(&self, user_id: Uuid, ip: Ipv4Addr) -> Result<bool> {
+        let uid = &user_id.as_bytes()[..];
+        let ip: u32 = ip.into();
+        let res = sqlx::query!(
+            // sqlite
+            "DELETE FROM pairs WHERE user_id = $1 AND config_id IN
+            (SELECT config_id FROM ips WHERE addr = $2)",
+            uid,
+            ip
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    pub async fn pairs(&self, user_id: Uuid) -> Result<Vec<Pair>> {
+        let uid = &user_id.as_bytes()[..];
+        sqlx::query!(
+            // sqlite
+            "SELECT configs.id, configs.name, ips.addr FROM pairs
+            INNER JOIN configs ON configs.id = pairs.config_id
+            INNER JOIN ips ON ips.config_id = configs.id
+            WHERE pairs.user_id = $1 AND configs.deleted = false
+            ORDER BY pairs.created_at",
+            uid
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|t| {
+            Ok(Pair {
+                config_id: Uuid::from_slice(&t.id)?,
+                name: t.name,
+                ip: Ipv4Addr::from(t.addr as u32),
+            })
+        })
+        .collect()
+    }
+
+    pub async fn stats(&self) -> Result<Vec<ConfigStats>> {
+        sqlx::query!(
+            // sqlite
+            "SELECT configs.id, configs.user_id, configs.name, configs.interface,
+            stats_v2.tx, stats_v2.rx, stats_v2.last_handshake
+            FROM configs
+            LEFT JOIN stats_v2 ON stats_v2.key = configs.key
+            WHERE configs.deleted = 0",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|t| {
+            Ok(ConfigStats {
+                config_id: Uuid::from_slice(&t.id)?,
+                user_id: Uuid::from_slice(&t.user_id)?,
+                name: t.name,
+                interface: t.interface.unwrap_or_default(),
+                tx: t.tx.unwrap_or_default() as _,
+                rx: t.rx.unwrap_or_default() as _,
+                last_handshake: t.last_handshake,
+            })
+        })
+        .collect()
+    }
+
+    /// Like [`Database::stats`] with the traffic of the daily rollups that
+    /// begin at or after `from` instead of the lifetime counters
+    pub async fn stats_since(&self, from: i64) -> Result<Vec<ConfigStats>> {
+        let period = Period::Day as i64;
+        sqlx::query!(
+            // sqlite
+            r#"SELECT configs.id, configs.user_id, configs.name, configs.interface,
+            COALESCE(SUM(traffic_rollups.tx), 0) as "tx!: i64",
+            COALESCE(SUM(traffic_rollups.rx), 0) as "rx!: i64",
+            stats_v2.last_handshake
+            FROM configs
+            LEFT JOIN traffic_rollups ON traffic_rollups.config_id = configs.id
+                AND traffic_rollups.period = $1 AND traffic_rollups.start >= $2
+            LEFT JOIN stats_v2 ON stats_v2.key = configs.key
+            WHERE configs.deleted = 0
+            GROUP BY configs.id"#,
+            period,
+            from
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|t| {
+            Ok(ConfigStats {
+                config_id: Uuid::from_slice(&t.id)?,
+                user_id: Uuid::from_slice(&t.user_id)?,
+                name: t.name,
+                interface: t.interface.unwrap_or_default(),
+                tx: t.tx as _,
+                rx: t.rx as _,
+                last_handshake: t.last_handshake,
+            })
+        })
+        .collect()
+    }
+
+    /// Configs whose latest handshake is not older than `since`, newest first
+    pub async fn connected_peers(&self, since: i64) -> Result<Vec<ConnectedPeer>> {
+        sqlx::query!(
+            // sqlite
+            r#"SELECT configs.id, configs.name, configs.interface, ips.addr,
+            stats_v2.last_handshake as "last_handshake!", stats_v2.endpoint
+            FROM configs
+            INNER JOIN ips ON ips.config_id = configs.id
+            INNER JOIN stats_v2 ON stats_v2.key = configs.key
+            WHERE configs.deleted = 0 AND stats_v2.last_handshake >= $1
+            ORDER BY stats_v2.last_handshake DESC"#,
+            since
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|t| {
+            Ok(ConnectedPeer {
+                config_id: Uuid::from_slice(&t.id)?,
+                name: t.name,
+                ip: Ipv4Addr::from(t.addr as u32),
+                interface: t.interface.unwrap_or_default(),
+                last_handshake: t.last_handshake,
+                endpoint: t.endpoint,
+            })
+        })
+        .collect()
+    }
+
+    /// Adds the deltas to the cumulative counters, stores them as samples
+    /// taken `at` and adds them to the hourly, daily and monthly rollups.
+    pub async fn update_peers_stats(
+        &self,
+        delta: Vec<([u8; WG_KEY_LEN], u64, u64)>,
+        at: OffsetDateTime,
+    ) -> Result<()> {
+        let ts = at.unix_timestamp();
+        let buckets = [Period::Hour, Period::Day, Period::Month].map(|p| (p as i64, p.start(at)));
+        let mut trans = self.pool.begin().await?;
+        for (id, tx, rx) in delta {
+            let tx = tx as i64;
+            let rx = rx as i64;
+            let id = &id[..];
+
+            sqlx::query!(
+                // sqlite
+                "INSERT INTO stats_v2(key, tx, rx) VALUES($3, $1, $2) 
+                ON CONFLICT(key) DO UPDATE SET 
+                tx = tx + excluded.tx,
+                rx = rx + excluded.rx",
+                tx,
+                rx,
+                id,
+            )
+            .execute(&mut trans)
+            .await?;
+
+            if tx == 0 && rx == 0 {
+                continue;
+            }
+            sqlx::query!(
+                // sqlite
+                "INSERT INTO traffic_samples(config_id, ts, tx, rx)
+                SELECT id, $2, $3, $4 FROM configs WHERE key = $1 AND deleted = false",
+                id,
+                ts,
+                tx,
+                rx,
+            )
+            .execute(&mut trans)
+            .await?;
+            for (period, start) in buckets {
+                sqlx::query!(
+                    // sqlite
+                    "INSERT INTO traffic_rollups(config_id, period, start, tx, rx)
+                    SELECT id, $2, $3, $4, $5 FROM configs WHERE key = $1 AND deleted = false
+                    ON CONFLICT(config_id, period, start) DO UPDATE SET
+                    tx = tx + excluded.tx,
+                    rx = rx + excluded.rx",
+                    id,
+                    period,
+                    start,
+                    tx,
+                    rx,
+                )
+                .execute(&mut trans)
+                .await?;
+            }
+        }
+        trans.commit().await?;
+
+        Ok(())
+    }
+
+    /// Stores the latest handshake and endpoint per key, a missing endpoint
+    /// keeps the stored one.
+    pub async fn update_handshakes(
+        &self,
+        handshakes: Vec<([u8; WG_KEY_LEN], i64, Option<String>)>,
+    ) -> Result<()> {
+        let mut trans = self.pool.begin().await?;
+        for (key, last_handshake, endpoint) in handshakes {
+            let key = &key[..];
+            sqlx::query!(
+                // sqlite
+                "INSERT INTO stats_v2(key, tx, rx, last_handshake, endpoint) VALUES($1, 0, 0, $2, $3)
+                ON CONFLICT(key) DO UPDATE SET
+                last_handshake = excluded.last_handshake,
+                endpoint = COALESCE(excluded.endpoint, endpoint)",
+                key,
+                last_handshake,
+                endpoint,
+            )
+            .execute(&mut trans)
+            .await?;
+        }
+        trans.commit().await?;
+        Ok(())
+    }
+
+    /// Drops samples older than `samples_before` and rollups of `period` that
+    /// begin before `rollups_before`.
+    pub async fn prune_traffic(
+        &self,
+        samples_before: Option<i64>,
+        rollups_before: &[(Period, i64)],
+    ) -> Result<u64> {
+        let mut removed = 0;
+        if let Some(before) = samples_before {
+            removed += sqlx::query!(
+                // sqlite
+                "DELETE FROM traffic_samples WHERE ts < $1",
+                before
+            )
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        }
+        for (period, before) in rollups_before {
+            let period = *period as i64;
+            removed += sqlx::query!(
+                // sqlite
+                "DELETE FROM traffic_rollups WHERE period = $1 AND start < $2",
+                period,
+                before
+            )
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        }
+        Ok(removed)
+    }
+
+    /// Raw samples of a config taken in `[from, to)`
+    pub async fn traffic_samples(
+        &self,
+        config_id: Uuid,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<TrafficPoint>> {
+        let id = &config_id.as_bytes()[..];
+        Ok(sqlx::query!(
+            // sqlite
+            "SELECT ts, tx, rx FROM traffic_samples
+            WHERE config_id = $1 AND ts >= $2 AND ts < $3
+            ORDER BY ts",
+            id,
+            from,
+            to
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|t| TrafficPoint {
+            start: t.ts,
+            tx: t.tx as u64,
+            rx: t.rx as u64,
+        })
+        .collect())
+    }
+
+    /// Buckets of `period` of a config that begin in `[from, to)`
+    pub async fn config_traffic(
+        &self,
+        config_id: Uuid,
+        period: Period,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<TrafficPoint>> {
+        let id = &config_id.as_bytes()[..];
+        let period = period as i64;
+        Ok(sqlx::query!(
+            // sqlite
+            "SELECT start, tx, rx FROM traffic_rollups
+            WHERE config_id = $1 AND period = $2 AND start >= $3 AND start < $4
+            ORDER BY start",
+            id,
+            period,
+            from,
+            to
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|t| TrafficPoint {
+            start: t.start,
+            tx: t.tx as u64,
+            rx: t.rx as u64,
+        })
+        .collect())
+    }
+
+    /// Buckets of `period` summed over all configs of a user, deleted ones included
+    pub async fn user_traffic(
+        &self,
+        user_id: Uuid,
+        period: Period,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<TrafficPoint>> {
+        let id = &user_id.as_bytes()[..];
+        let period = period as i64;
+        Ok(sqlx::query!(
+            // sqlite
+            r#"SELECT traffic_rollups.start, SUM(traffic_rollups.tx) as "tx!: i64",
+                SUM(traffic_rollups.rx) as "rx!: i64"
+            FROM traffic_rollups
+            INNER JOIN configs ON configs.id = traffic_rollups.config_id
+            WHERE configs.user_id = $1 AND period = $2 AND start >= $3 AND start < $4
+            GROUP BY traffic_rollups.start
+            ORDER BY traffic_rollups.start"#,
+            id,
+            period,
+            from,
+            to
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|t| TrafficPoint {
+            start: t.start,
+            tx: t.tx as u64,
+            rx: t.rx as u64,
+        })
+        .collect())
+    }
+
+    pub async fn user_id(&self, association: Association) -> Result<Uuid> {
+        let uid = match association {
+            Association::Telegram(uid) => {
+                Uuid::from_bytes(
+                    sqlx::query!(
+                        // sqlite
+                        "SELECT user_id FROM integrations WHERE telegram_id = $1",
+                        uid
+                    )
+                    .fetch_one(&self.pool)
+                    .await?
+                    .user_id
+                    .try_into()
+                    .unwrap(),
+                )
+            }
+        };
+
+        Ok(uid)
+    }
+
+    pub async fn rm_user_role(&self, uid: Uuid, role_id: Uuid) -> Result<()> {
+        let id = uid.as_bytes().as_slice();
+        let role = role_id.as_bytes().as_slice();
+        sqlx::query!(
+            // sqlite
+            "DELETE FROM user_roles WHERE user_id=$1 AND role_id=$2",
+            id,
+            role
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn add_user_role(&self, uid: Uuid, role_id: Uuid) -> Result<()> {
+        let id = uid.as_bytes().as_slice();
+        let role = role_id.as_bytes().as_slice();
+        sqlx::query!(
+            // sqlite
+            "INSERT INTO user_roles(user_id,role_id) VALUES($1, $2)",
+            id,
+            role
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Permissions of all roles of the user, unknown names are skipped
+    pub async fn user_permissions(&self, uid: Uuid) -> Result<Vec<Permission>> {
+        let id = uid.as_bytes().as_slice();
+        Ok(sqlx::query!(
+            // sqlite
+            "SELECT DISTINCT role_permissions.permission FROM user_roles
+            INNER JOIN role_permissions ON role_permissions.role_id = user_roles.role_id
+            WHERE user_roles.user_id = $1",
+            id
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .filter_map(|p| p.permission.parse().ok())
+        .collect())
+    }
+
+    pub async fn roles(&self) -> Result<Vec<Role>> {
+        let rows = sqlx::query!(
+            // sqlite
+            r#"SELECT roles.id, roles.name, role_permissions.permission as "permission?" FROM roles
+            LEFT JOIN role_permissions ON role_permissions.role_id = roles.id
+            ORDER BY roles.name, roles.id"#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut res: Vec<Role> = Vec::new();
+        for r in rows {
+            let id = Uuid::from_slice(&r.id)?;
+            if res.last().map(|l| l.id) != Some(id) {
+                res.push(Role {
+                    id,
+                    name: r.name.unwrap_or_default(),
+                    permissions: Vec::new(),
+                });
+            }
+            if let Some(p) = r.permission.and_then(|p| p.parse().ok()) {
+                res.last_mut().unwrap().permissions.push(p);
+            }
+        }
+        Ok(res)
+    }
+
+    pub async fn add_role(&self, role: &Role) -> Result<()> {
+        let id = role.id.as_bytes().as_slice();
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            // sqlite
+            "INSERT INTO roles(id, name) VALUES($1, $2)",
+            id,
+            role.name
+        )
+        .execute(&mut tx)
+        .await?;
+        for p in &role.permissions {
+            let p = p.name();
+            sqlx::query!(
+                // sqlite
+                "INSERT INTO role_permissions(role_id, permission) VALUES($1, $2)",
+                id,
+                p
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn set_role_permission(
+        &self,
+        role_id: Uuid,
+        permission: Permission,
+        granted: bool,
+    ) -> Result<()> {
+        let id = role_id.as_bytes().as_slice();
+        let p = permission.name();
+        if granted {
+            sqlx::query!(
+                // sqlite
+                "INSERT OR IGNORE INTO role_permissions(role_id, permission) VALUES($1, $2)",
+                id,
+                p
+            )
+            .execute(&self.pool)
+            .await?;
+        } else {
+            sqlx::query!(
+                // sqlite
+                "DELETE FROM role_permissions WHERE role_id = $1 AND permission = $2",
+                id,
+                p
+            )
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Removes the role from everyone and deletes it
+    pub async fn rm_role(&self, role_id: Uuid) -> Result<()> {
+        let id = role_id.as_bytes().as_slice();
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            // sqlite
+            "DELETE FROM user_roles WHERE role_id = $1",
+            id
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            // sqlite
+            "DELETE FROM role_permissions WHERE role_id = $1",
+            id
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            // sqlite
+            "DELETE FROM roles WHERE id = $1",
+            id
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Every user ordered by telegram id, those without one last
+    pub async fn users(&self) -> Result<Vec<UserSummary>> {
+        let mut roles: std::collections::HashMap<Uuid, Vec<Uuid>> = Default::default();
+        for r in sqlx::query!(
+            // sqlite
+            "SELECT user_id, role_id FROM user_roles"
+        )
+        .fetch_all(&self.pool)
+        .await?
+        {
+            roles
+                .entry(Uuid::from_slice(&r.user_id)?)
+                .or_default()
+                .push(Uuid::from_slice(&r.role_id)?);
+        }
+
+        sqlx::query!(
+            // sqlite
+            r#"SELECT users.id,
+            (SELECT telegram_id FROM integrations WHERE integrations.user_id = users.id) as "telegram_id?: i64",
+            COALESCE(SUM(configs.deleted = 0), 0) as "configs!: i64",
+            COALESCE(SUM(stats_v2.tx), 0) as "tx!: i64",
+            COALESCE(SUM(stats_v2.rx), 0) as "rx!: i64"
+            FROM users
+            LEFT JOIN configs ON configs.user_id = users.id
+            LEFT JOIN stats_v2 ON stats_v2.key = configs.key
+            GROUP BY users.id
+            ORDER BY 2 IS NULL, 2, users.id"#
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|u| {
+            let id = Uuid::from_slice(&u.id)?;
+            Ok(UserSummary {
+                id,
+                telegram_id: u.telegram_id,
+                roles: roles.remove(&id).unwrap_or_default(),
+                configs: u.configs as _,
+                tx: u.tx as _,
+                rx: u.rx as _,
+            })
+        })
+        .collect()
+    }
+
+    pub async fn user_roles(&self, uid: Uuid) -> Result<Vec<Uuid>> {
+        let id = uid.as_bytes().as_slice();
+        Ok(sqlx::query!(
+            // sqlite
+            "SELECT role_id FROM user_roles WHERE user_id = $1",
+            id
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|a| Uuid::from_slice(&a.role_id).unwrap())
+        .collect())
+    }
+
+    pub async fn configs_by_uid(&self, user_id: Uuid) -> Result<Vec<Config>> {
+        let user_id = &user_id.as_bytes()[..];
+        sqlx::query!(
+            // sqlite
+            "SELECT configs.*, ips.*, keys.priv_key, keys.psk FROM users
+                    INNER JOIN configs ON configs.user_id = users.id
+                    INNER JOIN ips ON ips.config_id = configs.id
+                    LEFT JOIN keys ON keys.key = configs.key AND keys.user_id = configs.user_id
+                    WHERE users.id = $1 AND deleted = 0",
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|f| {
+            Ok(Config {
+                id: Uuid::from_slice(&f.id).unwrap(),
+                user_id: Uuid::from_slice(&f.user_id).unwrap(),
+                name: f.name,
+                ip: Ipv4Addr::from(f.addr as u32),
+                ip6: ipv6_from_db(f.addr6)?,
+                priv_key: f
+                    .priv_key
+                    .map(|t| t.try_into())
+                    .transpose()
+                    .map_err(|_| DatabaseError::InvalidPubkeyData)?,
+                psk: f
+                    .psk
+                    .map(|t| t.try_into())
+                    .transpose()
+                    .map_err(|_| DatabaseError::InvalidPskData)?,
+                pub_key: f.key.try_into().unwrap(),
+                deleted: f.deleted,
+                profile_id: f.profile_id.map(|p| Uuid::from_slice(&p)).transpose()?,
+                exit_id: f.exit_id.map(|e| Uuid::from_slice(&e)).transpose()?,
+                interface: f.interface.unwrap_or_default(),
+                suspended: f.suspended_at.is_some(),
+                disabled: f.disabled_at.is_some(),
+                expires_at: f.expires_at,
+                expiry_warned: f.expiry_warned_at.is_some(),
+                expired: f.expired_at.is_some(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()
+    }
+
+    pub async fn keys(&self, user_id: Uuid) -> Result<Vec<crate::service::keys::Key>> {
+        let key = &user_id.as_bytes().as_slice();
+
+        Ok(sqlx::query!(
+            // sqlite
+            "SELECT * FROM keys WHERE user_id = $1",
+            key
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|f| Key {
+            key: f.key.try_into().unwrap(),
+            name: f.name,
+            user_id: Uuid::from_slice(&f.user_id).unwrap(),
+        })
+        .collect())
+    }
+
+    pub async fn key(&self, k: [u8; WG_KEY_LEN]) -> Result<Option<crate::service::keys::Key>> {
+        let key = &k.as_slice();
+
+        Ok(sqlx::query!(
+            // sqlite
+            "SELECT * FROM keys WHERE key = $1",
+            key
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(|f| Key {
+            key: f.key.try_into().unwrap(),
+            name: f.name,
+            user_id: Uuid::from_slice(&f.user_id).unwrap(),
+        }))
+    }
+
+    pub async fn add_key(
+        &self,
+        user_id: Uuid,
+        pb: [u8; WG_KEY_LEN],
+        prv: Option<[u8; WG_KEY_LEN]>,
+        psk: Option<[u8; WG_KEY_LEN]>,
+    ) -> Result<()> {
+        let pub_key = &pb.as_slice();
+        let priv_key = &prv.as_ref().map(|k| k.as_slice());
+        let psk = &psk.as_ref().map(|k| k.as_slice());
+        let uid = &user_id.as_bytes().as_slice();
+
+        sqlx::query!(
+            // sqlite
+            "INSERT INTO keys(key,priv_key,psk,name,user_id) VALUES($1, $2, $3, '', $4)",
+            pub_key,
+            priv_key,
+            psk,
+            uid
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Moves the config from `old` to a new key, the lifetime counters go
+    /// with it and the old key is forgotten
+    pub async fn replace_key(
+        &self,
+        config_id: Uuid,
+        old: [u8; WG_KEY_LEN],
+        pb: [u8; WG_KEY_LEN],
+        prv: Option<[u8; WG_KEY_LEN]>,
+        psk: Option<[u8; WG_KEY_LEN]>,
+    ) -> Result<()> {
+        let id = &config_id.as_bytes()[..];
+        let old = &old[..];
+        let pub_key = &pb[..];
+        let priv_key = prv.as_ref().map(|k| &k[..]);
+        let psk = psk.as_ref().map(|k| &k[..]);
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            // sqlite
+            "INSERT INTO keys(key, priv_key, psk, name, user_id)
+            SELECT $2, $3, $4, name, user_id FROM keys WHERE key = $1",
+            old,
+            pub_key,
+            priv_key,
+            psk
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            // sqlite
+            "UPDATE configs SET key = $2 WHERE id = $1",
+            id,
+            pub_key
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            // sqlite
+            "INSERT INTO stats_v2(key, tx, rx)
+            SELECT $2, tx, rx FROM stats_v2 WHERE key = $1",
+            old,
+            pub_key
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            // sqlite
+            "DELETE FROM stats_v2 WHERE key = $1",
+            old
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            // sqlite
+            "DELETE FROM keys WHERE key = $1",
+            old
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Sets the preshared key of a key of `user_id`
+    pub async fn set_psk(
+        &self,
+        user_id: Uuid,
+        k: [u8; WG_KEY_LEN],
+        psk: Option<[u8; WG_KEY_LEN]>,
+    ) -> Result<()> {
+        let user_id = &user_id.as_bytes()[..];
+        let key = &k.as_slice();
+        let psk = &psk.as_ref().map(|k| k.as_slice());
+
+        sqlx::query!(
+            // sqlite
+            "UPDATE keys SET psk = $3 WHERE key = $1 AND user_id = $2",
+            key,
+            user_id,
+            psk
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TelegramDb for Database {
+    async fn is_admin(
+        &self,
+        uid: i64,
+    ) -> std::result::Result<bool, Box<dyn std::error::Error + Send + Sync>> {
         return Ok(true);
         /*Ok(sqlx::query!(
             // sqlite
@@ -522,4 +2230,51 @@ impl TelegramDb for Database {
         .await?;*/
         Ok(())
     }
+
+    async fn dialogue(
+        &self,
+        chat_id: i64,
+    ) -> std::result::Result<Option<(String, i64)>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(sqlx::query!(
+            // sqlite
+            "SELECT state, updated_at FROM telegram_dialogues WHERE chat_id = $1",
+            chat_id
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(|d| (d.state, d.updated_at)))
+    }
+
+    async fn set_dialogue(
+        &self,
+        chat_id: i64,
+        state: &str,
+        updated_at: i64,
+    ) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        sqlx::query!(
+            // sqlite
+            "INSERT INTO telegram_dialogues(chat_id, state, updated_at) VALUES($1, $2, $3)
+            ON CONFLICT(chat_id) DO UPDATE SET state = $2, updated_at = $3",
+            chat_id,
+            state,
+            updated_at
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn rm_dialogue(
+        &self,
+        chat_id: i64,
+    ) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        sqlx::query!(
+            // sqlite
+            "DELETE FROM telegram_dialogues WHERE chat_id = $1",
+            chat_id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
 }
@@ -11,19 +11,30 @@ mod ui;
 mod utils;
 pub mod workers;
 mod roles;
+mod metrics;
+
+use std::time::Duration;
 
 use clap::Parser;
 use database::Database;
 use service::Wgcfg;
 use tracing::warn;
-use workers::stats::Stats;
+use workers::{expiry::Expiry, quota::Quota, reconcile::Reconcile, stats::Stats};
 
 #[derive(Debug, Parser)]
 struct Config {
     #[clap(long, short, env = "DB", value_parser)]
     db: String,
+    #[clap(long, env = "RECONCILE_INTERVAL", default_value = "300", value_parser)]
+    reconcile_interval: u64,
+    #[clap(long, env = "QUOTA_INTERVAL", default_value = "60", value_parser)]
+    quota_interval: u64,
+    #[clap(long, env = "EXPIRY_INTERVAL", default_value = "60", value_parser)]
+    expiry_interval: u64,
     #[clap(flatten)]
     service: service::Config,
+    #[clap(flatten)]
+    retention: workers::stats::Retention,
 
     #[clap(flatten)]
     api: ui::web::Config,
@@ -43,7 +54,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
     service.init().await?;
 
-    let worker = Stats::new("wg0".to_owned(), database.clone()).await?;
+    let interfaces = service
+        .interfaces()
+        .iter()
+        .map(|i| i.name.clone())
+        .collect();
+    let worker = Stats::new(interfaces, database.clone(), config.retention).await?;
+    tokio::spawn(worker.run());
+
+    let worker = Reconcile::new(
+        service.clone(),
+        Duration::from_secs(config.reconcile_interval),
+    );
+    tokio::spawn(worker.run());
+
+    let worker = Quota::new(service.clone(), Duration::from_secs(config.quota_interval));
+    tokio::spawn(worker.run());
+
+    let worker = Expiry::new(service.clone(), Duration::from_secs(config.expiry_interval));
     tokio::spawn(worker.run());
 
     for f in ui::run(config.bot, config.api, service, database) {
@@ -0,0 +1,63 @@
+//! Process wide counters that are not in the database, exported on `/metrics`.
+
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Number, failures and total duration of some operation
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Op {
+    pub count: u64,
+    pub errors: u64,
+    pub seconds: f64,
+}
+
+impl Op {
+    fn observe(&mut self, elapsed: Duration, failed: bool) {
+        self.count += 1;
+        self.errors += failed as u64;
+        self.seconds += elapsed.as_secs_f64();
+    }
+}
+
+static NETLINK: Mutex<BTreeMap<&'static str, Op>> = Mutex::new(BTreeMap::new());
+static STATS_CYCLES: Mutex<(Op, Duration)> = Mutex::new((
+    Op {
+        count: 0,
+        errors: 0,
+        seconds: 0.0,
+    },
+    Duration::ZERO,
+));
+
+/// Records a netlink request `op` that began at `started`
+pub fn observe_netlink<T, E>(op: &'static str, started: Instant, res: &Result<T, E>) {
+    let mut ops = NETLINK.lock().unwrap();
+    ops.entry(op)
+        .or_default()
+        .observe(started.elapsed(), res.is_err());
+}
+
+/// Records a stats worker cycle, `errors` is the number of failed steps
+pub fn observe_stats_cycle(elapsed: Duration, errors: u64) {
+    let mut cycles = STATS_CYCLES.lock().unwrap();
+    cycles.0.observe(elapsed, false);
+    cycles.0.errors += errors;
+    cycles.1 = elapsed;
+}
+
+pub fn netlink_ops() -> Vec<(&'static str, Op)> {
+    NETLINK
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(k, v)| (*k, *v))
+        .collect()
+}
+
+/// Totals of all cycles and the duration of the latest one
+pub fn stats_cycles() -> (Op, Duration) {
+    *STATS_CYCLES.lock().unwrap()
+}
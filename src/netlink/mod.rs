@@ -3,17 +3,39 @@ pub mod routes;
 pub mod rules;
 pub mod wireguard;
 
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
 
 use genetlink::{new_connection, GenetlinkHandle};
 use netlink_packet_core::{
-    NetlinkDeserializable, NetlinkMessage, NetlinkPayload, NetlinkSerializable,
+    NetlinkDeserializable, NetlinkHeader, NetlinkMessage, NetlinkPayload, NetlinkSerializable,
+    NLM_F_DUMP, NLM_F_REQUEST,
 };
 
+use netlink_packet_route::{AF_INET, AF_INET6};
 use netlink_sys::{protocols::NETLINK_ROUTE, Socket, SocketAddr};
 
 use error::NetlinkError;
 
+/// Address family, host prefix length and raw octets of a single address.
+pub(crate) fn host_prefix(addr: IpAddr) -> (u8, u8, Vec<u8>) {
+    match addr {
+        IpAddr::V4(a) => (AF_INET as u8, 32, a.octets().to_vec()),
+        IpAddr::V6(a) => (AF_INET6 as u8, 128, a.octets().to_vec()),
+    }
+}
+
+/// Inverse of [`host_prefix`], `None` unless `prefix_len` covers the whole address.
+pub(crate) fn host_addr(prefix_len: u8, octets: &[u8]) -> Option<IpAddr> {
+    match (prefix_len, octets.len()) {
+        (32, 4) => Some(Ipv4Addr::from(<[u8; 4]>::try_from(octets).ok()?).into()),
+        (128, 16) => Some(Ipv6Addr::from(<[u8; 16]>::try_from(octets).ok()?).into()),
+        _ => None,
+    }
+}
+
 #[derive(Clone)]
 pub struct Netlink {
     route: Socket,
@@ -84,4 +106,43 @@ impl Netlink {
             _ => Err(NetlinkError::UnexpectedResponse),
         }
     }
+
+    /// Sends a dump request for `msg` and collects every message of the reply
+    pub(crate) fn dump<T>(sock: &Socket, msg: T) -> Result<Vec<T>, NetlinkError>
+    where
+        T: NetlinkSerializable + NetlinkDeserializable + Debug,
+    {
+        let mut header = NetlinkHeader::default();
+        header.flags = NLM_F_REQUEST | NLM_F_DUMP;
+        let mut msg = NetlinkMessage::new(header, NetlinkPayload::InnerMessage(msg));
+        msg.finalize();
+
+        let mut buf = vec![0; msg.buffer_len()];
+        msg.serialize(&mut buf[..]);
+
+        sock.send(&buf, 0)?;
+
+        let mut res = Vec::new();
+        loop {
+            let mut receive_buffer = Vec::with_capacity(65536);
+            let size = sock.recv(&mut receive_buffer, 0)?;
+            let mut offset = 0;
+            // a single datagram carries several messages of the dump
+            while offset < size {
+                let bytes = &receive_buffer[offset..size];
+                let rx_packet = <NetlinkMessage<T>>::deserialize(bytes)?;
+                let len = rx_packet.header.length as usize;
+                if len == 0 {
+                    return Err(NetlinkError::UnexpectedResponse);
+                }
+                offset += (len + 3) & !3;
+                match rx_packet.payload {
+                    NetlinkPayload::InnerMessage(t) => res.push(t),
+                    NetlinkPayload::Done => return Ok(res),
+                    NetlinkPayload::Error(e) => return Err(NetlinkError::from(e.code)),
+                    _ => {}
+                }
+            }
+        }
+    }
 }
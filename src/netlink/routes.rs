@@ -1,40 +1,86 @@
-use std::net::Ipv4Addr;
+use std::{net::IpAddr, time::Instant};
 
 use netlink_packet_core::{
     NetlinkHeader, NetlinkMessage, NetlinkPayload, NLM_F_ACK, NLM_F_CREATE, NLM_F_REQUEST,
 };
 use netlink_packet_route::{
-    route, RouteHeader, RouteMessage, RtnlMessage, AF_INET, RTN_UNICAST, RTPROT_BOOT,
-    RT_SCOPE_LINK, RT_TABLE_MAIN,
+    route, RouteHeader, RouteMessage, RtnlMessage, RTN_UNICAST, RTPROT_BOOT, RT_SCOPE_LINK,
+    RT_TABLE_MAIN,
 };
 
-use super::{error::NetlinkError, Netlink};
+use super::{error::NetlinkError, host_addr, host_prefix, Netlink};
+use crate::metrics;
 
-impl Netlink {
-    pub fn add_ip_route(&self, addr: Ipv4Addr, iface: u32) -> Result<(), NetlinkError> {
-        let src = addr.octets().to_vec();
+fn host_route(addr: IpAddr, iface: u32) -> RouteMessage {
+    let (family, prefix_len, src) = host_prefix(addr);
+
+    let mut route_header = RouteHeader::default();
+    route_header.address_family = family;
+    route_header.protocol = RTPROT_BOOT;
+    route_header.scope = RT_SCOPE_LINK;
+    route_header.kind = RTN_UNICAST;
+    route_header.table = RT_TABLE_MAIN;
+    route_header.destination_prefix_length = prefix_len;
+
+    let mut route_message = RouteMessage::default();
+    route_message.header = route_header;
+    route_message.nlas = vec![route::Nla::Destination(src), route::Nla::Oif(iface)];
+    route_message
+}
 
+impl Netlink {
+    pub fn add_ip_route(&self, addr: IpAddr, iface: u32) -> Result<(), NetlinkError> {
         let mut header = NetlinkHeader::default();
         header.flags = NLM_F_REQUEST | NLM_F_CREATE | NLM_F_ACK;
 
-        let mut route_header = RouteHeader::default();
-        route_header.address_family = AF_INET as u8;
-        route_header.protocol = RTPROT_BOOT;
-        route_header.scope = RT_SCOPE_LINK;
-        route_header.kind = RTN_UNICAST;
-        route_header.table = RT_TABLE_MAIN;
-        route_header.destination_prefix_length = 32;
+        let started = Instant::now();
+        let res = Self::send::<_, RtnlMessage>(
+            &self.route,
+            NetlinkMessage::new(
+                header,
+                NetlinkPayload::from(RtnlMessage::NewRoute(host_route(addr, iface))),
+            ),
+        );
+        metrics::observe_netlink("route_add", started, &res);
+        res
+    }
 
-        let mut route_message = RouteMessage::default();
-        route_message.header = route_header;
-        route_message.nlas = vec![route::Nla::Destination(src), route::Nla::Oif(iface)];
+    pub fn del_ip_route(&self, addr: IpAddr, iface: u32) -> Result<(), NetlinkError> {
+        let mut header = NetlinkHeader::default();
+        header.flags = NLM_F_REQUEST | NLM_F_ACK;
 
-        Self::send::<_, RtnlMessage>(
+        let started = Instant::now();
+        let res = Self::send::<_, RtnlMessage>(
             &self.route,
             NetlinkMessage::new(
                 header,
-                NetlinkPayload::from(RtnlMessage::NewRoute(route_message)),
+                NetlinkPayload::from(RtnlMessage::DelRoute(host_route(addr, iface))),
             ),
-        )
+        );
+        metrics::observe_netlink("route_del", started, &res);
+        res
+    }
+
+    /// Destinations of the host routes through `iface` in the main table, the
+    /// kind [`Netlink::add_ip_route`] creates
+    pub fn host_routes(&self, iface: u32) -> Result<Vec<IpAddr>, NetlinkError> {
+        let started = Instant::now();
+        let res = Self::dump(&self.route, RtnlMessage::GetRoute(RouteMessage::default()));
+        metrics::observe_netlink("route_dump", started, &res);
+
+        Ok(res?
+            .into_iter()
+            .filter_map(|m| match m {
+                RtnlMessage::NewRoute(r) if r.header.table == RT_TABLE_MAIN => Some(r),
+                _ => None,
+            })
+            .filter(|r| r.nlas.contains(&route::Nla::Oif(iface)))
+            .filter_map(|r| {
+                r.nlas.iter().find_map(|n| match n {
+                    route::Nla::Destination(d) => host_addr(r.header.destination_prefix_length, d),
+                    _ => None,
+                })
+            })
+            .collect())
     }
 }
@@ -1,63 +1,110 @@
-use std::net::Ipv4Addr;
+use std::{net::IpAddr, time::Instant};
 
 use netlink_packet_core::{
-    NetlinkHeader, NetlinkMessage, NetlinkPayload, NLM_F_ACK, NLM_F_CREATE, NLM_F_REQUEST,
+    NetlinkHeader, NetlinkMessage, NetlinkPayload, NLM_F_ACK, NLM_F_CREATE, NLM_F_EXCL,
+    NLM_F_REQUEST,
 };
 use netlink_packet_route::{
-    nlas::rule, RtnlMessage, RuleHeader, RuleMessage, AF_INET, FR_ACT_TO_TBL, RT_TABLE_LOCAL,
+    nlas::rule, RtnlMessage, RuleHeader, RuleMessage, FR_ACT_TO_TBL, RT_TABLE_LOCAL,
 };
 
-use super::{Netlink, NetlinkError};
+use super::{host_addr, host_prefix, Netlink, NetlinkError};
+use crate::metrics;
+
+/// Policy rule sending traffic from an address to a routing table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rule {
+    pub table: u32,
+    /// Only match packets carrying this mark
+    pub fwmark: Option<u32>,
+    pub priority: u32,
+}
 
 macro_rules! msg {
-    (RtnlMessage::NewRule, $tbl: expr, $src: expr) => {
+    (RtnlMessage::NewRule, $rule: expr, $src: expr) => {
         msg!(
             RtnlMessage::NewRule,
-            $tbl,
+            $rule,
             $src,
-            NLM_F_REQUEST | NLM_F_CREATE | NLM_F_ACK
+            NLM_F_REQUEST | NLM_F_CREATE | NLM_F_EXCL | NLM_F_ACK
         )
     };
-    (RtnlMessage::DelRule, $tbl: expr, $src: expr) => {
-        msg!(RtnlMessage::DelRule, $tbl, $src, NLM_F_REQUEST | NLM_F_ACK)
+    (RtnlMessage::DelRule, $rule: expr, $src: expr) => {
+        msg!(RtnlMessage::DelRule, $rule, $src, NLM_F_REQUEST | NLM_F_ACK)
     };
-    ($t: expr, $tbl: expr, $src: expr, $flags: expr) => {{
+    ($t: expr, $rule: expr, $src: expr, $flags: expr) => {{
+        let (family, src_len, src) = host_prefix($src);
         let mut header = NetlinkHeader::default();
         header.flags = $flags;
         let mut rule_header = RuleHeader::default();
-        rule_header.family = AF_INET as u8;
+        rule_header.family = family;
         rule_header.table = RT_TABLE_LOCAL;
         rule_header.action = FR_ACT_TO_TBL;
-        rule_header.src_len = 32;
+        rule_header.src_len = src_len;
 
         let mut rule_message = RuleMessage::default();
         rule_message.header = rule_header;
         rule_message.nlas = vec![
-            rule::Nla::Priority(1000),
-            rule::Nla::Table($tbl),
-            rule::Nla::Source($src),
+            rule::Nla::Priority($rule.priority),
+            rule::Nla::Table($rule.table),
+            rule::Nla::Source(src),
         ];
+        if let Some(mark) = $rule.fwmark {
+            rule_message.nlas.push(rule::Nla::FwMark(mark));
+            rule_message.nlas.push(rule::Nla::FwMask(u32::MAX));
+        }
 
         NetlinkMessage::new(header, NetlinkPayload::from($t(rule_message)))
     }};
 }
 
 impl Netlink {
-    pub fn change_rule(
-        &self,
-        addr: Ipv4Addr,
-        table: u32,
-        enable: bool,
-    ) -> Result<(), NetlinkError> {
-        let src = addr.octets().to_vec();
-
-        Self::send::<_, RtnlMessage>(
+    pub fn change_rule(&self, addr: IpAddr, rule: &Rule, enable: bool) -> Result<(), NetlinkError> {
+        let started = Instant::now();
+        let res = Self::send::<_, RtnlMessage>(
             &self.route,
             if enable {
-                msg!(RtnlMessage::NewRule, table, src)
+                msg!(RtnlMessage::NewRule, rule, addr)
             } else {
-                msg!(RtnlMessage::DelRule, table, src)
+                msg!(RtnlMessage::DelRule, rule, addr)
             },
-        )
+        );
+        let op = if enable { "rule_add" } else { "rule_del" };
+        metrics::observe_netlink(op, started, &res);
+        res
+    }
+
+    /// Rules sending traffic from a single address to a table, the kind
+    /// [`Netlink::change_rule`] creates
+    pub fn host_rules(&self) -> Result<Vec<(IpAddr, Rule)>, NetlinkError> {
+        let started = Instant::now();
+        let res = Self::dump(&self.route, RtnlMessage::GetRule(RuleMessage::default()));
+        metrics::observe_netlink("rule_dump", started, &res);
+
+        Ok(res?
+            .into_iter()
+            .filter_map(|m| match m {
+                RtnlMessage::NewRule(r) if r.header.action == FR_ACT_TO_TBL => Some(r),
+                _ => None,
+            })
+            .filter_map(|r| {
+                let mut addr = None;
+                let mut rule = Rule {
+                    table: r.header.table.into(),
+                    fwmark: None,
+                    priority: 0,
+                };
+                for n in &r.nlas {
+                    match n {
+                        rule::Nla::Source(s) => addr = host_addr(r.header.src_len, s),
+                        rule::Nla::Table(t) => rule.table = *t,
+                        rule::Nla::FwMark(m) => rule.fwmark = Some(*m),
+                        rule::Nla::Priority(p) => rule.priority = *p,
+                        _ => {}
+                    }
+                }
+                Some((addr?, rule))
+            })
+            .collect())
     }
 }
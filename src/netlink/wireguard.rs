@@ -6,6 +6,8 @@ pub use interface::*;
 pub use peer::*;
 pub use update::*;
 
+use std::time::Instant;
+
 use futures::StreamExt;
 use netlink_packet_core::{NetlinkMessage, NetlinkPayload, NLM_F_ACK, NLM_F_DUMP, NLM_F_REQUEST};
 use netlink_packet_generic::GenlMessage;
@@ -16,6 +18,7 @@ use netlink_packet_wireguard::{
 };
 
 use super::{Netlink, NetlinkError};
+use crate::metrics;
 
 pub enum WireguardInterfaceId {
     Name(String),
@@ -36,6 +39,24 @@ impl Netlink {
         &mut self,
         id: WireguardInterfaceId,
     ) -> Result<Interface, NetlinkError> {
+        let started = Instant::now();
+        let res = self.get_device(id).await;
+        metrics::observe_netlink("wg_get_device", started, &res);
+        res
+    }
+
+    pub async fn wireguard_update(
+        &mut self,
+        id: WireguardInterfaceId,
+        update: WireguardUpdate,
+    ) -> Result<(), NetlinkError> {
+        let started = Instant::now();
+        let res = self.set_device(id, update).await;
+        metrics::observe_netlink("wg_set_device", started, &res);
+        res
+    }
+
+    async fn get_device(&mut self, id: WireguardInterfaceId) -> Result<Interface, NetlinkError> {
         let genlmsg: GenlMessage<Wireguard> = GenlMessage::from_payload(Wireguard {
             cmd: WireguardCmd::GetDevice,
             nlas: vec![id.into()],
@@ -45,21 +66,37 @@ impl Netlink {
         nlmsg.header.flags = NLM_F_REQUEST | NLM_F_DUMP;
         let mut responses = self.generic.request(nlmsg).await?;
 
+        // Big peer lists are split over several messages, the rest of them
+        // only carry peers (and a peer's allowed ips may continue in the next one)
+        let mut res: Option<Interface> = None;
         while let Some(result) = responses.next().await {
             let resp = result?;
             match resp.payload {
                 NetlinkPayload::InnerMessage(genlmsg) => {
-                    return Ok(Interface::from(genlmsg.payload))
+                    let part = Interface::from(genlmsg.payload);
+                    match &mut res {
+                        None => res = Some(part),
+                        Some(res) => {
+                            let mut peers = part.peers.into_iter().peekable();
+                            if let (Some(last), Some(next)) = (res.peers.last_mut(), peers.peek()) {
+                                if last.public_key == next.public_key {
+                                    let next = peers.next().unwrap_or_default();
+                                    last.allowed_ips.extend(next.allowed_ips);
+                                }
+                            }
+                            res.peers.extend(peers);
+                        }
+                    }
                 }
                 NetlinkPayload::Error(err) => return Err(NetlinkError::from(err.code)),
                 _ => {}
             }
         }
 
-        Err(NetlinkError::UnexpectedResponse)
+        res.ok_or(NetlinkError::UnexpectedResponse)
     }
 
-    pub async fn wireguard_update(
+    async fn set_device(
         &mut self,
         id: WireguardInterfaceId,
         update: WireguardUpdate,
@@ -39,9 +39,14 @@ impl From<Vec<WgPeerAttrs>> for Peer {
                     res.persistent_keepalive = v;
                 }
                 WgPeerAttrs::LastHandshake(v) => {
-                    let ts = v.duration_since(SystemTime::UNIX_EPOCH).ok().and_then(|n| {
-                        OffsetDateTime::from_unix_timestamp_nanos(n.as_nanos() as _).ok()
-                    });
+                    // the kernel reports the epoch for peers that never connected
+                    let ts = v
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .ok()
+                        .filter(|n| !n.is_zero())
+                        .and_then(|n| {
+                            OffsetDateTime::from_unix_timestamp_nanos(n.as_nanos() as _).ok()
+                        });
                     res.last_handshake = ts;
                 }
                 WgPeerAttrs::RxBytes(v) => {
@@ -1,6 +1,6 @@
 use cidr::IpCidr;
 use netlink_packet_wireguard::{
-    constants::{AF_INET, WGPEER_F_REMOVE_ME, WG_KEY_LEN},
+    constants::{AF_INET, AF_INET6, WGPEER_F_REMOVE_ME, WGPEER_F_REPLACE_ALLOWEDIPS, WG_KEY_LEN},
     nlas::{WgAllowedIp, WgAllowedIpAttrs, WgPeerAttrs},
 };
 
@@ -13,7 +13,10 @@ pub struct WireguardUpdate {
 #[derive(Debug)]
 pub struct PeerUpdate {
     pub public_key: Option<[u8; WG_KEY_LEN]>,
+    /// All zeroes removes the key
+    pub preshared_key: Option<[u8; WG_KEY_LEN]>,
     pub allowed_ips: Option<Vec<IpCidr>>,
+    pub replace_allowed_ips: bool,
     pub remove: bool,
 }
 
@@ -23,12 +26,16 @@ impl From<PeerUpdate> for Vec<WgPeerAttrs> {
         if let Some(p) = p.public_key {
             res.push(WgPeerAttrs::PublicKey(p));
         }
+        if let Some(k) = p.preshared_key {
+            res.push(WgPeerAttrs::PresharedKey(k));
+        }
         if let Some(p) = p.allowed_ips {
             let allowed_ips = p
                 .into_iter()
                 .map(|i| {
+                    let family = if i.is_ipv4() { AF_INET } else { AF_INET6 };
                     WgAllowedIp(vec![
-                        WgAllowedIpAttrs::Family(AF_INET),
+                        WgAllowedIpAttrs::Family(family),
                         WgAllowedIpAttrs::IpAddr(i.first_address()),
                         WgAllowedIpAttrs::Cidr(i.network_length()),
                     ])
@@ -36,8 +43,15 @@ impl From<PeerUpdate> for Vec<WgPeerAttrs> {
                 .collect();
             res.push(WgPeerAttrs::AllowedIps(allowed_ips));
         }
+        let mut flags = 0;
         if p.remove {
-            res.push(WgPeerAttrs::Flags(WGPEER_F_REMOVE_ME))
+            flags |= WGPEER_F_REMOVE_ME;
+        }
+        if p.replace_allowed_ips {
+            flags |= WGPEER_F_REPLACE_ALLOWEDIPS;
+        }
+        if flags != 0 {
+            res.push(WgPeerAttrs::Flags(flags))
         }
 
         res
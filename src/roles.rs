@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 pub const ADMIN: Uuid = {
@@ -9,3 +10,66 @@ pub const ADMIN: Uuid = {
         }
     }
 };
+
+/// What a role allows beyond managing the own configs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Permission {
+    ConfigsCreate,
+    /// Everything an owner can do with a config, on all configs, plus
+    /// quotas, expiry and suspension
+    ConfigsManageAll,
+    RequestsApprove,
+    /// Admins, roles and API tokens of other users
+    AdminsManage,
+    /// Traffic and status of all configs
+    StatsViewAll,
+    /// Profiles and exits
+    SettingsManage,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 6] = [
+        Permission::ConfigsCreate,
+        Permission::ConfigsManageAll,
+        Permission::RequestsApprove,
+        Permission::AdminsManage,
+        Permission::StatsViewAll,
+        Permission::SettingsManage,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Permission::ConfigsCreate => "configs.create",
+            Permission::ConfigsManageAll => "configs.manage_all",
+            Permission::RequestsApprove => "requests.approve",
+            Permission::AdminsManage => "admins.manage",
+            Permission::StatsViewAll => "stats.view_all",
+            Permission::SettingsManage => "settings.manage",
+        }
+    }
+}
+
+impl std::fmt::Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for Permission {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Permission::ALL
+            .into_iter()
+            .find(|p| p.name() == s)
+            .ok_or_else(|| s.to_owned())
+    }
+}
+
+/// Named set of permissions users can be given
+#[derive(Debug, Clone)]
+pub struct Role {
+    pub id: Uuid,
+    pub name: String,
+    pub permissions: Vec<Permission>,
+}
@@ -1,51 +1,96 @@
 use hmac::Mac;
+pub mod audit;
+pub mod auth;
 pub mod configs;
+pub mod exits;
+pub mod expiry;
+pub mod interfaces;
 pub mod keys;
+pub mod notifications;
+mod pool;
+pub mod presence;
+pub mod profiles;
+pub mod quotas;
+mod reconcile;
 pub mod requests;
+mod roles;
+pub mod traffic;
 mod user;
 pub mod wgcfg;
 pub mod workers;
 
 use std::{
-    net::{Ipv4Addr, SocketAddr},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
     sync::Arc,
 };
 
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, Mutex};
 
-use base64::{engine::general_purpose::STANDARD, Engine};
-use cidr::Ipv4Cidr;
+use cidr::{Ipv4Cidr, Ipv6Cidr};
 use clap::Parser;
-pub use configs::*;
 use hmac::Hmac;
+pub use pool::*;
 pub use requests::*;
 use sha2::Sha256;
 use tracing::instrument;
 pub use user::*;
 pub use wgcfg::*;
 
-use crate::{
-    database::Database,
-    netlink::{wireguard::WireguardInterfaceId, Netlink},
-};
+use crate::{database::Database, netlink::Netlink};
+use interfaces::{Interface, InterfaceSpec};
 
 struct Shared {
     netlink: Netlink,
-    range: cidr::InetAddressIterator<Ipv4Addr>,
 }
 
 #[derive(Debug, Parser)]
 pub struct Config {
     #[clap(short, long, env = "RANGE", value_parser)]
     range: Ipv4Cidr,
+    #[clap(long, env = "RANGE6", value_parser)]
+    range6: Option<Ipv6Cidr>,
+    #[clap(long, env = "SERVER_IP", value_parser)]
+    server_ip: Option<Ipv4Addr>,
+    #[clap(long, env = "SERVER_IP6", value_parser)]
+    server_ip6: Option<Ipv6Addr>,
+    #[clap(
+        long,
+        env = "IP_REUSE_DELAY_HOURS",
+        default_value = "168",
+        value_parser
+    )]
+    ip_reuse_delay_hours: u64,
     #[clap(short, long, env = "WG_INTERFACE", value_parser)]
     interface: String,
     #[clap(short, long, env = "WG_ENDPOINT", value_parser)]
     wireguard_endpoint: SocketAddr,
+    /// Interfaces managed next to the primary one, see [`InterfaceSpec`]
+    #[clap(
+        long = "extra-interface",
+        env = "WG_EXTRA_INTERFACES",
+        value_delimiter = ';',
+        value_parser
+    )]
+    extra_interfaces: Vec<InterfaceSpec>,
+    /// Table of the `double` exit that configs switched to double VPN before
+    /// named exits existed are moved to
     #[clap(short = 'v', long, env = "DVPN_TABLE", value_parser)]
-    dvpn_table: u32,
+    dvpn_table: Option<u32>,
     #[clap(short = 's', long, env = "JWT_SECRET", value_parser)]
     jwt_secret: String,
+    #[clap(
+        long,
+        env = "PAIR_CODE_TTL_MINUTES",
+        default_value = "10",
+        value_parser
+    )]
+    pair_code_ttl_minutes: u32,
+    /// How long before expiry owners of expiring configs are warned
+    #[clap(long, env = "EXPIRY_WARNING_HOURS", default_value = "24", value_parser)]
+    expiry_warning_hours: u32,
+    /// Lifetime of HTTP API tokens issued by the bot
+    #[clap(long, env = "API_TOKEN_TTL_HOURS", default_value = "24", value_parser)]
+    api_token_ttl_hours: u32,
 }
 
 #[derive(Clone)]
@@ -53,36 +98,46 @@ pub struct Wgcfg {
     database: Database,
 
     shared: Arc<Mutex<Shared>>,
-
-    iface: u32,
-    dvpn_table: u32,
-    endpoint: SocketAddr,
-    pub_key: String,
+    ip_reuse_delay_hours: u64,
+    interfaces: Arc<Vec<Interface>>,
+    dvpn_table: Option<u32>,
 
     hmac_key: Hmac<Sha256>,
+    pair_code_ttl: time::Duration,
+    notifications: broadcast::Sender<notifications::Notification>,
+    expiry_warning: time::Duration,
+    api_token_ttl: time::Duration,
 }
 
 impl Wgcfg {
     #[instrument(skip(db))]
     pub async fn new(config: Config, db: Database) -> Result<Self, ServiceError> {
         let mut netlink = Netlink::new()?;
-        let iface = netlink
-            .wg_interface(WireguardInterfaceId::Name(config.interface.clone()))
-            .await?;
-        let pk = STANDARD.encode(iface.public_key);
+        let primary = InterfaceSpec {
+            name: config.interface,
+            range: config.range,
+            range6: config.range6,
+            endpoint: config.wireguard_endpoint,
+            server_ip: config.server_ip,
+            server_ip6: config.server_ip6,
+        };
+        let mut interfaces = Vec::with_capacity(config.extra_interfaces.len() + 1);
+        for spec in [primary].into_iter().chain(config.extra_interfaces) {
+            interfaces.push(Interface::open(&mut netlink, spec).await?);
+        }
         let key: Hmac<Sha256> = Hmac::new_from_slice(config.jwt_secret.as_bytes())?;
 
         Ok(Self {
             database: db,
             dvpn_table: config.dvpn_table,
-            shared: Arc::new(Mutex::new(Shared {
-                netlink,
-                range: config.range.into_iter().addresses(),
-            })),
-            iface: iface.index,
-            endpoint: config.wireguard_endpoint,
-            pub_key: pk,
+            shared: Arc::new(Mutex::new(Shared { netlink })),
+            ip_reuse_delay_hours: config.ip_reuse_delay_hours,
+            interfaces: Arc::new(interfaces),
             hmac_key: key,
+            pair_code_ttl: time::Duration::minutes(config.pair_code_ttl_minutes as _),
+            notifications: broadcast::channel(64).0,
+            expiry_warning: time::Duration::hours(config.expiry_warning_hours as _),
+            api_token_ttl: time::Duration::hours(config.api_token_ttl_hours as _),
         })
     }
 }
@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tracing::{instrument, warn};
+use uuid::Uuid;
+
+use crate::roles::Permission;
+
+use super::{configs::Config, ServiceError, User, Wgcfg};
+
+/// Where a change came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Frontend {
+    Telegram,
+    Web,
+    Cli,
+    /// Workers and other changes nobody asked for, e.g. expiry
+    #[default]
+    System,
+}
+
+impl std::fmt::Display for Frontend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Frontend::Telegram => "telegram",
+            Frontend::Web => "web",
+            Frontend::Cli => "cli",
+            Frontend::System => "system",
+        })
+    }
+}
+
+impl std::str::FromStr for Frontend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "telegram" => Ok(Frontend::Telegram),
+            "web" => Ok(Frontend::Web),
+            "cli" => Ok(Frontend::Cli),
+            "system" => Ok(Frontend::System),
+            _ => Err(s.to_owned()),
+        }
+    }
+}
+
+/// A change about to be recorded, `action` is a dotted name like
+/// `config.rename`
+#[derive(Debug)]
+pub struct Change {
+    pub action: &'static str,
+    pub user_id: Option<Uuid>,
+    pub config_id: Option<Uuid>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl Change {
+    pub fn new(action: &'static str) -> Self {
+        Self {
+            action,
+            user_id: None,
+            config_id: None,
+            before: None,
+            after: None,
+        }
+    }
+
+    /// Change of `config`, which also targets its owner
+    pub fn of_config(action: &'static str, config: &Config) -> Self {
+        Self::new(action).user(config.user_id).config(config.id)
+    }
+
+    pub fn user(mut self, user_id: Uuid) -> Self {
+        self.user_id = Some(user_id);
+        self
+    }
+
+    pub fn config(mut self, config_id: Uuid) -> Self {
+        self.config_id = Some(config_id);
+        self
+    }
+
+    pub fn before(mut self, before: impl ToString) -> Self {
+        self.before = Some(before.to_string());
+        self
+    }
+
+    pub fn after(mut self, after: impl ToString) -> Self {
+        self.after = Some(after.to_string());
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub id: Uuid,
+    pub created_at: i64,
+    pub actor_id: Option<Uuid>,
+    pub frontend: Frontend,
+    pub action: String,
+    pub user_id: Option<Uuid>,
+    pub config_id: Option<Uuid>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// Narrows [`Wgcfg::audit_log`], `user_id` matches both the actor and the
+/// target of an entry
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditFilter {
+    pub user_id: Option<Uuid>,
+    pub config_id: Option<Uuid>,
+}
+
+impl Wgcfg {
+    /// Appends `change` made by `actor`, `None` for the service itself.
+    /// Called once the change is done, so a failure is only logged.
+    pub(super) async fn audit(&self, actor: Option<&User>, change: Change) {
+        let entry = AuditEntry {
+            id: Uuid::new_v4(),
+            created_at: OffsetDateTime::now_utc().unix_timestamp(),
+            actor_id: actor.map(|u| u.id),
+            frontend: actor.map(|u| u.frontend).unwrap_or_default(),
+            action: change.action.to_owned(),
+            user_id: change.user_id,
+            config_id: change.config_id,
+            before: change.before,
+            after: change.after,
+        };
+        if let Err(e) = self.database.add_audit_entry(&entry).await {
+            warn!("audit entry {} failed with error: {e}", entry.action);
+        }
+    }
+
+    /// Newest entries first, `limit` entries after skipping `offset`
+    #[instrument(skip(self))]
+    pub async fn audit_log(
+        &self,
+        user: &User,
+        filter: AuditFilter,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<AuditEntry>, ServiceError> {
+        if !user.can(Permission::AdminsManage) {
+            return Err(ServiceError::AccessDenied);
+        }
+        Ok(self.database.audit_log(filter, offset, limit).await?)
+    }
+}
@@ -0,0 +1,254 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jwt::{SignWithKey, VerifyWithKey};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::roles::Permission;
+
+use super::{
+    audit::{Change, Frontend},
+    ServiceError, User, Wgcfg,
+};
+
+const API_AUDIENCE: &str = "api";
+/// Tells personal tokens apart from the JWTs of [`Wgcfg::api_token`]
+const TOKEN_PREFIX: &str = "vpn_";
+
+/// Claims of a token for the HTTP API, `aud` keeps pair codes from being
+/// accepted as one. Tokens of an older `epoch` than the user's are revoked.
+#[derive(Debug, Serialize, Deserialize)]
+struct ApiClaims {
+    sub: Uuid,
+    aud: String,
+    exp: i64,
+    #[serde(default)]
+    epoch: i64,
+}
+
+/// What a personal token may be used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Reading configs, keys and traffic
+    Read,
+    /// Creating, renaming and removing configs
+    ConfigsWrite,
+    /// Keeps the roles of the owner, tokens without it keep only the
+    /// permissions their scopes allow
+    Admin,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::Read, Scope::ConfigsWrite, Scope::Admin];
+
+    /// Whether a token with this scope keeps `permission` of its owner
+    pub fn allows(&self, permission: Permission) -> bool {
+        match self {
+            Scope::Read => false,
+            Scope::ConfigsWrite => permission == Permission::ConfigsCreate,
+            Scope::Admin => true,
+        }
+    }
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Scope::Read => "read",
+            Scope::ConfigsWrite => "configs:write",
+            Scope::Admin => "admin",
+        })
+    }
+}
+
+impl std::str::FromStr for Scope {
+    type Err = ServiceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Scope::Read),
+            "configs:write" => Ok(Scope::ConfigsWrite),
+            "admin" => Ok(Scope::Admin),
+            _ => Err(ServiceError::InvalidScope(s.to_owned())),
+        }
+    }
+}
+
+/// Parses a comma separated list like `read,configs:write`
+pub fn parse_scopes(s: &str) -> Result<Vec<Scope>, ServiceError> {
+    let mut res = Vec::new();
+    for scope in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let scope = scope.parse()?;
+        if !res.contains(&scope) {
+            res.push(scope);
+        }
+    }
+    if res.is_empty() {
+        return Err(ServiceError::InvalidScope(s.to_owned()));
+    }
+    Ok(res)
+}
+
+/// Inverse of [`parse_scopes`]
+pub fn join_scopes(scopes: &[Scope]) -> String {
+    scopes
+        .iter()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Named personal token, the secret itself is only known when it is created
+#[derive(Debug, Clone)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+}
+
+impl ApiToken {
+    pub fn expired(&self, now: OffsetDateTime) -> bool {
+        self.expires_at.is_some_and(|t| t <= now.unix_timestamp())
+    }
+}
+
+fn hash(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+impl Wgcfg {
+    /// Issues a token acting as `user` on the HTTP API
+    #[instrument(skip(self))]
+    pub async fn api_token(&self, user: &User) -> Result<String, ServiceError> {
+        let claims = ApiClaims {
+            sub: user.id,
+            aud: API_AUDIENCE.to_owned(),
+            exp: (OffsetDateTime::now_utc() + self.api_token_ttl).unix_timestamp(),
+            epoch: self.database.token_epoch(user.id).await?,
+        };
+        Ok(claims.sign_with_key(&self.hmac_key)?)
+    }
+
+    /// Revokes every token from [`Wgcfg::api_token`] of `user_id`, personal
+    /// tokens stay valid
+    #[instrument(skip(self))]
+    pub async fn end_api_sessions(&self, user: &User, user_id: Uuid) -> Result<(), ServiceError> {
+        if user.id != user_id && !user.can(Permission::AdminsManage) {
+            return Err(ServiceError::AccessDenied);
+        }
+        if !self.database.bump_token_epoch(user_id).await? {
+            return Err(ServiceError::NotFound);
+        }
+        self.audit(Some(user), Change::new("token.end_sessions").user(user_id))
+            .await;
+        Ok(())
+    }
+
+    /// Creates a personal token of `user`, returns its id and the secret
+    #[instrument(skip(self))]
+    pub async fn create_api_token(
+        &self,
+        user: &User,
+        name: String,
+        scopes: Vec<Scope>,
+        expires_at: Option<OffsetDateTime>,
+    ) -> Result<(Uuid, String), ServiceError> {
+        if scopes.contains(&Scope::Admin) && !user.can(Permission::AdminsManage) {
+            return Err(ServiceError::AccessDenied);
+        }
+        let mut secret = [0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+        let secret = format!("{TOKEN_PREFIX}{}", URL_SAFE_NO_PAD.encode(secret));
+
+        let token = ApiToken {
+            id: Uuid::new_v4(),
+            user_id: user.id,
+            name,
+            scopes,
+            created_at: OffsetDateTime::now_utc().unix_timestamp(),
+            expires_at: expires_at.map(|t| t.unix_timestamp()),
+        };
+        self.database.add_api_token(&token, &hash(&secret)).await?;
+        let change = Change::new("token.create").user(user.id).after(format!(
+            "{} {}",
+            token.name,
+            join_scopes(&token.scopes)
+        ));
+        self.audit(Some(user), change).await;
+        Ok((token.id, secret))
+    }
+
+    /// Personal tokens of `user`
+    #[instrument(skip(self))]
+    pub async fn api_tokens(&self, user: &User) -> Result<Vec<ApiToken>, ServiceError> {
+        Ok(self.database.api_tokens(Some(user.id)).await?)
+    }
+
+    /// Personal tokens of everyone
+    #[instrument(skip(self))]
+    pub async fn all_api_tokens(&self, user: &User) -> Result<Vec<ApiToken>, ServiceError> {
+        if !user.can(Permission::AdminsManage) {
+            return Err(ServiceError::AccessDenied);
+        }
+        Ok(self.database.api_tokens(None).await?)
+    }
+
+    /// Revokes a token of `user`, token managers can revoke every token
+    #[instrument(skip(self))]
+    pub async fn revoke_api_token(&self, user: &User, id: Uuid) -> Result<(), ServiceError> {
+        let Some(token) = self.database.api_token(id).await? else {
+            return Err(ServiceError::NotFound);
+        };
+        if token.user_id != user.id && !user.can(Permission::AdminsManage) {
+            return Err(ServiceError::AccessDenied);
+        }
+        self.database.rm_api_token(id).await?;
+        let change = Change::new("token.revoke")
+            .user(token.user_id)
+            .before(&token.name);
+        self.audit(Some(user), change).await;
+        Ok(())
+    }
+
+    /// User and scopes of a token from [`Wgcfg::api_token`], which has all
+    /// scopes, or [`Wgcfg::create_api_token`]
+    #[instrument(skip(self, token))]
+    pub async fn authenticate(&self, token: &str) -> Result<(User, Vec<Scope>), ServiceError> {
+        let now = OffsetDateTime::now_utc();
+        if token.starts_with(TOKEN_PREFIX) {
+            let Some(t) = self.database.api_token_by_hash(&hash(token)).await? else {
+                return Err(ServiceError::InvalidToken);
+            };
+            if t.expired(now) {
+                return Err(ServiceError::InvalidToken);
+            }
+            let mut user = self.user_by_id(t.user_id).await?;
+            user.frontend = Frontend::Web;
+            if !t.scopes.contains(&Scope::Admin) {
+                user.roles.clear();
+                user.permissions
+                    .retain(|p| t.scopes.iter().any(|s| s.allows(*p)));
+            }
+            return Ok((user, t.scopes));
+        }
+
+        let claims: ApiClaims = token
+            .verify_with_key(&self.hmac_key)
+            .map_err(|_| ServiceError::InvalidToken)?;
+        if claims.aud != API_AUDIENCE || claims.exp < now.unix_timestamp() {
+            return Err(ServiceError::InvalidToken);
+        }
+        if claims.epoch != self.database.token_epoch(claims.sub).await? {
+            return Err(ServiceError::InvalidToken);
+        }
+        let mut user = self.user_by_id(claims.sub).await?;
+        user.frontend = Frontend::Web;
+        Ok((user, Scope::ALL.to_vec()))
+    }
+}
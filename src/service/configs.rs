@@ -1,106 +1,234 @@
-use std::net::{IpAddr, Ipv4Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 use cidr::IpCidr;
-use rand::rngs::OsRng;
-use tracing::instrument;
+use rand::{rngs::OsRng, RngCore};
+use time::OffsetDateTime;
+use tracing::{info, instrument};
 use uuid::Uuid;
 
 use crate::{
     database::{DatabaseError, FullConfig},
-    netlink::wireguard::{PeerUpdate, WireguardInterfaceId, WireguardUpdate},
+    netlink::{
+        error::NetlinkError,
+        wireguard::{PeerUpdate, WireguardInterfaceId, WireguardUpdate},
+    },
+    roles::Permission,
+    utils,
 };
 
-use super::{ServerInfo, ServiceError, User, Wgcfg};
+use super::{audit::Change, notifications::Notification, ServiceError, Shared, User, Wgcfg};
 use base64::{engine::general_purpose::STANDARD, Engine};
 use x25519_dalek::{PublicKey, StaticSecret};
 
-pub struct ConfigInfo {
-    pub ip: IpAddr,
-    pub priv_key: Option<String>,
-}
-
 pub struct Config {
     pub id: Uuid,
     pub user_id: Uuid,
     pub ip: Ipv4Addr,
+    pub ip6: Option<Ipv6Addr>,
     pub pub_key: [u8; 32],
     pub priv_key: Option<[u8; 32]>,
+    pub psk: Option<[u8; 32]>,
     pub name: String,
     pub deleted: bool,
+    pub profile_id: Option<Uuid>,
+    /// Exit the traffic leaves through, direct when `None`
+    pub exit_id: Option<Uuid>,
+    /// Name of the WireGuard interface the config is served on
+    pub interface: String,
+    /// Out of the kernel because a quota is exhausted
+    pub suspended: bool,
+    /// Unix timestamp after which the config stops working
+    pub expires_at: Option<i64>,
+    pub expiry_warned: bool,
+    /// Out of the kernel because `expires_at` has passed
+    pub expired: bool,
+    /// Out of the kernel because an admin suspended it
+    pub disabled: bool,
+}
+
+pub(super) fn decode_key(k: &str) -> Result<[u8; 32], ServiceError> {
+    let mut pk = [0u8; 32];
+    if let Ok(32) = STANDARD.decode_slice(k.as_bytes(), &mut pk) {
+        Ok(pk)
+    } else {
+        Err(ServiceError::InvalidKey)
+    }
+}
+
+fn generate_psk() -> [u8; 32] {
+    let mut psk = [0u8; 32];
+    OsRng.fill_bytes(&mut psk);
+    psk
 }
 
 impl Config {
-    pub fn config_file(&self, server: ServerInfo) -> Vec<u8> {
-        format!(
-            "[Interface]
-Address = {ip}
-PrivateKey = {priv_key}
-ListenPort = 51820
-
-[Peer]
-PublicKey = {pub_key}
-Endpoint = {endpoint}
-AllowedIPs = 0.0.0.0/0, ::/0",
-            ip = self.ip,
-            priv_key = self
-                .priv_key
-                .map(|k| base64::engine::general_purpose::STANDARD.encode(k))
-                .unwrap_or("<INSERT PRIVATE KEY>".to_owned()),
-            pub_key = server.pub_key,
-            endpoint = server.addr
-        )
-        .into_bytes()
+    pub fn addrs(&self) -> Vec<IpAddr> {
+        let mut res = vec![IpAddr::V4(self.ip)];
+        if let Some(ip6) = self.ip6 {
+            res.push(IpAddr::V6(ip6));
+        }
+        res
+    }
+
+    pub fn allowed_ips(&self) -> Vec<IpCidr> {
+        self.addrs().into_iter().map(IpCidr::new_host).collect()
+    }
+
+    /// Name of the reason the config does not work, `active` if it works
+    pub fn state(&self) -> &'static str {
+        if self.deleted {
+            "deleted"
+        } else if self.disabled {
+            "disabled"
+        } else if self.expired {
+            "expired"
+        } else if self.suspended {
+            "suspended"
+        } else {
+            "active"
+        }
+    }
+
+    /// Whether the peer should be present in the kernel
+    pub fn active(&self) -> bool {
+        !self.deleted && !self.suspended && !self.expired && !self.disabled
     }
 }
 
 impl Wgcfg {
+    /// Adds the peer and routes of `config` to the kernel when it is active and
+    /// removes them otherwise, for changes of the flags [`Config::active`] looks at.
+    pub(super) async fn sync_peer(
+        &self,
+        state: &mut Shared,
+        config: &Config,
+    ) -> Result<(), ServiceError> {
+        let update = if config.active() {
+            PeerUpdate {
+                public_key: Some(config.pub_key),
+                preshared_key: config.psk,
+                allowed_ips: Some(config.allowed_ips()),
+                replace_allowed_ips: true,
+                remove: false,
+            }
+        } else {
+            PeerUpdate {
+                public_key: Some(config.pub_key),
+                preshared_key: None,
+                allowed_ips: None,
+                replace_allowed_ips: false,
+                remove: true,
+            }
+        };
+        let Some(index) = self.config_interface(config).map(|i| i.index) else {
+            return Ok(());
+        };
+        state
+            .netlink
+            .wireguard_update(
+                WireguardInterfaceId::Index(index),
+                WireguardUpdate {
+                    peers: vec![update],
+                    replace_peers: false,
+                },
+            )
+            .await?;
+        for addr in config.addrs() {
+            let res = if config.active() {
+                state.netlink.add_ip_route(addr, index)
+            } else {
+                state.netlink.del_ip_route(addr, index)
+            };
+            match res {
+                Ok(()) | Err(NetlinkError::AlreadyExists) | Err(NetlinkError::NotFound) => {}
+                Err(e) => tracing::warn!("ip route change for {addr} error: {e}"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a config on `interface`, the primary interface when `None`.
+    /// Only those managing all configs may give it an expiry.
     #[instrument(skip(self))]
     pub async fn new_config(
         &self,
         user: &User,
         name: String,
         key: Option<String>,
+        with_psk: bool,
+        interface: Option<&str>,
+        expires_at: Option<OffsetDateTime>,
     ) -> Result<Uuid, ServiceError> {
+        if !user.can(Permission::ConfigsCreate)
+            || (expires_at.is_some() && !user.can(Permission::ConfigsManageAll))
+        {
+            return Err(ServiceError::AccessDenied);
+        }
+        let change = Change::new("config.create").user(user.id).after(&name);
+        let id = self
+            .add_config(user, name, key, with_psk, interface, expires_at)
+            .await?;
+        self.audit(Some(user), change.config(id)).await;
+        Ok(id)
+    }
+
+    /// [`Wgcfg::new_config`] without the permission check, for configs
+    /// created on behalf of `user`, e.g. an approved request
+    pub(super) async fn add_config(
+        &self,
+        user: &User,
+        name: String,
+        key: Option<String>,
+        with_psk: bool,
+        interface: Option<&str>,
+        expires_at: Option<OffsetDateTime>,
+    ) -> Result<Uuid, ServiceError> {
+        let expires_at = expires_at.map(|t| t.unix_timestamp());
+        if let Some(t) = expires_at.filter(|&t| t <= OffsetDateTime::now_utc().unix_timestamp()) {
+            return Err(ServiceError::InvalidExpiry(t));
+        }
+        let iface = match interface {
+            Some(name) => self.interface(name)?,
+            None => self.primary_interface(),
+        };
         let (pub_key, privkey) = key
-            .map(|k| {
-                let mut pk = [0u8; 32];
-                if let Ok(32) = STANDARD.decode_slice(k.as_bytes(), &mut pk) {
-                    Ok((pk, None))
-                } else {
-                    Err(ServiceError::InvalidKey)
-                }
-            })
+            .map(|k| Ok::<_, ServiceError>((decode_key(&k)?, None)))
             .unwrap_or_else(|| {
                 let private = StaticSecret::new(OsRng);
                 let public = PublicKey::from(&private);
                 Ok((public.to_bytes(), Some(private.to_bytes())))
             })?;
 
-        self.database.add_key(user.id, pub_key, privkey).await?;
+        let psk = with_psk.then(generate_psk);
 
-        let ip = {
-            self.shared
-                .lock()
-                .await
-                .range
-                .next()
-                .ok_or(ServiceError::IpPoolExhausted)?
-        };
+        let mut state = self.shared.lock().await;
+        self.database
+            .add_key(user.id, pub_key, privkey, psk)
+            .await?;
+
+        let (ip, ip6) = self.allocate_addrs(&mut state, iface).await?;
 
         let id = Uuid::new_v4();
-        match self
-            .database
-            .add_config(Config {
-                ip,
-                pub_key,
-                priv_key: privkey,
-                name,
-                id,
-                deleted: false,
-                user_id: user.id,
-            })
-            .await
-        {
+        let config = Config {
+            ip,
+            ip6,
+            pub_key,
+            priv_key: privkey,
+            psk,
+            name,
+            id,
+            deleted: false,
+            user_id: user.id,
+            profile_id: None,
+            exit_id: None,
+            interface: iface.name.clone(),
+            suspended: false,
+            expires_at,
+            expiry_warned: false,
+            expired: false,
+            disabled: false,
+        };
+        match self.database.add_config(&config).await {
             Ok(()) => {}
             Err(DatabaseError::Sqlx(s))
                 if Some("2067") == s.as_database_error().and_then(|e| e.code()).as_deref() =>
@@ -110,24 +238,27 @@ impl Wgcfg {
             Err(e) => Err(e)?,
         };
 
-        let mut state = self.shared.lock().await;
         let nlink = &mut state.netlink;
 
         nlink
             .wireguard_update(
-                WireguardInterfaceId::Index(self.iface),
+                WireguardInterfaceId::Index(iface.index),
                 WireguardUpdate {
                     replace_peers: false,
                     peers: vec![PeerUpdate {
                         public_key: Some(pub_key),
-                        allowed_ips: Some(vec![IpCidr::new_host(IpAddr::V4(ip))]),
+                        preshared_key: config.psk,
+                        allowed_ips: Some(config.allowed_ips()),
+                        replace_allowed_ips: false,
                         remove: false,
                     }],
                 },
             )
             .await?;
-        if let Err(e) = nlink.add_ip_route(ip, self.iface) {
-            tracing::warn!("ip route add error: {e}");
+        for addr in config.addrs() {
+            if let Err(e) = nlink.add_ip_route(addr, iface.index) {
+                tracing::warn!("ip route add error: {e}");
+            }
         }
 
         Ok(id)
@@ -135,38 +266,290 @@ impl Wgcfg {
 
     #[instrument(skip(self))]
     pub async fn rm_config(&self, user: &User, config_id: Uuid) -> Result<(), ServiceError> {
+        let mut state = self.shared.lock().await;
         let t = self.database.config(config_id).await?;
-        let Some(config) = t else {
+        let Some(config) = t.filter(|c| !c.deleted) else {
             return Err(ServiceError::NotFound)
         };
-        if config.user_id != user.id && !user.is_admin() {
+        if config.user_id != user.id && !user.can(Permission::ConfigsManageAll) {
             return Err(ServiceError::AccessDenied);
         }
 
         self.database.rm_config(config.id).await?;
-        let mut state = self.shared.lock().await;
         let nlink = &mut state.netlink;
-        nlink
-            .wireguard_update(
-                WireguardInterfaceId::Index(self.iface),
-                WireguardUpdate {
-                    peers: vec![PeerUpdate {
-                        public_key: Some(config.pub_key),
-                        allowed_ips: None,
-                        remove: true,
-                    }],
-                    replace_peers: false,
-                },
-            )
+        if let Some(iface) = self.config_interface(&config) {
+            nlink
+                .wireguard_update(
+                    WireguardInterfaceId::Index(iface.index),
+                    WireguardUpdate {
+                        peers: vec![PeerUpdate {
+                            public_key: Some(config.pub_key),
+                            preshared_key: None,
+                            allowed_ips: None,
+                            replace_allowed_ips: false,
+                            remove: true,
+                        }],
+                        replace_peers: false,
+                    },
+                )
+                .await?;
+            for addr in config.addrs() {
+                match nlink.del_ip_route(addr, iface.index) {
+                    Ok(()) | Err(NetlinkError::NotFound) => {}
+                    Err(e) => tracing::warn!("ip route del for {addr} error: {e}"),
+                }
+            }
+        }
+        if let Some(exit_id) = config.exit_id {
+            let exit = self.exit(exit_id).await?;
+            for addr in config.addrs() {
+                if let Err(e) = nlink.change_rule(addr, &exit.rule(), false) {
+                    tracing::warn!("ip rule del error: {e}");
+                }
+            }
+        }
+        self.audit(
+            Some(user),
+            Change::of_config("config.remove", &config).before(&config.name),
+        )
+        .await;
+        Ok(())
+    }
+
+    /// Suspends (`disabled`) or resumes a config, it keeps its addresses and
+    /// keys and works again as before once resumed.
+    #[instrument(skip(self))]
+    pub async fn set_disabled(
+        &self,
+        user: &User,
+        config_id: Uuid,
+        disabled: bool,
+    ) -> Result<(), ServiceError> {
+        if !user.can(Permission::ConfigsManageAll) {
+            return Err(ServiceError::AccessDenied);
+        }
+        let mut state = self.shared.lock().await;
+        let Some(config) = self.database.config(config_id).await? else {
+            return Err(ServiceError::NotFound);
+        };
+        if config.deleted {
+            return Err(ServiceError::NotFound);
+        }
+        self.change_disabled(&mut state, user, config, disabled)
+            .await
+    }
+
+    /// [`Wgcfg::set_disabled`] for every config of `user_id`, a failing config
+    /// doesn't stop the rest and all failures are reported together
+    #[instrument(skip(self))]
+    pub async fn set_user_disabled(
+        &self,
+        user: &User,
+        user_id: Uuid,
+        disabled: bool,
+    ) -> Result<(), ServiceError> {
+        if !user.can(Permission::ConfigsManageAll) {
+            return Err(ServiceError::AccessDenied);
+        }
+        let mut state = self.shared.lock().await;
+        let mut errors = Vec::new();
+        for config in self.database.configs_by_uid(user_id).await? {
+            let config_id = config.id;
+            if let Err(e) = self
+                .change_disabled(&mut state, user, config, disabled)
+                .await
+            {
+                tracing::warn!(%config_id, "disabled state change error: {e}");
+                errors.push(format!("{config_id}: {e}"));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ServiceError::Unexpected(errors.join("; ")))
+        }
+    }
+
+    async fn change_disabled(
+        &self,
+        state: &mut Shared,
+        user: &User,
+        mut config: Config,
+        disabled: bool,
+    ) -> Result<(), ServiceError> {
+        if config.disabled == disabled {
+            return Ok(());
+        }
+        config.disabled = disabled;
+        self.sync_peer(state, &config).await?;
+        let disabled_at = disabled.then(|| OffsetDateTime::now_utc().unix_timestamp());
+        self.database.set_disabled(config.id, disabled_at).await?;
+        info!(config_id = %config.id, disabled, "config disabled state changed");
+        let action = if disabled {
+            "config.disable"
+        } else {
+            "config.enable"
+        };
+        self.audit(Some(user), Change::of_config(action, &config))
+            .await;
+        let config_active = config.active();
+        let (user_id, config_id, name) = (config.user_id, config.id, config.name);
+        self.notify(if disabled {
+            Notification::ConfigDisabled {
+                user_id,
+                config_id,
+                name,
+            }
+        } else {
+            Notification::ConfigEnabled {
+                user_id,
+                config_id,
+                name,
+                active: config_active,
+            }
+        });
+        Ok(())
+    }
+
+    /// Replaces the preshared key of a config, ip and keypair stay the same.
+    #[instrument(skip(self))]
+    pub async fn rotate_psk(&self, user: &User, config_id: Uuid) -> Result<(), ServiceError> {
+        let mut state = self.shared.lock().await;
+        let Some(config) = self.database.config(config_id).await? else {
+            return Err(ServiceError::NotFound);
+        };
+        if config.user_id != user.id && !user.can(Permission::ConfigsManageAll) {
+            return Err(ServiceError::AccessDenied);
+        }
+        if config.deleted {
+            return Err(ServiceError::NotFound);
+        }
+
+        let psk = generate_psk();
+        self.database
+            .set_psk(config.user_id, config.pub_key, Some(psk))
             .await?;
+        // a suspended peer gets the new key when it is restored
+        if let Some(iface) = self.config_interface(&config).filter(|_| config.active()) {
+            state
+                .netlink
+                .wireguard_update(
+                    WireguardInterfaceId::Index(iface.index),
+                    WireguardUpdate {
+                        peers: vec![PeerUpdate {
+                            public_key: Some(config.pub_key),
+                            preshared_key: Some(psk),
+                            allowed_ips: None,
+                            replace_allowed_ips: false,
+                            remove: false,
+                        }],
+                        replace_peers: false,
+                    },
+                )
+                .await?;
+        }
+        self.audit(Some(user), Change::of_config("config.rotate_psk", &config))
+            .await;
         Ok(())
     }
 
+    /// Replaces the keypair of a config with `key` or a generated one, e.g.
+    /// after a device is lost. Address, name and traffic history stay, a
+    /// preshared key is replaced as well. Returns the config with the new key.
+    #[instrument(skip(self))]
+    pub async fn rotate_key(
+        &self,
+        user: &User,
+        config_id: Uuid,
+        key: Option<String>,
+    ) -> Result<Config, ServiceError> {
+        let (pub_key, priv_key) = key
+            .map(|k| Ok::<_, ServiceError>((decode_key(&k)?, None)))
+            .unwrap_or_else(|| {
+                let private = StaticSecret::new(OsRng);
+                let public = PublicKey::from(&private);
+                Ok((public.to_bytes(), Some(private.to_bytes())))
+            })?;
+
+        let mut state = self.shared.lock().await;
+        let Some(mut config) = self.database.config(config_id).await? else {
+            return Err(ServiceError::NotFound);
+        };
+        if config.user_id != user.id && !user.can(Permission::ConfigsManageAll) {
+            return Err(ServiceError::AccessDenied);
+        }
+        if config.deleted {
+            return Err(ServiceError::NotFound);
+        }
+
+        let old_key = config.pub_key;
+        let psk = config.psk.map(|_| generate_psk());
+        match self
+            .database
+            .replace_key(config.id, old_key, pub_key, priv_key, psk)
+            .await
+        {
+            Ok(()) => {}
+            Err(DatabaseError::Sqlx(s))
+                if matches!(
+                    s.as_database_error().and_then(|e| e.code()).as_deref(),
+                    Some("1555" | "2067")
+                ) =>
+            {
+                return Err(ServiceError::ClientAlreadyExists)
+            }
+            Err(e) => Err(e)?,
+        };
+        config.pub_key = pub_key;
+        config.priv_key = priv_key;
+        config.psk = psk;
+
+        // a suspended peer gets the new key when it is restored, both changes
+        // go in one message, the kernel never has the address on two peers or
+        // on none
+        if let Some(iface) = self.config_interface(&config).filter(|_| config.active()) {
+            state
+                .netlink
+                .wireguard_update(
+                    WireguardInterfaceId::Index(iface.index),
+                    WireguardUpdate {
+                        peers: vec![
+                            PeerUpdate {
+                                public_key: Some(old_key),
+                                preshared_key: None,
+                                allowed_ips: None,
+                                replace_allowed_ips: false,
+                                remove: true,
+                            },
+                            PeerUpdate {
+                                public_key: Some(pub_key),
+                                preshared_key: psk,
+                                allowed_ips: Some(config.allowed_ips()),
+                                replace_allowed_ips: false,
+                                remove: false,
+                            },
+                        ],
+                        replace_peers: false,
+                    },
+                )
+                .await?;
+        }
+        info!(config_id = %config.id, "config key rotated");
+        self.audit(
+            Some(user),
+            Change::of_config("config.rotate_key", &config)
+                .before(STANDARD.encode(old_key))
+                .after(STANDARD.encode(pub_key)),
+        )
+        .await;
+        Ok(config)
+    }
+
     #[instrument(skip(self))]
     pub async fn config(&self, user: &User, config_id: Uuid) -> Result<FullConfig, ServiceError> {
         let t = self.database.config_with_stats(config_id).await?;
         if let Some(config) = &t {
-            if config.config.user_id != user.id && !user.is_admin() {
+            if config.config.user_id != user.id && !user.can(Permission::ConfigsManageAll) {
                 return Err(ServiceError::AccessDenied);
             }
         }
@@ -174,8 +557,35 @@ impl Wgcfg {
         t.ok_or(ServiceError::NotFound)
     }
 
+    /// Renders the client config file using the config's profile.
+    #[instrument(skip(self, config), fields(config_id = %config.id))]
+    pub async fn config_file(&self, config: &Config) -> Result<String, ServiceError> {
+        let profile = self.profile(config.profile_id).await?;
+        // the server part is left for the user to fill in when the interface
+        // of the config is gone
+        let server = match self.server_info(&config.interface).await {
+            Ok(server) => Some(server),
+            Err(ServiceError::UnknownInterface(_)) => None,
+            Err(e) => return Err(e),
+        };
+        Ok(utils::format_config(config, &profile, server.as_ref()))
+    }
+
     #[instrument(skip(self))]
     pub async fn configs(&self, uid: Uuid) -> Result<Vec<Config>, ServiceError> {
         Ok(self.database.configs_by_uid(uid).await?)
     }
+
+    /// Configs of `user_id` as seen by `user`
+    #[instrument(skip(self))]
+    pub async fn user_configs(
+        &self,
+        user: &User,
+        user_id: Uuid,
+    ) -> Result<Vec<Config>, ServiceError> {
+        if user.id != user_id && !user.can(Permission::ConfigsManageAll) {
+            return Err(ServiceError::AccessDenied);
+        }
+        self.configs(user_id).await
+    }
 }
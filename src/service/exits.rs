@@ -0,0 +1,298 @@
+use std::{net::IpAddr, str::FromStr};
+
+use tracing::{instrument, warn};
+use uuid::Uuid;
+
+use crate::{
+    netlink::{error::NetlinkError, rules::Rule, Netlink},
+    roles::Permission,
+};
+
+use super::{audit::Change, configs::Config, ServiceError, Shared, User, Wgcfg};
+
+pub const DEFAULT_PRIORITY: u32 = 1000;
+
+/// Upstream the traffic of a config leaves through, configs without one go direct.
+///
+/// Every exit needs a table: policy rules can only match a mark, not set
+/// one, so `fwmark` narrows the rule to packets marked elsewhere (e.g. by
+/// nftables) and a mark alone has nowhere to send the traffic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exit {
+    pub id: Uuid,
+    pub name: String,
+    pub table: u32,
+    pub fwmark: Option<u32>,
+    pub priority: u32,
+}
+
+impl Exit {
+    pub fn rule(&self) -> Rule {
+        Rule {
+            table: self.table,
+            fwmark: self.fwmark,
+            priority: self.priority,
+        }
+    }
+}
+
+impl std::fmt::Display for Exit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} table={}", self.name, self.table)?;
+        if let Some(fwmark) = self.fwmark {
+            write!(f, " fwmark={fwmark}")?;
+        }
+        write!(f, " priority={}", self.priority)
+    }
+}
+
+/// Parses `name table=n [fwmark=n] [priority=n]`, the same format the exit
+/// is displayed in.
+impl FromStr for Exit {
+    type Err = ServiceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |e: &str| ServiceError::InvalidExit(e.to_owned());
+        let mut parts = s.split_whitespace();
+        let name = parts.next().ok_or_else(|| invalid("name is missing"))?;
+        if name == "direct" {
+            return Err(invalid("direct is reserved"));
+        }
+
+        let mut table = None;
+        let mut res = Self {
+            id: Uuid::new_v4(),
+            name: name.to_owned(),
+            table: 0,
+            fwmark: None,
+            priority: DEFAULT_PRIORITY,
+        };
+        for p in parts {
+            let number = |v: &str| {
+                v.parse::<u32>()
+                    .map_err(|_| invalid(&format!("invalid {p}")))
+            };
+            match p.split_once('=') {
+                Some(("table", v)) => table = Some(number(v)?),
+                Some(("fwmark", v)) => res.fwmark = Some(number(v)?),
+                Some(("priority", v)) => res.priority = number(v)?,
+                _ => return Err(invalid(&format!("unknown option {p}"))),
+            }
+        }
+        res.table = table.ok_or_else(|| {
+            invalid("table is missing, fwmark only narrows the rule to marked packets")
+        })?;
+        Ok(res)
+    }
+}
+
+/// Makes sure `addr` is routed through `exit` only, the rules of all other
+/// exits for it are removed. Returns the errors of the rule changes.
+pub(super) fn sync_rules(
+    netlink: &Netlink,
+    addr: IpAddr,
+    exit: Option<Uuid>,
+    exits: &[Exit],
+) -> Vec<NetlinkError> {
+    let mut errors = Vec::new();
+    for (rule, enable) in rule_changes(exit, exits) {
+        match (netlink.change_rule(addr, &rule, enable), enable) {
+            (Ok(()), _) | (Err(NetlinkError::NotFound), false) => {}
+            (Err(NetlinkError::AlreadyExists), true) => {}
+            (Err(err), _) => errors.push(err),
+        }
+    }
+    errors
+}
+
+/// Rules to remove and add for an address switching to `exit`, removals
+/// first: two exits may share a table with different priorities
+fn rule_changes(exit: Option<Uuid>, exits: &[Exit]) -> Vec<(Rule, bool)> {
+    let (wanted, other): (Vec<_>, Vec<_>) = exits.iter().partition(|e| Some(e.id) == exit);
+    other
+        .iter()
+        .map(|e| (e.rule(), false))
+        .chain(wanted.iter().map(|e| (e.rule(), true)))
+        .collect()
+}
+
+impl Wgcfg {
+    #[instrument(skip(self))]
+    pub async fn exits(&self) -> Result<Vec<Exit>, ServiceError> {
+        Ok(self.database.exits().await?)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn exit(&self, id: Uuid) -> Result<Exit, ServiceError> {
+        self.database.exit(id).await?.ok_or(ServiceError::NotFound)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn create_exit(&self, user: &User, spec: &str) -> Result<Uuid, ServiceError> {
+        if !user.can(Permission::SettingsManage) {
+            return Err(ServiceError::AccessDenied);
+        }
+        let exit: Exit = spec.parse()?;
+        self.database.add_exit(&exit).await?;
+        self.audit(Some(user), Change::new("exit.create").after(spec))
+            .await;
+        Ok(exit.id)
+    }
+
+    /// Removes the exit, configs using it go direct.
+    #[instrument(skip(self))]
+    pub async fn rm_exit(&self, user: &User, id: Uuid) -> Result<(), ServiceError> {
+        if !user.can(Permission::SettingsManage) {
+            return Err(ServiceError::AccessDenied);
+        }
+        let state = self.shared.lock().await;
+        let exit = self.exit(id).await?;
+        let configs = self.database.configs().await?;
+        for c in configs.iter().filter(|c| c.exit_id == Some(id)) {
+            for addr in c.addrs() {
+                match state.netlink.change_rule(addr, &exit.rule(), false) {
+                    Ok(()) | Err(NetlinkError::NotFound) => {}
+                    Err(e) => warn!("ip rule del for {addr} failed with error: {e}"),
+                }
+            }
+        }
+        self.database.rm_exit(id).await?;
+        self.audit(Some(user), Change::new("exit.remove").before(&exit.name))
+            .await;
+        Ok(())
+    }
+
+    /// Moves the config to another exit, `None` routes it directly.
+    #[instrument(skip(self))]
+    pub async fn set_config_exit(
+        &self,
+        user: &User,
+        config_id: Uuid,
+        exit_id: Option<Uuid>,
+    ) -> Result<(), ServiceError> {
+        let state = self.shared.lock().await;
+        let Some(config) = self.database.config(config_id).await? else {
+            return Err(ServiceError::NotFound);
+        };
+        if config.user_id != user.id && !user.can(Permission::ConfigsManageAll) {
+            return Err(ServiceError::AccessDenied);
+        }
+        self.apply_exit(&state, user, config, exit_id).await
+    }
+
+    /// Same as [`Wgcfg::set_config_exit`] for the config with `addr` and the exit
+    /// called `exit`, the caller is responsible for checking `user` has access.
+    #[instrument(skip(self))]
+    pub async fn change_settings(
+        &self,
+        user: &User,
+        addr: std::net::IpAddr,
+        exit: Option<&str>,
+    ) -> Result<(), ServiceError> {
+        let state = self.shared.lock().await;
+        let Some(config) = self.database.config_by_addr(addr).await? else {
+            return Err(ServiceError::NotFound);
+        };
+        let exit_id = match exit {
+            Some(name) => Some(
+                self.exits()
+                    .await?
+                    .into_iter()
+                    .find(|e| e.name == name)
+                    .ok_or(ServiceError::NotFound)?
+                    .id,
+            ),
+            None => None,
+        };
+        self.apply_exit(&state, user, config, exit_id).await
+    }
+
+    /// Same as [`Wgcfg::change_settings`] on behalf of the owner of the config with `addr`
+    #[instrument(skip(self))]
+    pub async fn change_settings_by_addr(
+        &self,
+        addr: std::net::IpAddr,
+        exit: Option<&str>,
+    ) -> Result<(), ServiceError> {
+        let Some(config) = self.database.config_by_addr(addr).await? else {
+            return Err(ServiceError::NotFound);
+        };
+        let owner = self.user_by_id(config.user_id).await?;
+        self.change_settings(&owner, addr, exit).await
+    }
+
+    /// Needs the lock taken before `config` was read
+    async fn apply_exit(
+        &self,
+        state: &Shared,
+        actor: &User,
+        mut config: Config,
+        exit_id: Option<Uuid>,
+    ) -> Result<(), ServiceError> {
+        if config.deleted {
+            return Err(ServiceError::NotFound);
+        }
+
+        let exits = self.database.exits().await?;
+        if exit_id.is_some() && !exits.iter().any(|e| Some(e.id) == exit_id) {
+            return Err(ServiceError::NotFound);
+        }
+        for addr in config.addrs() {
+            if let Some(e) = sync_rules(&state.netlink, addr, exit_id, &exits).pop() {
+                Err(e)?
+            }
+        }
+        let name = |id: Option<Uuid>| {
+            exits
+                .iter()
+                .find(|e| Some(e.id) == id)
+                .map_or("direct", |e| e.name.as_str())
+        };
+        let change = Change::of_config("config.exit", &config)
+            .before(name(config.exit_id))
+            .after(name(exit_id));
+        config.exit_id = exit_id;
+        self.database.update_config(config).await?;
+        self.audit(Some(actor), change).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exit(n: u128, table: u32, priority: u32) -> Exit {
+        Exit {
+            id: Uuid::from_u128(n),
+            name: format!("exit{n}"),
+            table,
+            fwmark: None,
+            priority,
+        }
+    }
+
+    #[test]
+    fn removals_come_before_the_addition() {
+        let exits = [exit(1, 100, 1000), exit(2, 100, 1001), exit(3, 101, 1000)];
+        let changes = rule_changes(Some(exits[0].id), &exits);
+        assert_eq!(
+            changes,
+            [
+                (exits[1].rule(), false),
+                (exits[2].rule(), false),
+                (exits[0].rule(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn direct_removes_every_exit() {
+        let exits = [exit(1, 100, 1000), exit(2, 101, 1000)];
+        let changes = rule_changes(None, &exits);
+        assert_eq!(
+            changes,
+            [(exits[0].rule(), false), (exits[1].rule(), false)]
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use time::OffsetDateTime;
+use tracing::{info, instrument};
+use uuid::Uuid;
+
+use crate::roles::Permission;
+
+use super::{audit::Change, notifications::Notification, ServiceError, User, Wgcfg};
+
+/// Parses durations like `12h`, `7d` or `2w`
+pub fn parse_duration(s: &str) -> Option<time::Duration> {
+    let (n, unit) = s.split_at(s.len().checked_sub(1)?);
+    let n: i64 = n.parse().ok().filter(|n| *n > 0)?;
+    match unit {
+        "h" => Some(time::Duration::hours(n)),
+        "d" => Some(time::Duration::days(n)),
+        "w" => Some(time::Duration::weeks(n)),
+        _ => None,
+    }
+}
+
+impl Wgcfg {
+    /// Sets when the config stops working, `None` keeps it forever. An expired
+    /// config that is extended into the future works again right away.
+    #[instrument(skip(self))]
+    pub async fn set_expiry(
+        &self,
+        user: &User,
+        config_id: Uuid,
+        expires_at: Option<OffsetDateTime>,
+    ) -> Result<(), ServiceError> {
+        if !user.can(Permission::ConfigsManageAll) {
+            return Err(ServiceError::AccessDenied);
+        }
+        let Some(config) = self.database.config(config_id).await? else {
+            return Err(ServiceError::NotFound);
+        };
+        if config.deleted {
+            return Err(ServiceError::NotFound);
+        }
+
+        let timestamp = |t: Option<i64>| t.map_or("never".to_owned(), |t| t.to_string());
+        let change = Change::of_config("config.expiry", &config)
+            .before(timestamp(config.expires_at))
+            .after(timestamp(expires_at.map(|t| t.unix_timestamp())));
+        self.database
+            .set_expiry(config_id, expires_at.map(|t| t.unix_timestamp()))
+            .await?;
+        self.audit(Some(user), change).await;
+        if config.expires_at.is_some() {
+            self.notify(Notification::ConfigExtended {
+                user_id: config.user_id,
+                config_id,
+                name: config.name,
+                expires_at,
+            });
+        }
+        self.enforce_expiry().await
+    }
+
+    /// Disables configs past their expiry, brings back extended ones and
+    /// warns owners of configs that expire within the warning period.
+    #[instrument(skip(self))]
+    pub async fn enforce_expiry(&self) -> Result<(), ServiceError> {
+        let now = OffsetDateTime::now_utc();
+        let mut state = self.shared.lock().await;
+
+        for mut c in self
+            .database
+            .configs()
+            .await?
+            .into_iter()
+            .filter(|c| !c.deleted)
+        {
+            let expired = c.expires_at.is_some_and(|t| t <= now.unix_timestamp());
+            if expired != c.expired {
+                c.expired = expired;
+                self.sync_peer(&mut state, &c).await?;
+                if expired {
+                    info!(config_id = %c.id, "config expired");
+                    self.database
+                        .set_expired(c.id, Some(now.unix_timestamp()))
+                        .await?;
+                    self.audit(None, Change::of_config("config.expire", &c))
+                        .await;
+                    self.notify(Notification::ConfigExpired {
+                        user_id: c.user_id,
+                        config_id: c.id,
+                        name: c.name,
+                    });
+                } else {
+                    info!(config_id = %c.id, "config extended, restored");
+                    self.database.set_expired(c.id, None).await?;
+                    self.audit(None, Change::of_config("config.unexpire", &c))
+                        .await;
+                }
+                continue;
+            }
+
+            let Some(expires_at) = c.expires_at else {
+                continue;
+            };
+            if !expired
+                && !c.expiry_warned
+                && expires_at - now.unix_timestamp() <= self.expiry_warning.whole_seconds()
+            {
+                self.database
+                    .set_expiry_warned(c.id, now.unix_timestamp())
+                    .await?;
+                self.notify(Notification::ExpiryWarning {
+                    user_id: c.user_id,
+                    config_id: c.id,
+                    name: c.name,
+                    expires_at: OffsetDateTime::from_unix_timestamp(expires_at)
+                        .map_err(|e| ServiceError::Unexpected(e.to_string()))?,
+                });
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use cidr::{IpCidr, Ipv4Cidr, Ipv6Cidr};
+use tracing::warn;
+
+use crate::netlink::{error::NetlinkError, wireguard::WireguardInterfaceId, Netlink};
+
+use super::{configs::Config, Pool, ServiceError, Wgcfg};
+
+/// Command line description of an interface besides the primary one
+#[derive(Debug, Clone)]
+pub struct InterfaceSpec {
+    pub name: String,
+    pub range: Ipv4Cidr,
+    pub range6: Option<Ipv6Cidr>,
+    pub endpoint: SocketAddr,
+    pub server_ip: Option<Ipv4Addr>,
+    pub server_ip6: Option<Ipv6Addr>,
+}
+
+/// Parses `name range=cidr endpoint=addr:port [range6=cidr] [server_ip=ip] [server_ip6=ip]`
+impl FromStr for InterfaceSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn parse<T: FromStr>(k: &str, v: &str) -> Result<T, String> {
+            v.parse().map_err(|_| format!("invalid {k}: {v}"))
+        }
+
+        let mut parts = s.split_whitespace();
+        let name = parts.next().ok_or("interface name is missing")?;
+        let (mut range, mut range6, mut endpoint) = (None, None, None);
+        let (mut server_ip, mut server_ip6) = (None, None);
+        for p in parts {
+            match p.split_once('=') {
+                Some((k @ "range", v)) => range = Some(parse(k, v)?),
+                Some((k @ "range6", v)) => range6 = Some(parse(k, v)?),
+                Some((k @ "endpoint", v)) => endpoint = Some(parse(k, v)?),
+                Some((k @ "server_ip", v)) => server_ip = Some(parse(k, v)?),
+                Some((k @ "server_ip6", v)) => server_ip6 = Some(parse(k, v)?),
+                _ => return Err(format!("unknown option {p}")),
+            }
+        }
+
+        Ok(Self {
+            name: name.to_owned(),
+            range: range.ok_or("range is missing")?,
+            range6,
+            endpoint: endpoint.ok_or("endpoint is missing")?,
+            server_ip,
+            server_ip6,
+        })
+    }
+}
+
+/// WireGuard interface managed by the service, every one has its own
+/// address ranges, endpoint and server key
+#[derive(Debug, Clone)]
+pub struct Interface {
+    pub name: String,
+    pub index: u32,
+    pub pub_key: String,
+    pub endpoint: SocketAddr,
+    pub ranges: Vec<IpCidr>,
+    pub(super) pool: Pool<Ipv4Addr>,
+    pub(super) pool6: Option<Pool<Ipv6Addr>>,
+}
+
+impl Interface {
+    pub(super) async fn open(
+        netlink: &mut Netlink,
+        spec: InterfaceSpec,
+    ) -> Result<Self, NetlinkError> {
+        let iface = netlink
+            .wg_interface(WireguardInterfaceId::Name(spec.name.clone()))
+            .await?;
+
+        Ok(Self {
+            index: iface.index,
+            pub_key: STANDARD.encode(iface.public_key),
+            endpoint: spec.endpoint,
+            pool: Pool::v4(spec.range, spec.server_ip),
+            pool6: spec.range6.map(|r| Pool::v6(r, spec.server_ip6)),
+            ranges: [IpCidr::V4(spec.range)]
+                .into_iter()
+                .chain(spec.range6.map(IpCidr::V6))
+                .collect(),
+            name: spec.name,
+        })
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        self.ranges.iter().any(|r| r.contains(&addr))
+    }
+}
+
+impl Wgcfg {
+    /// All managed interfaces, the primary one comes first
+    pub fn interfaces(&self) -> &[Interface] {
+        &self.interfaces
+    }
+
+    /// Interface new configs go to unless asked otherwise
+    pub fn primary_interface(&self) -> &Interface {
+        &self.interfaces[0]
+    }
+
+    pub fn interface(&self, name: &str) -> Result<&Interface, ServiceError> {
+        self.interfaces
+            .iter()
+            .find(|i| i.name == name)
+            .ok_or_else(|| ServiceError::UnknownInterface(name.to_owned()))
+    }
+
+    /// Interface of `config`, `None` when it is no longer configured. Such
+    /// configs are still changed in the database, only the kernel is skipped.
+    pub(super) fn config_interface(&self, config: &Config) -> Option<&Interface> {
+        let iface = self.interface(&config.interface).ok();
+        if iface.is_none() {
+            warn!(config_id = %config.id, "interface {} is not configured", config.interface);
+        }
+        iface
+    }
+
+    /// Interface whose ranges contain `addr`
+    pub fn interface_by_addr(&self, addr: IpAddr) -> Option<&Interface> {
+        self.interfaces.iter().find(|i| i.contains(addr))
+    }
+}
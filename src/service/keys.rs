@@ -1,4 +1,4 @@
-use crate::service::ServiceError;
+use crate::{roles::Permission, service::ServiceError};
 use tracing::instrument;
 use uuid::Uuid;
 
@@ -17,7 +17,7 @@ impl Wgcfg {
         let Some(key) = key else {
             return Err(ServiceError::NotFound);
         };
-        if !user.is_admin() && user.id != key.user_id {
+        if !user.can(Permission::ConfigsManageAll) && user.id != key.user_id {
             return Err(ServiceError::AccessDenied);
         }
 
@@ -0,0 +1,80 @@
+use time::OffsetDateTime;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use super::{quotas::Quota, Wgcfg};
+
+/// Events the frontends forward to the affected users
+#[derive(Debug, Clone)]
+pub enum Notification {
+    /// The peer is out of the kernel until the period resets or the cap is raised
+    QuotaExceeded {
+        user_id: Uuid,
+        config_id: Uuid,
+        name: String,
+        quota: Quota,
+    },
+    QuotaRestored {
+        user_id: Uuid,
+        config_id: Uuid,
+        name: String,
+    },
+    ExpiryWarning {
+        user_id: Uuid,
+        config_id: Uuid,
+        name: String,
+        expires_at: OffsetDateTime,
+    },
+    ConfigExpired {
+        user_id: Uuid,
+        config_id: Uuid,
+        name: String,
+    },
+    /// An admin moved the expiry, `None` means it never expires now
+    ConfigExtended {
+        user_id: Uuid,
+        config_id: Uuid,
+        name: String,
+        expires_at: Option<OffsetDateTime>,
+    },
+    /// An admin suspended the config
+    ConfigDisabled {
+        user_id: Uuid,
+        config_id: Uuid,
+        name: String,
+    },
+    /// An admin resumed the config, it stays out of the kernel while
+    /// `active` is false because of a quota or its expiry
+    ConfigEnabled {
+        user_id: Uuid,
+        config_id: Uuid,
+        name: String,
+        active: bool,
+    },
+}
+
+impl Notification {
+    /// User the notification is meant for
+    pub fn user_id(&self) -> Uuid {
+        match self {
+            Notification::QuotaExceeded { user_id, .. }
+            | Notification::QuotaRestored { user_id, .. }
+            | Notification::ExpiryWarning { user_id, .. }
+            | Notification::ConfigExpired { user_id, .. }
+            | Notification::ConfigExtended { user_id, .. }
+            | Notification::ConfigDisabled { user_id, .. }
+            | Notification::ConfigEnabled { user_id, .. } => *user_id,
+        }
+    }
+}
+
+impl Wgcfg {
+    pub fn subscribe(&self) -> broadcast::Receiver<Notification> {
+        self.notifications.subscribe()
+    }
+
+    pub(super) fn notify(&self, notification: Notification) {
+        // nobody listening is fine, e.g. the bot is disabled
+        let _ = self.notifications.send(notification);
+    }
+}
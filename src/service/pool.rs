@@ -0,0 +1,266 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use cidr::{Ipv4Cidr, Ipv6Cidr};
+use time::{Duration, OffsetDateTime};
+use tracing::{info, instrument};
+
+use super::{interfaces::Interface, ServiceError, Shared, Wgcfg};
+
+pub trait PoolAddr: Copy {
+    fn to_u128(self) -> u128;
+    fn from_u128(bits: u128) -> Self;
+}
+
+impl PoolAddr for Ipv4Addr {
+    fn to_u128(self) -> u128 {
+        u32::from(self) as u128
+    }
+
+    fn from_u128(bits: u128) -> Self {
+        Ipv4Addr::from(bits as u32)
+    }
+}
+
+impl PoolAddr for Ipv6Addr {
+    fn to_u128(self) -> u128 {
+        u128::from(self)
+    }
+
+    fn from_u128(bits: u128) -> Self {
+        Ipv6Addr::from(bits)
+    }
+}
+
+/// Host addresses of a range that can be handed out to configs.
+#[derive(Debug, Clone)]
+pub struct Pool<A> {
+    first: u128,
+    last: u128,
+    reserved: Vec<u128>,
+    _family: std::marker::PhantomData<A>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PoolUsage {
+    pub used: u128,
+    pub capacity: u128,
+}
+
+impl std::fmt::Display for PoolUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.used, self.capacity)
+    }
+}
+
+impl Pool<Ipv4Addr> {
+    /// Skips network and broadcast addresses, and the server address
+    /// (the first host of the range unless set explicitly).
+    pub fn v4(range: Ipv4Cidr, server: Option<Ipv4Addr>) -> Self {
+        let (mut first, mut last) = (
+            range.first_address().to_u128(),
+            range.last_address().to_u128(),
+        );
+        if range.network_length() <= 30 {
+            first += 1;
+            last -= 1;
+        }
+        Self::new(first, last, server.map(|s| s.to_u128()).unwrap_or(first))
+    }
+}
+
+impl Pool<Ipv6Addr> {
+    /// Skips the subnet-router anycast address and the server address
+    /// (the first host of the range unless set explicitly).
+    pub fn v6(range: Ipv6Cidr, server: Option<Ipv6Addr>) -> Self {
+        let (mut first, last) = (
+            range.first_address().to_u128(),
+            range.last_address().to_u128(),
+        );
+        if range.network_length() < 128 {
+            first += 1;
+        }
+        Self::new(first, last, server.map(|s| s.to_u128()).unwrap_or(first))
+    }
+}
+
+impl<A: PoolAddr> Pool<A> {
+    fn new(first: u128, last: u128, server: u128) -> Self {
+        Self {
+            first,
+            last,
+            reserved: vec![server],
+            _family: Default::default(),
+        }
+    }
+
+    fn contains(&self, addr: u128) -> bool {
+        self.first <= addr && addr <= self.last
+    }
+
+    /// Lowest address of the pool that is neither reserved nor in `used`.
+    pub fn lowest_free(&self, used: &[A]) -> Option<A> {
+        let mut taken = used
+            .iter()
+            .map(|a| a.to_u128())
+            .chain(self.reserved.iter().copied())
+            .filter(|a| self.contains(*a))
+            .collect::<Vec<_>>();
+        taken.sort_unstable();
+        taken.dedup();
+
+        let mut candidate = self.first;
+        for addr in taken {
+            if addr != candidate {
+                break;
+            }
+            candidate = candidate.checked_add(1)?;
+        }
+
+        self.contains(candidate).then(|| A::from_u128(candidate))
+    }
+
+    pub fn usage(&self, used: &[A]) -> PoolUsage {
+        let reserved = self.reserved.iter().filter(|a| self.contains(**a)).count() as u128;
+        let used = used
+            .iter()
+            .map(|a| a.to_u128())
+            .filter(|a| self.contains(*a) && !self.reserved.contains(a))
+            .count() as u128;
+
+        PoolUsage {
+            used,
+            capacity: (self.last - self.first).saturating_add(1) - reserved,
+        }
+    }
+}
+
+impl Wgcfg {
+    /// Picks addresses for a new config on `iface`. The caller has to keep
+    /// `_shared` locked until the addresses are stored, otherwise two configs
+    /// can get the same address.
+    #[instrument(skip(self, _shared, iface), fields(iface = %iface.name))]
+    pub(super) async fn allocate_addrs(
+        &self,
+        _shared: &mut Shared,
+        iface: &Interface,
+    ) -> Result<(Ipv4Addr, Option<Ipv6Addr>), ServiceError> {
+        let cutoff = OffsetDateTime::now_utc() - Duration::hours(self.ip_reuse_delay_hours as _);
+        let released = self.database.release_addrs(cutoff.unix_timestamp()).await?;
+        if released > 0 {
+            info!("released addresses of {released} deleted configs");
+        }
+
+        let (used, used6) = self.database.used_addrs().await?;
+        let ip = iface
+            .pool
+            .lowest_free(&used)
+            .ok_or(ServiceError::IpPoolExhausted)?;
+        let ip6 = iface
+            .pool6
+            .as_ref()
+            .map(|p| p.lowest_free(&used6).ok_or(ServiceError::IpPoolExhausted))
+            .transpose()?;
+
+        Ok((ip, ip6))
+    }
+
+    /// Gives an ipv6 address to configs created before their interface had an
+    /// ipv6 pool, returns how many got one.
+    pub(super) async fn backfill_addr6(&self) -> Result<usize, ServiceError> {
+        let (_, mut used6) = self.database.used_addrs().await?;
+        let configs = self.database.configs().await?;
+        let mut filled = 0;
+        for iface in self.interfaces.iter() {
+            let Some(pool6) = &iface.pool6 else {
+                continue;
+            };
+            for c in configs
+                .iter()
+                .filter(|c| !c.deleted && c.ip6.is_none() && c.interface == iface.name)
+            {
+                let ip6 = pool6
+                    .lowest_free(&used6)
+                    .ok_or(ServiceError::IpPoolExhausted)?;
+                self.database.set_addr6(c.id, ip6).await?;
+                used6.push(ip6);
+                filled += 1;
+            }
+        }
+        Ok(filled)
+    }
+
+    /// Usage of the ipv4 and ipv6 pools of every interface, keyed by its name
+    #[instrument(skip(self))]
+    pub async fn pool_usage(
+        &self,
+    ) -> Result<Vec<(String, PoolUsage, Option<PoolUsage>)>, ServiceError> {
+        let (used, used6) = self.database.used_addrs().await?;
+        Ok(self
+            .interfaces
+            .iter()
+            .map(|i| {
+                (
+                    i.name.clone(),
+                    i.pool.usage(&used),
+                    i.pool6.as_ref().map(|p| p.usage(&used6)),
+                )
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v4(range: &str, server: Option<&str>) -> Pool<Ipv4Addr> {
+        Pool::v4(range.parse().unwrap(), server.map(|s| s.parse().unwrap()))
+    }
+
+    fn addrs(a: &[&str]) -> Vec<Ipv4Addr> {
+        a.iter().map(|a| a.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn v4_skips_network_server_and_broadcast() {
+        let pool = v4("10.0.0.0/29", None);
+        assert_eq!(pool.lowest_free(&[]), Some("10.0.0.2".parse().unwrap()));
+
+        let used = addrs(&["10.0.0.2", "10.0.0.3", "10.0.0.4", "10.0.0.5"]);
+        assert_eq!(pool.lowest_free(&used), Some("10.0.0.6".parse().unwrap()));
+
+        let used = addrs(&["10.0.0.2", "10.0.0.3", "10.0.0.4", "10.0.0.5", "10.0.0.6"]);
+        assert_eq!(pool.lowest_free(&used), None);
+    }
+
+    #[test]
+    fn v4_explicit_server() {
+        let pool = v4("10.0.0.0/29", Some("10.0.0.3"));
+        let used = addrs(&["10.0.0.1", "10.0.0.2"]);
+        assert_eq!(pool.lowest_free(&used), Some("10.0.0.4".parse().unwrap()));
+    }
+
+    #[test]
+    fn freed_addresses_are_reused_lowest_first() {
+        let pool = v4("10.0.0.0/24", None);
+        let used = addrs(&["10.0.0.2", "10.0.0.4", "10.0.0.5"]);
+        assert_eq!(pool.lowest_free(&used), Some("10.0.0.3".parse().unwrap()));
+    }
+
+    #[test]
+    fn v6_skips_anycast_and_server() {
+        let pool = Pool::v6("fd00::/126".parse().unwrap(), None);
+        let used: Vec<Ipv6Addr> = vec!["fd00::2".parse().unwrap()];
+        assert_eq!(pool.lowest_free(&[]), Some("fd00::2".parse().unwrap()));
+        assert_eq!(pool.lowest_free(&used), Some("fd00::3".parse().unwrap()));
+    }
+
+    #[test]
+    fn usage_ignores_server_and_foreign_addresses() {
+        let pool = v4("10.0.0.0/24", None);
+        let used = addrs(&["10.0.0.1", "10.0.0.2", "10.0.0.3", "10.0.1.2"]);
+        let usage = pool.usage(&used);
+        assert_eq!(usage.used, 2);
+        assert_eq!(usage.capacity, 253);
+    }
+}
@@ -0,0 +1,65 @@
+use time::{Duration, OffsetDateTime};
+use tracing::instrument;
+
+use crate::{database::ConnectedPeer, roles::Permission};
+
+use super::{ServiceError, User, Wgcfg};
+
+/// WireGuard renews the session every two minutes while there is traffic, a
+/// peer counts as online if its latest handshake is younger than this.
+pub const ONLINE_WINDOW: Duration = Duration::minutes(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Presence {
+    Online,
+    /// Time since the latest handshake
+    LastSeen(Duration),
+    Never,
+}
+
+impl Presence {
+    pub fn new(last_handshake: Option<i64>, now: OffsetDateTime) -> Self {
+        match last_handshake {
+            None => Presence::Never,
+            Some(t) => {
+                let ago = Duration::seconds(now.unix_timestamp() - t);
+                if ago < ONLINE_WINDOW {
+                    Presence::Online
+                } else {
+                    Presence::LastSeen(ago)
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Presence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Presence::Online => write!(f, "online"),
+            Presence::Never => write!(f, "never connected"),
+            Presence::LastSeen(ago) if ago.whole_days() > 0 => {
+                write!(f, "last seen {} days ago", ago.whole_days())
+            }
+            Presence::LastSeen(ago) if ago.whole_hours() > 0 => {
+                write!(f, "last seen {} hours ago", ago.whole_hours())
+            }
+            Presence::LastSeen(ago) => write!(f, "last seen {} minutes ago", ago.whole_minutes()),
+        }
+    }
+}
+
+impl Wgcfg {
+    /// Peers that are online right now
+    #[instrument(skip(self))]
+    pub async fn connected_peers(&self, user: &User) -> Result<Vec<ConnectedPeer>, ServiceError> {
+        if !user.can(Permission::StatsViewAll) {
+            return Err(ServiceError::AccessDenied);
+        }
+        let since = OffsetDateTime::now_utc() - ONLINE_WINDOW;
+        Ok(self
+            .database
+            .connected_peers(since.unix_timestamp())
+            .await?)
+    }
+}
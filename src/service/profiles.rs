@@ -0,0 +1,216 @@
+use std::{net::IpAddr, str::FromStr};
+
+use cidr::IpCidr;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::roles::Permission;
+
+use super::{audit::Change, ServiceError, User, Wgcfg};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Routing {
+    /// Everything goes through the tunnel
+    Full,
+    /// Only the vpn ranges go through the tunnel
+    Internal,
+    Custom(Vec<IpCidr>),
+}
+
+impl Routing {
+    pub fn kind(&self) -> i64 {
+        match self {
+            Routing::Full => 0,
+            Routing::Internal => 1,
+            Routing::Custom(_) => 2,
+        }
+    }
+}
+
+impl std::fmt::Display for Routing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Routing::Full => f.write_str("full"),
+            Routing::Internal => f.write_str("internal"),
+            Routing::Custom(c) => f.write_str(&join(c, ",")),
+        }
+    }
+}
+
+impl FromStr for Routing {
+    type Err = ServiceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(Self::Full),
+            "internal" => Ok(Self::Internal),
+            s => Ok(Self::Custom(parse_list(s)?)),
+        }
+    }
+}
+
+/// Client side settings of a config
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub id: Uuid,
+    pub name: String,
+    pub routing: Routing,
+    pub dns: Vec<IpAddr>,
+    pub mtu: Option<u16>,
+    pub keepalive: Option<u16>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            id: Uuid::nil(),
+            name: "default".to_owned(),
+            routing: Routing::Full,
+            dns: Vec::new(),
+            mtu: None,
+            keepalive: None,
+        }
+    }
+}
+
+impl std::fmt::Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.name, self.routing)?;
+        if !self.dns.is_empty() {
+            write!(f, " dns={}", join(&self.dns, ","))?;
+        }
+        if let Some(mtu) = self.mtu {
+            write!(f, " mtu={mtu}")?;
+        }
+        if let Some(keepalive) = self.keepalive {
+            write!(f, " keepalive={keepalive}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses `name full|internal|cidr,... [dns=ip,...] [mtu=n] [keepalive=n]`,
+/// the same format the profile is displayed in.
+impl FromStr for Profile {
+    type Err = ServiceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |e: &str| ServiceError::InvalidProfile(e.to_owned());
+        let mut parts = s.split_whitespace();
+        let name = parts.next().ok_or_else(|| invalid("name is missing"))?;
+        let routing = parts
+            .next()
+            .ok_or_else(|| invalid("routing is missing"))?
+            .parse()?;
+
+        let mut res = Self {
+            id: Uuid::new_v4(),
+            name: name.to_owned(),
+            routing,
+            ..Default::default()
+        };
+        for p in parts {
+            match p.split_once('=') {
+                Some(("dns", v)) => res.dns = parse_list(v)?,
+                Some(("mtu", v)) => res.mtu = Some(v.parse().map_err(|_| invalid("invalid mtu"))?),
+                Some(("keepalive", v)) => {
+                    res.keepalive = Some(v.parse().map_err(|_| invalid("invalid keepalive"))?)
+                }
+                _ => return Err(invalid(&format!("unknown option {p}"))),
+            }
+        }
+        Ok(res)
+    }
+}
+
+pub(crate) fn join<T: ToString>(items: &[T], sep: &str) -> String {
+    items
+        .iter()
+        .map(|i| i.to_string())
+        .collect::<Vec<_>>()
+        .join(sep)
+}
+
+pub(crate) fn parse_list<T: FromStr>(s: &str) -> Result<Vec<T>, ServiceError> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|i| !i.is_empty())
+        .map(|i| {
+            i.parse()
+                .map_err(|_| ServiceError::InvalidProfile(format!("invalid value {i}")))
+        })
+        .collect()
+}
+
+impl Wgcfg {
+    #[instrument(skip(self))]
+    pub async fn profiles(&self) -> Result<Vec<Profile>, ServiceError> {
+        Ok(self.database.profiles().await?)
+    }
+
+    /// Profile used for the config files, the default one when `id` is `None`.
+    #[instrument(skip(self))]
+    pub async fn profile(&self, id: Option<Uuid>) -> Result<Profile, ServiceError> {
+        let Some(id) = id else {
+            return Ok(Profile::default());
+        };
+        self.database
+            .profile(id)
+            .await?
+            .ok_or(ServiceError::NotFound)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn create_profile(&self, user: &User, spec: &str) -> Result<Uuid, ServiceError> {
+        if !user.can(Permission::SettingsManage) {
+            return Err(ServiceError::AccessDenied);
+        }
+        let profile: Profile = spec.parse()?;
+        self.database.add_profile(&profile).await?;
+        self.audit(Some(user), Change::new("profile.create").after(spec))
+            .await;
+        Ok(profile.id)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn rm_profile(&self, user: &User, id: Uuid) -> Result<(), ServiceError> {
+        if !user.can(Permission::SettingsManage) {
+            return Err(ServiceError::AccessDenied);
+        }
+        let profile = self.profile(Some(id)).await?;
+        self.database.rm_profile(id).await?;
+        self.audit(
+            Some(user),
+            Change::new("profile.remove").before(&profile.name),
+        )
+        .await;
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    pub async fn set_config_profile(
+        &self,
+        user: &User,
+        config_id: Uuid,
+        profile_id: Option<Uuid>,
+    ) -> Result<(), ServiceError> {
+        // update_config writes the exit too, don't race apply_exit
+        let _state = self.shared.lock().await;
+        let Some(mut config) = self.database.config(config_id).await? else {
+            return Err(ServiceError::NotFound);
+        };
+        if !user.can(Permission::ConfigsManageAll) && user.id != config.user_id {
+            return Err(ServiceError::AccessDenied);
+        }
+        let before = self.profile(config.profile_id).await?;
+        let after = self.profile(profile_id).await?;
+
+        let change = Change::of_config("config.profile", &config)
+            .before(&before.name)
+            .after(&after.name);
+        config.profile_id = profile_id;
+        self.database.update_config(config).await?;
+        self.audit(Some(user), change).await;
+        Ok(())
+    }
+}
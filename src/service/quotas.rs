@@ -0,0 +1,274 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use time::OffsetDateTime;
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::{
+    database::{Period, Usage},
+    roles::Permission,
+};
+
+use super::{audit::Change, notifications::Notification, ServiceError, User, Wgcfg};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaTarget {
+    /// Sum of all configs of the user
+    User(Uuid),
+    Config(Uuid),
+}
+
+/// Data cap per billing period, the period is a calendar day or month in UTC
+#[derive(Debug, Clone)]
+pub struct Quota {
+    pub target: QuotaTarget,
+    /// Bytes, tx and rx together
+    pub limit: u64,
+    pub period: Period,
+}
+
+const GB: u64 = 1024 * 1024 * 1024;
+
+impl std::fmt::Display for Quota {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let period = match self.period {
+            Period::Hour => "hour",
+            Period::Day => "day",
+            Period::Month => "month",
+        };
+        write!(f, "{:.1} GB per {period}", self.limit as f64 / GB as f64)
+    }
+}
+
+/// Parses `<GB> [day|month]`, month is the default and 0 removes the cap.
+pub fn parse_limit(s: &str) -> Result<Option<(u64, Period)>, ServiceError> {
+    let invalid = || ServiceError::InvalidQuota(s.to_owned());
+    let mut parts = s.split_whitespace();
+    let gb: f64 = parts
+        .next()
+        .and_then(|v| v.parse().ok())
+        .filter(|v: &f64| v.is_finite() && *v >= 0.0)
+        .ok_or_else(invalid)?;
+    let period = match parts.next() {
+        None | Some("month") => Period::Month,
+        Some("day") => Period::Day,
+        Some(_) => return Err(invalid()),
+    };
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+
+    let limit = (gb * GB as f64) as u64;
+    Ok((limit > 0).then_some((limit, period)))
+}
+
+impl Quota {
+    fn applies(&self, config_id: Uuid, user_id: Uuid) -> bool {
+        match self.target {
+            QuotaTarget::User(u) => u == user_id,
+            QuotaTarget::Config(c) => c == config_id,
+        }
+    }
+
+    fn used(&self, usage: &[Usage]) -> u64 {
+        usage
+            .iter()
+            .filter(|u| match self.target {
+                QuotaTarget::User(id) => u.user_id == id,
+                QuotaTarget::Config(id) => u.config_id == id,
+            })
+            .map(|u| u.bytes)
+            .sum()
+    }
+}
+
+impl Wgcfg {
+    #[instrument(skip(self))]
+    pub async fn quotas(&self, config_id: Uuid, user_id: Uuid) -> Result<Vec<Quota>, ServiceError> {
+        Ok(self
+            .database
+            .quotas()
+            .await?
+            .into_iter()
+            .filter(|q| q.applies(config_id, user_id))
+            .collect())
+    }
+
+    /// Sets or removes (`limit` is `None`) a cap, a raised cap takes effect right away.
+    #[instrument(skip(self))]
+    pub async fn set_quota(
+        &self,
+        user: &User,
+        target: QuotaTarget,
+        limit: Option<(u64, Period)>,
+    ) -> Result<(), ServiceError> {
+        if !user.can(Permission::ConfigsManageAll) {
+            return Err(ServiceError::AccessDenied);
+        }
+        let describe = |q: Option<Quota>| q.map_or("none".to_owned(), |q| q.to_string());
+        let before = self
+            .database
+            .quotas()
+            .await?
+            .into_iter()
+            .find(|q| q.target == target);
+        let after = limit.map(|(limit, period)| Quota {
+            target,
+            limit,
+            period,
+        });
+        let change = match target {
+            QuotaTarget::User(id) => Change::new("quota.set").user(id),
+            QuotaTarget::Config(id) => Change::new("quota.set").config(id),
+        };
+        self.database.set_quota(target, limit).await?;
+        self.audit(
+            Some(user),
+            change.before(describe(before)).after(describe(after)),
+        )
+        .await;
+        self.enforce_quotas().await
+    }
+
+    /// Suspends configs over one of their caps and restores those that are
+    /// within all of them again, e.g. after the period reset.
+    #[instrument(skip(self))]
+    pub async fn enforce_quotas(&self) -> Result<(), ServiceError> {
+        let now = OffsetDateTime::now_utc();
+        let mut state = self.shared.lock().await;
+        let quotas = self.database.quotas().await?;
+        let mut usage = HashMap::new();
+        for q in &quotas {
+            if let Entry::Vacant(e) = usage.entry(q.period) {
+                e.insert(
+                    self.database
+                        .period_usage(q.period, q.period.start(now))
+                        .await?,
+                );
+            }
+        }
+
+        for mut c in self
+            .database
+            .configs()
+            .await?
+            .into_iter()
+            .filter(|c| !c.deleted)
+        {
+            let exceeded = quotas
+                .iter()
+                .filter(|q| q.applies(c.id, c.user_id))
+                .find(|q| q.used(&usage[&q.period]) >= q.limit);
+            if exceeded.is_some() == c.suspended {
+                continue;
+            }
+
+            c.suspended = exceeded.is_some();
+            let suspended_at = c.suspended.then(|| now.unix_timestamp());
+            let res = match self.sync_peer(&mut state, &c).await {
+                Ok(()) => self
+                    .database
+                    .set_suspended(c.id, suspended_at)
+                    .await
+                    .map_err(ServiceError::from),
+                Err(e) => Err(e),
+            };
+            // one broken config must not keep the others over their quota
+            if let Err(e) = res {
+                warn!(config_id = %c.id, "quota enforcement error: {e}");
+                continue;
+            }
+            if let Some(quota) = exceeded {
+                info!(config_id = %c.id, "quota exceeded, config suspended");
+                self.audit(
+                    None,
+                    Change::of_config("config.quota_suspend", &c).after(quota),
+                )
+                .await;
+                self.notify(Notification::QuotaExceeded {
+                    user_id: c.user_id,
+                    config_id: c.id,
+                    name: c.name,
+                    quota: quota.clone(),
+                });
+            } else {
+                info!(config_id = %c.id, "config within quota again, restored");
+                self.audit(None, Change::of_config("config.quota_restore", &c))
+                    .await;
+                self.notify(Notification::QuotaRestored {
+                    user_id: c.user_id,
+                    config_id: c.id,
+                    name: c.name,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(ts: i64) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(ts).unwrap()
+    }
+
+    fn usage(config: u128, user: u128, bytes: u64) -> Usage {
+        Usage {
+            config_id: Uuid::from_u128(config),
+            user_id: Uuid::from_u128(user),
+            bytes,
+        }
+    }
+
+    #[test]
+    fn periods_roll_over_at_utc_boundaries() {
+        // 2024-02-29 23:59:59 and one second later
+        let last = at(1709251199);
+        let next = at(1709251200);
+        assert_eq!(Period::Hour.start(last), 1709247600);
+        assert_eq!(Period::Day.start(last), 1709164800);
+        assert_eq!(Period::Month.start(last), 1706745600);
+        for period in [Period::Hour, Period::Day, Period::Month] {
+            assert_eq!(period.start(next), 1709251200);
+        }
+    }
+
+    #[test]
+    fn periods_ignore_the_local_offset() {
+        // 2025-01-01 01:30 at +02:00 is still 2024-12-31 in utc
+        let local = at(1735687800).to_offset(time::UtcOffset::from_hms(2, 0, 0).unwrap());
+        assert_eq!(Period::Day.start(local), 1735603200);
+        assert_eq!(Period::Month.start(local), 1733011200);
+    }
+
+    #[test]
+    fn used_sums_the_target_only() {
+        let usage = [usage(1, 10, 5), usage(2, 10, 7), usage(3, 20, 11)];
+        let user = Quota {
+            target: QuotaTarget::User(Uuid::from_u128(10)),
+            limit: GB,
+            period: Period::Day,
+        };
+        let config = Quota {
+            target: QuotaTarget::Config(Uuid::from_u128(3)),
+            ..user.clone()
+        };
+        assert_eq!(user.used(&usage), 12);
+        assert_eq!(config.used(&usage), 11);
+        // nothing counted yet in a fresh period
+        assert_eq!(user.used(&[]), 0);
+    }
+
+    #[test]
+    fn user_quotas_apply_to_every_config_of_the_user() {
+        let quota = Quota {
+            target: QuotaTarget::User(Uuid::from_u128(10)),
+            limit: GB,
+            period: Period::Month,
+        };
+        assert!(quota.applies(Uuid::from_u128(1), Uuid::from_u128(10)));
+        assert!(!quota.applies(Uuid::from_u128(1), Uuid::from_u128(20)));
+    }
+}
@@ -0,0 +1,477 @@
+use std::{collections::HashMap, net::IpAddr};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use cidr::IpCidr;
+use netlink_packet_wireguard::constants::WG_KEY_LEN;
+use tracing::{instrument, warn};
+use uuid::Uuid;
+
+use crate::netlink::{
+    rules::Rule,
+    wireguard::{PeerUpdate, WireguardInterfaceId, WireguardUpdate},
+};
+
+use super::{configs::Config, exits::Exit, interfaces::Interface, ServiceError, Shared, Wgcfg};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Correction {
+    PeerAdded {
+        config: Uuid,
+        key: String,
+    },
+    PeerRemoved {
+        interface: String,
+        key: String,
+    },
+    PresharedKeyReplaced {
+        config: Uuid,
+        key: String,
+    },
+    AllowedIpsReplaced {
+        config: Uuid,
+        key: String,
+        was: Vec<IpCidr>,
+    },
+    RouteRestored {
+        config: Uuid,
+        addr: IpAddr,
+    },
+    /// Route to an address no active config on the interface has
+    RouteRemoved {
+        interface: String,
+        addr: IpAddr,
+    },
+    RuleRestored {
+        config: Uuid,
+        addr: IpAddr,
+        exit: Uuid,
+    },
+    /// Rule of an exit the config at `addr` doesn't use, `None` when no config
+    /// has the address
+    RuleRemoved {
+        config: Option<Uuid>,
+        addr: IpAddr,
+        exit: Uuid,
+    },
+}
+
+#[derive(Debug, Default)]
+pub struct ReconcileReport {
+    pub corrections: Vec<Correction>,
+    pub failures: usize,
+}
+
+impl Wgcfg {
+    /// Brings the kernel state in line with active configs. Peers, host routes
+    /// and exit rules are listed and diffed against the database, missing ones
+    /// are added and stale ones removed.
+    #[instrument(skip(self))]
+    pub async fn reconcile(&self) -> Result<ReconcileReport, ServiceError> {
+        // every config and exit mutator takes the lock before its first database
+        // access and keeps it until the kernel is updated, so nothing changes
+        // between the reads below and the corrections
+        let mut state = self.shared.lock().await;
+        let configs = self.database.configs().await?;
+        let exits = self.database.exits().await?;
+
+        let mut report = ReconcileReport::default();
+        for iface in self.interfaces.iter() {
+            self.reconcile_interface(&mut state, iface, &configs, &mut report)
+                .await?;
+        }
+        reconcile_rules(&state, &configs, &exits, &mut report);
+
+        Ok(report)
+    }
+
+    async fn reconcile_interface(
+        &self,
+        state: &mut Shared,
+        iface: &Interface,
+        configs: &[Config],
+        report: &mut ReconcileReport,
+    ) -> Result<(), ServiceError> {
+        let kernel = state
+            .netlink
+            .wg_interface(WireguardInterfaceId::Index(iface.index))
+            .await?;
+
+        let mut kernel: HashMap<_, _> = kernel
+            .peers
+            .into_iter()
+            .map(|p| {
+                let psk = p.preshared_key.filter(|k| k != &[0; WG_KEY_LEN]);
+                (p.public_key, (p.allowed_ips, psk))
+            })
+            .collect();
+        let mut updates = Vec::new();
+        let mut routes = HashMap::new();
+
+        for c in configs
+            .iter()
+            .filter(|c| c.active() && c.interface == iface.name)
+        {
+            let key = STANDARD.encode(c.pub_key);
+            let mut wanted = c.allowed_ips();
+            wanted.sort();
+
+            match kernel.remove(&c.pub_key) {
+                None => {
+                    updates.push(PeerUpdate {
+                        public_key: Some(c.pub_key),
+                        preshared_key: c.psk,
+                        allowed_ips: Some(wanted),
+                        replace_allowed_ips: true,
+                        remove: false,
+                    });
+                    report
+                        .corrections
+                        .push(Correction::PeerAdded { config: c.id, key });
+                }
+                Some((mut was, psk)) => {
+                    was.sort();
+                    let mut update = PeerUpdate {
+                        public_key: Some(c.pub_key),
+                        preshared_key: None,
+                        allowed_ips: None,
+                        replace_allowed_ips: false,
+                        remove: false,
+                    };
+                    if psk != c.psk {
+                        update.preshared_key = Some(c.psk.unwrap_or_default());
+                        report.corrections.push(Correction::PresharedKeyReplaced {
+                            config: c.id,
+                            key: key.clone(),
+                        });
+                    }
+                    if was != wanted {
+                        update.allowed_ips = Some(wanted);
+                        update.replace_allowed_ips = true;
+                        report.corrections.push(Correction::AllowedIpsReplaced {
+                            config: c.id,
+                            key,
+                            was,
+                        });
+                    }
+                    if update.preshared_key.is_some() || update.allowed_ips.is_some() {
+                        updates.push(update);
+                    }
+                }
+            }
+
+            routes.extend(c.addrs().into_iter().map(|addr| (addr, c.id)));
+        }
+        reconcile_routes(state, iface, routes, report);
+
+        for key in kernel.into_keys() {
+            updates.push(PeerUpdate {
+                public_key: Some(key),
+                preshared_key: None,
+                allowed_ips: None,
+                replace_allowed_ips: false,
+                remove: true,
+            });
+            report.corrections.push(Correction::PeerRemoved {
+                interface: iface.name.clone(),
+                key: STANDARD.encode(key),
+            });
+        }
+
+        if !updates.is_empty() {
+            state
+                .netlink
+                .wireguard_update(
+                    WireguardInterfaceId::Index(iface.index),
+                    WireguardUpdate {
+                        replace_peers: false,
+                        peers: updates,
+                    },
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Adds the missing host routes of active configs on `iface` and removes the
+/// ones left behind, `wanted` maps addresses to their config.
+fn reconcile_routes(
+    state: &Shared,
+    iface: &Interface,
+    wanted: HashMap<IpAddr, Uuid>,
+    report: &mut ReconcileReport,
+) {
+    let present = match state.netlink.host_routes(iface.index) {
+        Ok(present) => present,
+        Err(e) => {
+            warn!("listing routes of {} failed with error: {e}", iface.name);
+            report.failures += 1;
+            return;
+        }
+    };
+
+    for c in route_corrections(&iface.name, present, wanted) {
+        let res = match &c {
+            Correction::RouteRemoved { addr, .. } => state.netlink.del_ip_route(*addr, iface.index),
+            Correction::RouteRestored { addr, .. } => {
+                state.netlink.add_ip_route(*addr, iface.index)
+            }
+            _ => continue,
+        };
+        match res {
+            Ok(()) => report.corrections.push(c),
+            Err(e) => {
+                warn!(correction = ?c, "route change failed with error: {e}");
+                report.failures += 1;
+            }
+        }
+    }
+}
+
+/// Removals of the `present` routes nobody wants, then restores of the
+/// `wanted` ones that are missing
+fn route_corrections(
+    interface: &str,
+    present: Vec<IpAddr>,
+    mut wanted: HashMap<IpAddr, Uuid>,
+) -> Vec<Correction> {
+    let mut res = Vec::new();
+    for addr in present {
+        if wanted.remove(&addr).is_none() {
+            res.push(Correction::RouteRemoved {
+                interface: interface.to_owned(),
+                addr,
+            });
+        }
+    }
+    let mut missing: Vec<_> = wanted.into_iter().collect();
+    missing.sort();
+    res.extend(
+        missing
+            .into_iter()
+            .map(|(addr, config)| Correction::RouteRestored { config, addr }),
+    );
+    res
+}
+
+/// Makes the rules of the exits match the exits of active configs.
+fn reconcile_rules(
+    state: &Shared,
+    configs: &[Config],
+    exits: &[Exit],
+    report: &mut ReconcileReport,
+) {
+    let present = match state.netlink.host_rules() {
+        Ok(present) => present,
+        Err(e) => {
+            warn!("listing rules failed with error: {e}");
+            report.failures += 1;
+            return;
+        }
+    };
+
+    for c in rule_corrections(present, configs, exits) {
+        let (addr, exit, enable) = match &c {
+            Correction::RuleRemoved { addr, exit, .. } => (*addr, *exit, false),
+            Correction::RuleRestored { addr, exit, .. } => (*addr, *exit, true),
+            _ => continue,
+        };
+        let Some(exit) = exits.iter().find(|e| e.id == exit) else {
+            continue;
+        };
+        match state.netlink.change_rule(addr, &exit.rule(), enable) {
+            Ok(()) => report.corrections.push(c),
+            Err(e) => {
+                warn!(correction = ?c, "rule change failed with error: {e}");
+                report.failures += 1;
+            }
+        }
+    }
+}
+
+/// Removals of the `present` exit rules no config wants, then restores of
+/// the rules active configs miss. Rules of inactive configs are left alone,
+/// they stay in place while the peer is out, and rules matching no exit
+/// belong to other software.
+fn rule_corrections(
+    present: Vec<(IpAddr, Rule)>,
+    configs: &[Config],
+    exits: &[Exit],
+) -> Vec<Correction> {
+    let owners: HashMap<IpAddr, &Config> = configs
+        .iter()
+        .filter(|c| !c.deleted)
+        .flat_map(|c| c.addrs().into_iter().map(move |addr| (addr, c)))
+        .collect();
+    let mut wanted: HashMap<_, _> = owners
+        .iter()
+        .filter(|(_, c)| c.active())
+        .filter_map(|(addr, c)| {
+            let exit = exits.iter().find(|e| Some(e.id) == c.exit_id)?;
+            Some(((*addr, exit.rule()), (c.id, exit.id)))
+        })
+        .collect();
+
+    let mut res = Vec::new();
+    for (addr, rule) in present {
+        let Some(exit) = exits.iter().find(|e| e.rule() == rule) else {
+            continue;
+        };
+        if wanted.remove(&(addr, rule)).is_some() {
+            continue;
+        }
+        let config = owners.get(&addr);
+        if config.is_some_and(|c| !c.active() && c.exit_id == Some(exit.id)) {
+            continue;
+        }
+        res.push(Correction::RuleRemoved {
+            config: config.map(|c| c.id),
+            addr,
+            exit: exit.id,
+        });
+    }
+    let mut missing: Vec<_> = wanted
+        .into_iter()
+        .map(|((addr, _), (config, exit))| (addr, config, exit))
+        .collect();
+    missing.sort();
+    res.extend(
+        missing
+            .into_iter()
+            .map(|(addr, config, exit)| Correction::RuleRestored { config, addr, exit }),
+    );
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn config(n: u128, exit: Option<&Exit>) -> Config {
+        Config {
+            id: Uuid::from_u128(n),
+            user_id: Uuid::from_u128(0),
+            ip: Ipv4Addr::new(10, 0, 0, n as u8),
+            ip6: None,
+            pub_key: [n as u8; 32],
+            priv_key: None,
+            psk: None,
+            name: format!("config{n}"),
+            deleted: false,
+            profile_id: None,
+            exit_id: exit.map(|e| e.id),
+            interface: "wg0".to_owned(),
+            suspended: false,
+            expires_at: None,
+            expiry_warned: false,
+            expired: false,
+            disabled: false,
+        }
+    }
+
+    fn exit(n: u128) -> Exit {
+        Exit {
+            id: Uuid::from_u128(100 + n),
+            name: format!("exit{n}"),
+            table: 100 + n as u32,
+            fwmark: None,
+            priority: 1000,
+        }
+    }
+
+    fn addr(n: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(10, 0, 0, n))
+    }
+
+    #[test]
+    fn routes_removes_stale_and_restores_missing() {
+        let wanted = HashMap::from([(addr(1), Uuid::from_u128(1)), (addr(2), Uuid::from_u128(2))]);
+        let res = route_corrections("wg0", vec![addr(1), addr(9)], wanted);
+        assert_eq!(
+            res,
+            [
+                Correction::RouteRemoved {
+                    interface: "wg0".to_owned(),
+                    addr: addr(9),
+                },
+                Correction::RouteRestored {
+                    config: Uuid::from_u128(2),
+                    addr: addr(2),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn routes_in_sync() {
+        let wanted = HashMap::from([(addr(1), Uuid::from_u128(1))]);
+        assert_eq!(route_corrections("wg0", vec![addr(1)], wanted), []);
+    }
+
+    #[test]
+    fn rules_ignore_foreign_tables() {
+        let exits = [exit(1)];
+        let foreign = Rule {
+            table: 42,
+            fwmark: None,
+            priority: 1000,
+        };
+        assert_eq!(rule_corrections(vec![(addr(1), foreign)], &[], &exits), []);
+    }
+
+    #[test]
+    fn rules_keep_those_of_inactive_configs() {
+        let exits = [exit(1)];
+        let mut c = config(1, Some(&exits[0]));
+        c.suspended = true;
+        let present = vec![(addr(1), exits[0].rule())];
+        assert_eq!(rule_corrections(present, &[c], &exits), []);
+    }
+
+    #[test]
+    fn rules_switch_to_the_current_exit() {
+        let exits = [exit(1), exit(2)];
+        let c = config(1, Some(&exits[1]));
+        let present = vec![(addr(1), exits[0].rule())];
+        assert_eq!(
+            rule_corrections(present, &[c], &exits),
+            [
+                Correction::RuleRemoved {
+                    config: Some(Uuid::from_u128(1)),
+                    addr: addr(1),
+                    exit: exits[0].id,
+                },
+                Correction::RuleRestored {
+                    config: Uuid::from_u128(1),
+                    addr: addr(1),
+                    exit: exits[1].id,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rules_of_deleted_configs_are_removed() {
+        let exits = [exit(1)];
+        let mut c = config(1, Some(&exits[0]));
+        c.deleted = true;
+        let present = vec![(addr(1), exits[0].rule())];
+        assert_eq!(
+            rule_corrections(present, &[c], &exits),
+            [Correction::RuleRemoved {
+                config: None,
+                addr: addr(1),
+                exit: exits[0].id,
+            }]
+        );
+    }
+
+    #[test]
+    fn rules_skip_direct_configs() {
+        let exits = [exit(1)];
+        assert_eq!(rule_corrections(vec![], &[config(1, None)], &exits), []);
+    }
+}
@@ -1,26 +1,46 @@
+use time::OffsetDateTime;
 use tracing::instrument;
 use uuid::Uuid;
 
-use crate::database;
+use crate::{database, roles::Permission};
 
-use super::{ServiceError, Wgcfg};
+use super::{audit::Change, configs::decode_key, ServiceError, User, Wgcfg};
 
-impl From<database::Request> for Request {
-    fn from(r: database::Request) -> Self {
-        Self {
+impl TryFrom<database::Request> for Request {
+    type Error = ServiceError;
+
+    fn try_from(r: database::Request) -> Result<Self, Self::Error> {
+        Ok(Self {
             id: r.id,
+            user_id: r.user_id,
             telegram_id: r.telegram_id,
             status: r.status.into(),
-        }
+            name: r.name,
+            key: r.key,
+            config_id: r.config_id,
+            created_at: OffsetDateTime::from_unix_timestamp(r.created_at)
+                .map_err(|e| ServiceError::Unexpected(e.to_string()))?,
+            updated_at: OffsetDateTime::from_unix_timestamp(r.updated_at)
+                .map_err(|e| ServiceError::Unexpected(e.to_string()))?,
+        })
     }
 }
 
 pub struct Request {
     pub id: Uuid,
+    pub user_id: Uuid,
     pub telegram_id: Option<i64>,
     pub status: RequestStatus,
+    /// Name of the config created on approval
+    pub name: String,
+    /// Public key supplied by the requester
+    pub key: Option<String>,
+    pub config_id: Option<Uuid>,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RequestStatus {
     Pending,
     Approved,
@@ -28,8 +48,8 @@ pub enum RequestStatus {
     Unknown,
 }
 
-impl From<i32> for RequestStatus {
-    fn from(i: i32) -> Self {
+impl From<i64> for RequestStatus {
+    fn from(i: i64) -> Self {
         match i {
             0 => Self::Pending,
             1 => Self::Approved,
@@ -52,54 +72,183 @@ impl std::fmt::Display for RequestStatus {
 
 impl Wgcfg {
     #[instrument(skip(self))]
-    pub async fn requests_by_uid(&self, uid: i64) -> Result<Vec<Request>, ServiceError> {
-        todo!();
-        /*Ok(self
-        .database
-        .requests_by_telegram_uid(uid)
-        .await?
-        .into_iter()
-        .map(|f| f.into())
-        .collect())*/
+    pub async fn requests_by_uid(&self, user: &User) -> Result<Vec<Request>, ServiceError> {
+        self.database
+            .requests_by_uid(user.id)
+            .await?
+            .into_iter()
+            .map(Request::try_from)
+            .collect()
     }
 
+    /// Pending requests
     #[instrument(skip(self))]
-    pub async fn requests(&self) -> Result<Vec<Request>, ServiceError> {
-        todo!();
-        /*
-        Ok(self
-            .database
-            .requests()
+    pub async fn requests(&self, user: &User) -> Result<Vec<Request>, ServiceError> {
+        if !user.can(Permission::RequestsApprove) {
+            return Err(ServiceError::AccessDenied);
+        }
+        self.database
+            .requests(RequestStatus::Pending as i64)
             .await?
             .into_iter()
-            .map(|f| f.into())
-            .collect())*/
+            .map(Request::try_from)
+            .collect()
     }
 
     #[instrument(skip(self))]
-    pub async fn approve_request(&self, id: Uuid) -> Result<(), ServiceError> {
-        todo!()
-        /*
-        Ok(self
-            .database
-            .update_request_status(id, RequestStatus::Approved as i32)
-            .await?)
-            */
+    pub async fn request(&self, user: &User, id: Uuid) -> Result<Request, ServiceError> {
+        let Some(request) = self.database.request(id).await? else {
+            return Err(ServiceError::NotFound);
+        };
+        if !user.can(Permission::RequestsApprove) && request.user_id != user.id {
+            return Err(ServiceError::AccessDenied);
+        }
+        request.try_into()
+    }
+
+    /// Creates the config through [`Wgcfg::new_config`] on behalf of the requester.
+    #[instrument(skip(self))]
+    pub async fn approve_request(&self, user: &User, id: Uuid) -> Result<Request, ServiceError> {
+        let request = self.request(user, id).await?;
+        // claim the request first, so it can't be approved twice
+        self.set_request_status(
+            user,
+            id,
+            RequestStatus::Pending,
+            RequestStatus::Approved,
+            None,
+        )
+        .await?;
+
+        let config_id = match self.user_by_id(request.user_id).await {
+            Ok(requester) => {
+                self.add_config(
+                    &requester,
+                    request.name.clone(),
+                    request.key.clone(),
+                    true,
+                    None,
+                    None,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        let config_id = match config_id {
+            Ok(config_id) => config_id,
+            Err(e) => {
+                self.set_request_status(
+                    user,
+                    id,
+                    RequestStatus::Approved,
+                    RequestStatus::Pending,
+                    None,
+                )
+                .await?;
+                return Err(e);
+            }
+        };
+        self.set_request_status(
+            user,
+            id,
+            RequestStatus::Approved,
+            RequestStatus::Approved,
+            Some(config_id),
+        )
+        .await?;
+        let change = Change::new("request.approve")
+            .user(request.user_id)
+            .config(config_id)
+            .after(&request.name);
+        self.audit(Some(user), change).await;
+
+        self.request(user, id).await
     }
 
     #[instrument(skip(self))]
-    pub async fn request_config(&self, id: i64) -> Result<(), ServiceError> {
-        todo!()
+    pub async fn request_config(
+        &self,
+        user: &User,
+        name: String,
+        key: Option<String>,
+    ) -> Result<Request, ServiceError> {
+        if let Some(k) = &key {
+            decode_key(k)?;
+        }
+        let pending = self
+            .database
+            .requests_by_uid(user.id)
+            .await?
+            .into_iter()
+            .any(|r| r.status == RequestStatus::Pending as i64);
+        if pending {
+            return Err(ServiceError::RequestPending);
+        }
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let id = Uuid::new_v4();
+        self.database
+            .add_request(database::Request {
+                id,
+                user_id: user.id,
+                telegram_id: None,
+                status: RequestStatus::Pending as i64,
+                name,
+                key,
+                config_id: None,
+                created_at: now,
+                updated_at: now,
+            })
+            .await?;
+        self.audit(Some(user), Change::new("request.create").user(user.id))
+            .await;
+
+        self.request(user, id).await
     }
 
     #[instrument(skip(self))]
-    pub async fn decline_request(&self, id: Uuid) -> Result<(), ServiceError> {
-        todo!()
-        /*
-        Ok(self
+    pub async fn decline_request(&self, user: &User, id: Uuid) -> Result<Request, ServiceError> {
+        self.set_request_status(
+            user,
+            id,
+            RequestStatus::Pending,
+            RequestStatus::Declined,
+            None,
+        )
+        .await?;
+
+        let request = self.request(user, id).await?;
+        let change = Change::new("request.decline")
+            .user(request.user_id)
+            .before(&request.name);
+        self.audit(Some(user), change).await;
+        Ok(request)
+    }
+
+    async fn set_request_status(
+        &self,
+        user: &User,
+        id: Uuid,
+        from: RequestStatus,
+        to: RequestStatus,
+        config_id: Option<Uuid>,
+    ) -> Result<(), ServiceError> {
+        if !user.can(Permission::RequestsApprove) {
+            return Err(ServiceError::AccessDenied);
+        }
+        let updated = self
             .database
-            .update_request_status(id, RequestStatus::Declined as i32)
-            .await?)
-        */
+            .update_request(
+                id,
+                from as i64,
+                to as i64,
+                config_id,
+                OffsetDateTime::now_utc().unix_timestamp(),
+            )
+            .await?;
+        if !updated {
+            return Err(ServiceError::RequestProcessed);
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,136 @@
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::roles::{self, Permission, Role};
+
+use super::{audit::Change, ServiceError, User, Wgcfg};
+
+impl Wgcfg {
+    #[instrument(skip(self))]
+    pub async fn roles(&self, user: &User) -> Result<Vec<Role>, ServiceError> {
+        if !user.can(Permission::AdminsManage) {
+            return Err(ServiceError::AccessDenied);
+        }
+        Ok(self.database.roles().await?)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn role(&self, user: &User, id: Uuid) -> Result<Role, ServiceError> {
+        self.roles(user)
+            .await?
+            .into_iter()
+            .find(|r| r.id == id)
+            .ok_or(ServiceError::NotFound)
+    }
+
+    /// Creates a role from `name [permission,...]`
+    #[instrument(skip(self))]
+    pub async fn create_role(&self, user: &User, spec: &str) -> Result<Uuid, ServiceError> {
+        if !user.can(Permission::AdminsManage) {
+            return Err(ServiceError::AccessDenied);
+        }
+        let invalid = || ServiceError::InvalidRole(spec.to_owned());
+        let mut parts = spec.split_whitespace();
+        let name = parts.next().ok_or_else(invalid)?;
+        let permissions = match parts.next() {
+            Some(list) => list
+                .split(',')
+                .map(|p| p.parse().map_err(|_| invalid()))
+                .collect::<Result<Vec<Permission>, _>>()?,
+            None => Vec::new(),
+        };
+        if parts.next().is_some() || self.database.roles().await?.iter().any(|r| r.name == name) {
+            return Err(invalid());
+        }
+
+        let role = Role {
+            id: Uuid::new_v4(),
+            name: name.to_owned(),
+            permissions,
+        };
+        self.database.add_role(&role).await?;
+        self.audit(Some(user), Change::new("role.create").after(spec))
+            .await;
+        Ok(role.id)
+    }
+
+    /// Grants or revokes `permission` of a role, the admin role stays as it is
+    #[instrument(skip(self))]
+    pub async fn set_role_permission(
+        &self,
+        user: &User,
+        role_id: Uuid,
+        permission: Permission,
+        granted: bool,
+    ) -> Result<(), ServiceError> {
+        if !user.can(Permission::AdminsManage) {
+            return Err(ServiceError::AccessDenied);
+        }
+        if role_id == roles::ADMIN {
+            return Err(ServiceError::InvalidRole("admin".to_owned()));
+        }
+        let role = self.role(user, role_id).await?;
+        self.database
+            .set_role_permission(role_id, permission, granted)
+            .await?;
+        let action = if granted { "role.grant" } else { "role.revoke" };
+        let change = Change::new(action).before(&role.name).after(permission);
+        self.audit(Some(user), change).await;
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    pub async fn rm_role(&self, user: &User, role_id: Uuid) -> Result<(), ServiceError> {
+        if !user.can(Permission::AdminsManage) {
+            return Err(ServiceError::AccessDenied);
+        }
+        if role_id == roles::ADMIN {
+            return Err(ServiceError::InvalidRole("admin".to_owned()));
+        }
+        let role = self.role(user, role_id).await?;
+        self.database.rm_role(role_id).await?;
+        self.audit(Some(user), Change::new("role.remove").before(&role.name))
+            .await;
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    pub async fn assign_role(
+        &self,
+        user: &User,
+        user_id: Uuid,
+        role_id: Uuid,
+    ) -> Result<(), ServiceError> {
+        if !user.can(Permission::AdminsManage) {
+            return Err(ServiceError::AccessDenied);
+        }
+        let role = self.role(user, role_id).await?;
+        if !self.database.user_roles(user_id).await?.contains(&role_id) {
+            self.database.add_user_role(user_id, role_id).await?;
+            let change = Change::new("role.assign").user(user_id).after(&role.name);
+            self.audit(Some(user), change).await;
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    pub async fn unassign_role(
+        &self,
+        user: &User,
+        user_id: Uuid,
+        role_id: Uuid,
+    ) -> Result<(), ServiceError> {
+        if !user.can(Permission::AdminsManage) {
+            return Err(ServiceError::AccessDenied);
+        }
+        let role = self.role(user, role_id).await?;
+        if self.database.user_roles(user_id).await?.contains(&role_id) {
+            self.database.rm_user_role(user_id, role_id).await?;
+            let change = Change::new("role.unassign")
+                .user(user_id)
+                .before(&role.name);
+            self.audit(Some(user), change).await;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+};
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::{
+    database::{ConfigStats, Period, TrafficPoint, UserSummary},
+    roles::Permission,
+};
+
+use super::{ServiceError, User, Wgcfg};
+
+/// Time range of the traffic leaderboard, days are calendar days in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TrafficWindow {
+    Today,
+    #[default]
+    Week,
+    Month,
+    AllTime,
+}
+
+impl TrafficWindow {
+    pub const ALL: [TrafficWindow; 4] = [
+        TrafficWindow::Today,
+        TrafficWindow::Week,
+        TrafficWindow::Month,
+        TrafficWindow::AllTime,
+    ];
+
+    /// Beginning of the window, `None` for the lifetime counters
+    pub fn start(self, now: OffsetDateTime) -> Option<i64> {
+        let today = Period::Day.start(now);
+        let day = time::Duration::DAY.whole_seconds();
+        match self {
+            TrafficWindow::Today => Some(today),
+            TrafficWindow::Week => Some(today - 6 * day),
+            TrafficWindow::Month => Some(today - 29 * day),
+            TrafficWindow::AllTime => None,
+        }
+    }
+}
+
+impl std::fmt::Display for TrafficWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TrafficWindow::Today => "today",
+            TrafficWindow::Week => "7 days",
+            TrafficWindow::Month => "30 days",
+            TrafficWindow::AllTime => "all time",
+        })
+    }
+}
+
+/// Order of the traffic leaderboard, the largest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TrafficSort {
+    #[default]
+    Total,
+    Tx,
+    Rx,
+}
+
+impl TrafficSort {
+    pub const ALL: [TrafficSort; 3] = [TrafficSort::Total, TrafficSort::Tx, TrafficSort::Rx];
+
+    pub fn key(self, tx: u64, rx: u64) -> u64 {
+        match self {
+            TrafficSort::Total => tx + rx,
+            TrafficSort::Tx => tx,
+            TrafficSort::Rx => rx,
+        }
+    }
+}
+
+impl std::fmt::Display for TrafficSort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TrafficSort::Total => "total",
+            TrafficSort::Tx => "tx",
+            TrafficSort::Rx => "rx",
+        })
+    }
+}
+
+impl Wgcfg {
+    /// [`Wgcfg::stats`] summed per user, `tx` and `rx` of the summaries
+    /// cover `window`
+    #[instrument(skip(self))]
+    pub async fn user_stats(
+        &self,
+        user: &User,
+        window: TrafficWindow,
+    ) -> Result<Vec<UserSummary>, ServiceError> {
+        let mut traffic: HashMap<Uuid, (u64, u64)> = HashMap::new();
+        for p in self.stats(user, window).await? {
+            let t = traffic.entry(p.user_id).or_default();
+            t.0 += p.tx;
+            t.1 += p.rx;
+        }
+        let mut res = self.database.users().await?;
+        for u in &mut res {
+            (u.tx, u.rx) = traffic.remove(&u.id).unwrap_or_default();
+        }
+        res.sort_by_key(|u| Reverse(u.tx + u.rx));
+        Ok(res)
+    }
+
+    /// Counters of all configs, unchecked as it is meant for monitoring
+    #[instrument(skip(self))]
+    pub async fn config_stats(&self) -> Result<Vec<ConfigStats>, ServiceError> {
+        Ok(self.database.stats().await?)
+    }
+
+    /// Number of configs per [`Config::state`](super::configs::Config::state)
+    #[instrument(skip(self))]
+    pub async fn config_states(&self) -> Result<BTreeMap<&'static str, usize>, ServiceError> {
+        let mut res = BTreeMap::new();
+        for c in self.database.configs().await? {
+            *res.entry(c.state()).or_default() += 1;
+        }
+        Ok(res)
+    }
+
+    /// Traffic of a config in buckets of `period` that begin in `[from, to)`
+    #[instrument(skip(self))]
+    pub async fn config_traffic(
+        &self,
+        user: &User,
+        config_id: Uuid,
+        period: Period,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+    ) -> Result<Vec<TrafficPoint>, ServiceError> {
+        let Some(config) = self.database.config(config_id).await? else {
+            return Err(ServiceError::NotFound);
+        };
+        if config.user_id != user.id && !user.can(Permission::StatsViewAll) {
+            return Err(ServiceError::AccessDenied);
+        }
+        Ok(self
+            .database
+            .config_traffic(
+                config_id,
+                period,
+                from.unix_timestamp(),
+                to.unix_timestamp(),
+            )
+            .await?)
+    }
+
+    /// Traffic of all configs of `user_id` in buckets of `period`
+    #[instrument(skip(self))]
+    pub async fn user_traffic(
+        &self,
+        user: &User,
+        user_id: Uuid,
+        period: Period,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+    ) -> Result<Vec<TrafficPoint>, ServiceError> {
+        if user_id != user.id && !user.can(Permission::StatsViewAll) {
+            return Err(ServiceError::AccessDenied);
+        }
+        Ok(self
+            .database
+            .user_traffic(user_id, period, from.unix_timestamp(), to.unix_timestamp())
+            .await?)
+    }
+}
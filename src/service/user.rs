@@ -1,11 +1,20 @@
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr};
 
+use jwt::{SignWithKey, VerifyWithKey};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
 use tracing::instrument;
 use uuid::Uuid;
 
-use crate::roles;
+use crate::{
+    database::UserSummary,
+    roles::{self, Permission},
+};
 
-use super::{ServiceError, Wgcfg};
+use super::{
+    audit::{Change, Frontend},
+    ServiceError, Wgcfg,
+};
 
 #[derive(Debug)]
 pub enum Association {
@@ -21,24 +30,38 @@ pub struct ClientInfo {
 pub struct User {
     pub id: Uuid,
     pub roles: Vec<Uuid>,
+    /// Union of the permissions of `roles`
+    pub permissions: Vec<Permission>,
+    /// Where the user acts from, recorded in the audit log
+    pub frontend: Frontend,
 }
 
 impl User {
-    pub fn is_admin(&self) -> bool {
-        self.roles.contains(&crate::roles::ADMIN)
+    pub fn can(&self, permission: Permission) -> bool {
+        self.permissions.contains(&permission)
     }
 }
 
+/// Claims of a pair code, `user_id` is the owner of the config at the time
+/// the code was issued.
+#[derive(Debug, Serialize, Deserialize)]
+struct PairCode {
+    id: Uuid,
+    config_id: Uuid,
+    user_id: Uuid,
+    exp: i64,
+}
+
 impl Wgcfg {
+    /// Whether the config with `ip` is owned by or paired with the user
     #[instrument(skip(self))]
     pub async fn association_exists(
         &self,
         ip: Ipv4Addr,
         s: Association,
     ) -> Result<bool, ServiceError> {
-        match s {
-            Association::Telegram(uid) => Ok(self.database.is_paired(uid, ip).await?),
-        }
+        let uid = self.database.user_id(s).await?;
+        Ok(self.database.is_paired(uid, ip).await?)
     }
 
     #[instrument(skip(self))]
@@ -47,40 +70,98 @@ impl Wgcfg {
         ip: Ipv4Addr,
         s: Association,
     ) -> Result<(), ServiceError> {
-        todo!();
-        /*
-        match s {
-            Association::Telegram(uid) => Ok(self.database.rm_pair(uid, ip).await?),
-        }*/
+        let user = self.user(s).await?;
+        if !self.database.rm_pair(user.id, ip).await? {
+            return Err(ServiceError::NotFound);
+        }
+        let change = Change::new("pair.remove").user(user.id).before(ip);
+        self.audit(Some(&user), change).await;
+        Ok(())
     }
 
-    #[instrument(skip(self))]
+    /// Redeems a code from [`Wgcfg::pair_code`], every code works only once
+    #[instrument(skip(self, pair_code))]
     pub async fn create_association(
         &self,
         pair_code: String,
         s: Association,
     ) -> Result<(), ServiceError> {
-        todo!();
-        /*
-        let data: Ipv4Addr = pair_code.verify_with_key(&self.hmac_key)?;
-        match s {
-            Association::Telegram(uid) => Ok(self.database.add_pair(uid, data).await?),
+        let claims: PairCode = pair_code
+            .verify_with_key(&self.hmac_key)
+            .map_err(|_| ServiceError::InvalidPairCode)?;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        if claims.exp < now {
+            return Err(ServiceError::InvalidPairCode);
         }
-        */
+        match self.database.config(claims.config_id).await? {
+            Some(c) if !c.deleted && c.user_id == claims.user_id => {}
+            _ => return Err(ServiceError::InvalidPairCode),
+        }
+
+        let user = self.user(s).await?;
+        if !self.database.use_pair_code(claims.id, now).await? {
+            return Err(ServiceError::InvalidPairCode);
+        }
+        self.database
+            .add_pair(claims.config_id, user.id, now)
+            .await?;
+        let change = Change::new("pair.create")
+            .user(user.id)
+            .config(claims.config_id);
+        self.audit(Some(&user), change).await;
+        Ok(())
     }
 
     #[instrument(skip(self))]
     pub async fn associations(&self, s: Association) -> Result<Vec<ClientInfo>, ServiceError> {
-        Ok(vec![])
-        /*match s {
-            Association::Telegram(uid) => Ok(self
-                .database
-                .pairs(uid)
-                .await?
-                .into_iter()
-                .map(|c| ClientInfo { ip: c.1, name: c.0 })
-                .collect()),
-        }*/
+        let uid = self.database.user_id(s).await?;
+        Ok(self
+            .database
+            .pairs(uid)
+            .await?
+            .into_iter()
+            .map(|c| ClientInfo {
+                ip: c.ip,
+                name: Some(c.name),
+            })
+            .collect())
+    }
+
+    /// Issues a single-use code that pairs the config with whoever redeems it
+    #[instrument(skip(self))]
+    pub async fn pair_code(&self, user: &User, config_id: Uuid) -> Result<String, ServiceError> {
+        let Some(config) = self.database.config(config_id).await? else {
+            return Err(ServiceError::NotFound);
+        };
+        if config.deleted {
+            return Err(ServiceError::NotFound);
+        }
+        if !user.can(Permission::ConfigsManageAll) && user.id != config.user_id {
+            return Err(ServiceError::AccessDenied);
+        }
+
+        let claims = PairCode {
+            id: Uuid::new_v4(),
+            config_id,
+            user_id: config.user_id,
+            exp: (OffsetDateTime::now_utc() + self.pair_code_ttl).unix_timestamp(),
+        };
+        self.database
+            .add_pair_code(claims.id, config_id, config.user_id, claims.exp)
+            .await?;
+        self.audit(Some(user), Change::of_config("config.pair_code", &config))
+            .await;
+        Ok(claims.sign_with_key(&self.hmac_key)?)
+    }
+
+    /// Same as [`Wgcfg::pair_code`] on behalf of the owner of the config with `ip`
+    #[instrument(skip(self))]
+    pub async fn pair_code_by_addr(&self, ip: IpAddr) -> Result<String, ServiceError> {
+        let Some(config) = self.database.config_by_addr(ip).await? else {
+            return Err(ServiceError::NotFound);
+        };
+        let owner = self.user_by_id(config.user_id).await?;
+        self.pair_code(&owner, config.id).await
     }
 
     #[instrument(skip(self))]
@@ -90,66 +171,89 @@ impl Wgcfg {
         config_id: Uuid,
         name: &str,
     ) -> Result<(), ServiceError> {
+        // update_config writes the exit too, don't race apply_exit
+        let _state = self.shared.lock().await;
         let config = self.database.config(config_id).await?;
         let Some(mut config) = config else {
             return Err(ServiceError::NotFound);
         };
-        if !user.is_admin() && user.id != config.user_id {
+        if !user.can(Permission::ConfigsManageAll) && user.id != config.user_id {
             return Err(ServiceError::NotFound);
         }
+        let change = Change::of_config("config.rename", &config)
+            .before(&config.name)
+            .after(name);
         config.name.clear();
         config.name.push_str(name);
         self.database.update_config(config).await?;
+        self.audit(Some(user), change).await;
 
         Ok(())
     }
 
     #[instrument(skip(self))]
     pub async fn user(&self, assoc: Association) -> Result<User, ServiceError> {
+        let frontend = match assoc {
+            Association::Telegram(_) => Frontend::Telegram,
+        };
         let uid = self.database.user_id(assoc).await?;
-        let roles = self.database.user_roles(uid).await?;
-        Ok(User { id: uid, roles })
-        //Ok(self.database.rename_client(ip, name).await?)
+        Ok(User {
+            frontend,
+            ..self.user_by_id(uid).await?
+        })
     }
 
     #[instrument(skip(self))]
-    pub async fn rm_admin(&self, user: &User, user_id: Uuid) -> Result<(), ServiceError> {
-        if !user.is_admin() {
+    pub async fn user_by_id(&self, id: Uuid) -> Result<User, ServiceError> {
+        let roles = self.database.user_roles(id).await?;
+        let permissions = self.database.user_permissions(id).await?;
+        Ok(User {
+            id,
+            roles,
+            permissions,
+            frontend: Frontend::System,
+        })
+    }
+
+    /// Everyone with an account, see [`UserSummary`]
+    #[instrument(skip(self))]
+    pub async fn users(&self, user: &User) -> Result<Vec<UserSummary>, ServiceError> {
+        if !user.can(Permission::AdminsManage) {
             return Err(ServiceError::AccessDenied);
         }
-        self.database.rm_user_role(user_id, roles::ADMIN).await?;
+        Ok(self.database.users().await?)
+    }
 
-        Ok(())
+    #[instrument(skip(self))]
+    pub async fn user_summary(&self, user: &User, id: Uuid) -> Result<UserSummary, ServiceError> {
+        self.users(user)
+            .await?
+            .into_iter()
+            .find(|u| u.id == id)
+            .ok_or(ServiceError::NotFound)
     }
 
     #[instrument(skip(self))]
-    pub async fn add_admin(&self, user: &User, user_id: Uuid) -> Result<(), ServiceError> {
-        if !user.is_admin() {
-            return Err(ServiceError::AccessDenied);
-        }
-        self.database.add_user_role(user_id, roles::ADMIN).await?;
+    pub async fn telegram_id(&self, user_id: Uuid) -> Result<Option<i64>, ServiceError> {
+        Ok(self.database.telegram_id(user_id).await?)
+    }
 
-        Ok(())
+    /// Telegram chats of everyone who may approve requests
+    #[instrument(skip(self))]
+    pub async fn admin_telegram_ids(&self) -> Result<Vec<i64>, ServiceError> {
+        Ok(self
+            .database
+            .telegram_ids_with_permission(Permission::RequestsApprove)
+            .await?)
     }
 
     #[instrument(skip(self))]
-    pub async fn change_settings(
-        &self,
-        addr: Ipv4Addr,
-        double_vpn: bool,
-    ) -> Result<(), ServiceError> {
-        todo!()
-        /*
-        self.database
-            .update_peer_settings(addr, PeerSettings { double_vpn })
-            .await?;
+    pub async fn rm_admin(&self, user: &User, user_id: Uuid) -> Result<(), ServiceError> {
+        self.unassign_role(user, user_id, roles::ADMIN).await
+    }
 
-        self.shared
-            .lock()
-            .await
-            .netlink
-            .change_rule(addr, self.dvpn_table, double_vpn)?;
-        Ok(())
-        */
+    #[instrument(skip(self))]
+    pub async fn add_admin(&self, user: &User, user_id: Uuid) -> Result<(), ServiceError> {
+        self.assign_role(user, user_id, roles::ADMIN).await
     }
 }
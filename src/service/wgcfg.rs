@@ -1,22 +1,24 @@
-use std::{
-    array::TryFromSliceError,
-    net::{IpAddr, Ipv4Addr, SocketAddr},
-};
+use std::{array::TryFromSliceError, cmp::Reverse, net::SocketAddr};
 
 use cidr::IpCidr;
-use jwt::SignWithKey;
 use thiserror::Error;
-use tracing::{instrument, warn};
+use time::OffsetDateTime;
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
 
-pub use super::ConfigInfo;
-use super::Wgcfg;
-pub use super::{Request, RequestStatus};
+use super::{
+    exits::{self, sync_rules, Exit},
+    presence::ONLINE_WINDOW,
+    traffic::TrafficWindow,
+    PoolUsage, User, Wgcfg,
+};
 use crate::{
     database::DatabaseError,
     netlink::{
         error::NetlinkError,
         wireguard::{PeerUpdate, WireguardInterfaceId, WireguardUpdate},
     },
+    roles::Permission,
 };
 
 #[derive(Debug, Error)]
@@ -41,6 +43,30 @@ pub enum ServiceError {
     NotFound,
     #[error("access denied")]
     AccessDenied,
+    #[error("invalid profile: {0}")]
+    InvalidProfile(String),
+    #[error("you already have a pending request")]
+    RequestPending,
+    #[error("request is already processed")]
+    RequestProcessed,
+    #[error("pair code is invalid, expired or already used")]
+    InvalidPairCode,
+    #[error("invalid exit: {0}")]
+    InvalidExit(String),
+    #[error("unknown interface {0}")]
+    UnknownInterface(String),
+    #[error("invalid quota {0}, expected <GB> [day|month]")]
+    InvalidQuota(String),
+    #[error("missing, invalid or expired token")]
+    InvalidToken,
+    #[error("invalid scope {0}, expected read, configs:write or admin")]
+    InvalidScope(String),
+    #[error("invalid role {0}")]
+    InvalidRole(String),
+    #[error("invalid expiry {0}")]
+    InvalidExpiry(i64),
+    #[error("invalid query {0}")]
+    InvalidQuery(String),
 }
 
 impl From<TryFromSliceError> for ServiceError {
@@ -52,89 +78,204 @@ impl From<TryFromSliceError> for ServiceError {
 pub struct ServerInfo {
     pub addr: SocketAddr,
     pub pub_key: String,
+    pub ranges: Vec<IpCidr>,
 }
 
+/// Traffic of a config over a [`TrafficWindow`]
 pub struct PeerInfo {
+    pub config_id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub tx: u64,
+    pub rx: u64,
+}
+
+/// What the server screen shows about an interface
+pub struct InterfaceOverview {
+    pub name: String,
+    pub pub_key: String,
+    pub endpoint: SocketAddr,
+    pub listen_port: u16,
+    /// Peers in the kernel, suspended and expired configs are not there
+    pub peers: usize,
+    /// Peers with a handshake within [`ONLINE_WINDOW`]
+    pub online: usize,
+    pub pool: PoolUsage,
+    pub pool6: Option<PoolUsage>,
+    /// Lifetime traffic of the configs on the interface
     pub tx: u64,
     pub rx: u64,
 }
 
 impl Wgcfg {
     #[instrument(skip(self))]
-    pub async fn server_info(&self) -> Result<ServerInfo, ServiceError> {
+    pub async fn server_info(&self, interface: &str) -> Result<ServerInfo, ServiceError> {
+        let iface = self.interface(interface)?;
         Ok(ServerInfo {
-            addr: self.endpoint,
-            pub_key: self.pub_key.clone(),
+            addr: iface.endpoint,
+            pub_key: iface.pub_key.clone(),
+            ranges: iface.ranges.clone(),
         })
     }
 
     #[instrument(skip(self))]
     pub async fn init(&self) -> Result<(), ServiceError> {
+        if let Some(table) = self.dvpn_table {
+            let exit = Exit {
+                id: Uuid::new_v4(),
+                name: "double".to_owned(),
+                table,
+                fwmark: None,
+                priority: exits::DEFAULT_PRIORITY,
+            };
+            let moved = self.database.import_double_vpn(&exit).await?;
+            if moved > 0 {
+                info!("moved {moved} double vpn configs to exit {}", exit.name);
+            }
+        } else {
+            let left = self.database.double_vpn_configs().await?;
+            if left > 0 {
+                warn!(
+                    "{left} configs are in double vpn mode, they go direct until DVPN_TABLE is set"
+                );
+            }
+        }
+
+        let primary = &self.primary_interface().name;
+        let adopted = self.database.adopt_configs(primary).await?;
+        if adopted > 0 {
+            info!("assigned {adopted} configs to interface {primary}");
+        }
+        let filled = self.backfill_addr6().await?;
+        if filled > 0 {
+            info!("assigned ipv6 addresses to {filled} configs");
+        }
+
+        let exits = self.database.exits().await?;
         let peers = self.database.configs().await?;
-        let mut mapped_peers = Vec::with_capacity(peers.len());
 
-        for p in peers.into_iter().filter(|a| !a.deleted) {
-            mapped_peers.push(PeerUpdate {
-                allowed_ips: Some(vec![IpCidr::new_host(IpAddr::V4(p.ip))]),
-                public_key: Some(p.pub_key),
-                remove: false,
-            });
+        for iface in self.interfaces.iter() {
+            let mut mapped_peers = Vec::new();
+            for p in peers
+                .iter()
+                .filter(|a| a.active() && a.interface == iface.name)
+            {
+                mapped_peers.push(PeerUpdate {
+                    allowed_ips: Some(p.allowed_ips()),
+                    public_key: Some(p.pub_key),
+                    preshared_key: p.psk,
+                    replace_allowed_ips: false,
+                    remove: false,
+                });
+
+                for ip in p.addrs() {
+                    if let Err(e) = self
+                        .shared
+                        .lock()
+                        .await
+                        .netlink
+                        .add_ip_route(ip, iface.index)
+                    {
+                        warn!("restore route for {ip} failed with error: {e}")
+                    }
+                    let errors =
+                        sync_rules(&self.shared.lock().await.netlink, ip, p.exit_id, &exits);
+                    for e in errors {
+                        warn!("restore rule for {ip} failed with error: {e}")
+                    }
+                }
+            }
 
-            if let Err(e) = self
-                .shared
+            self.shared
                 .lock()
                 .await
                 .netlink
-                .add_ip_route(p.ip, self.iface)
-            {
-                warn!("restore route for {ip} failed with error: {e}", ip = p.ip)
-            }
-            if let Err(e) =
-                self.shared
-                    .lock()
-                    .await
-                    .netlink
-                    .change_rule(p.ip, self.dvpn_table, false)
-            {
-                warn!("restore rule for {ip} failed with error: {e}", ip = p.ip)
-            }
+                .wireguard_update(
+                    WireguardInterfaceId::Index(iface.index),
+                    WireguardUpdate {
+                        peers: mapped_peers,
+                        replace_peers: true,
+                    },
+                )
+                .await?;
         }
 
-        let pos = self.database.configs_count().await?;
-
-        let mut shared = self.shared.lock().await;
-        for _ in 0..pos {
-            shared.range.next();
+        for (name, usage, usage6) in self.pool_usage().await? {
+            info!("ip pool usage of {name}: {usage}, ipv6: {usage6:?}");
         }
-
-        let wg = &mut shared.netlink;
-        Ok(wg
-            .wireguard_update(
-                WireguardInterfaceId::Index(self.iface),
-                WireguardUpdate {
-                    peers: mapped_peers,
-                    replace_peers: true,
-                },
-            )
-            .await?)
+        Ok(())
     }
 
+    /// Traffic of every config within `window`, the busiest first
     #[instrument(skip(self))]
-    pub async fn pair_code(&self, ip: Ipv4Addr) -> Result<String, ServiceError> {
-        Ok(ip.sign_with_key(&self.hmac_key)?)
+    pub async fn stats(
+        &self,
+        user: &User,
+        window: TrafficWindow,
+    ) -> Result<Vec<PeerInfo>, ServiceError> {
+        if !user.can(Permission::StatsViewAll) {
+            return Err(ServiceError::AccessDenied);
+        }
+        let stats = match window.start(OffsetDateTime::now_utc()) {
+            Some(from) => self.database.stats_since(from).await?,
+            None => self.database.stats().await?,
+        };
+        let mut res = stats
+            .into_iter()
+            .map(|p| PeerInfo {
+                config_id: p.config_id,
+                user_id: p.user_id,
+                name: p.name,
+                tx: p.tx,
+                rx: p.rx,
+            })
+            .collect::<Vec<_>>();
+        res.sort_by_key(|p| Reverse(p.tx + p.rx));
+        Ok(res)
     }
 
+    /// State of every interface as the kernel sees it, see [`InterfaceOverview`]
     #[instrument(skip(self))]
-    pub async fn stats(&self) -> Result<Vec<PeerInfo>, ServiceError> {
-        todo!()
-        /*let mut res = self
-            .database
-            .stats()
-            .await?
-            .into_iter()
-            .map(|p| PeerInfo { tx: p.tx, rx: p.rx })
-            .collect::<Vec<_>>();
-        res.sort_by(|a, b| (b.rx + b.tx).cmp(&(a.rx + a.tx)));
-        Ok(res)*/
+    pub async fn server_overview(
+        &self,
+        user: &User,
+    ) -> Result<Vec<InterfaceOverview>, ServiceError> {
+        if !user.can(Permission::StatsViewAll) {
+            return Err(ServiceError::AccessDenied);
+        }
+        let stats = self.database.stats().await?;
+        let pools = self.pool_usage().await?;
+        let now = OffsetDateTime::now_utc();
+
+        let mut state = self.shared.lock().await;
+        let mut res = Vec::with_capacity(self.interfaces.len());
+        for (iface, (_, pool, pool6)) in self.interfaces.iter().zip(pools) {
+            let device = state
+                .netlink
+                .wg_interface(WireguardInterfaceId::Index(iface.index))
+                .await?;
+            let online = device
+                .peers
+                .iter()
+                .filter(|p| p.last_handshake.is_some_and(|t| now - t < ONLINE_WINDOW))
+                .count();
+            let (tx, rx) = stats
+                .iter()
+                .filter(|s| s.interface == iface.name)
+                .fold((0, 0), |(tx, rx), s| (tx + s.tx, rx + s.rx));
+            res.push(InterfaceOverview {
+                name: iface.name.clone(),
+                pub_key: iface.pub_key.clone(),
+                endpoint: iface.endpoint,
+                listen_port: device.listen_port,
+                peers: device.peers.len(),
+                online,
+                pool,
+                pool6,
+                tx,
+                rx,
+            });
+        }
+        Ok(res)
     }
 }
@@ -6,4 +6,19 @@ pub trait TelegramDb: Sync + Send {
     async fn add_admin(&self, uid: i64) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
     async fn rm_admin(&self, uid: i64) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
     async fn add_user(&self, uid: i64) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+    /// Serialized dialogue state of the chat and when it was last updated
+    async fn dialogue(
+        &self,
+        chat_id: i64,
+    ) -> Result<Option<(String, i64)>, Box<dyn std::error::Error + Send + Sync>>;
+    async fn set_dialogue(
+        &self,
+        chat_id: i64,
+        state: &str,
+        updated_at: i64,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+    async fn rm_dialogue(
+        &self,
+        chat_id: i64,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
 }
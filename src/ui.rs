@@ -7,7 +7,7 @@ pub mod web;
 
 pub fn run(
     tg: telegram::Config,
-    _web: web::Config,
+    web: web::Config,
     service: Wgcfg,
     database: Database,
 ) -> FuturesUnordered<tokio::task::JoinHandle<Result<(), Box<dyn std::error::Error + Send + Sync>>>>
@@ -15,7 +15,7 @@ pub fn run(
     let futures = FuturesUnordered::new();
 
     futures.push(tokio::spawn(telegram::start(tg, service.clone(), database)));
-    //futures.push(tokio::spawn(web::start(web, service)));
+    futures.push(tokio::spawn(web::start(web, service)));
 
     futures
 }
@@ -1,9 +1,12 @@
 use std::sync::LazyLock;
 use teloxide::types::{InlineKeyboardButton, InlineKeyboardButtonKind};
 
-use crate::service::configs::Config;
+use crate::database::{ConnectedPeer, UserSummary};
+use crate::roles::{Permission, Role};
+use crate::service::traffic::{TrafficSort, TrafficWindow};
+use crate::service::{auth::ApiToken, configs::Config, exits::Exit, profiles::Profile, Request};
 
-use super::Action;
+use super::{Action, TopView};
 
 pub static MAIN_MENU: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
     InlineKeyboardButton::new(
@@ -12,17 +15,33 @@ pub static MAIN_MENU: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
     )
 });
 
-pub static ADD_ADMIN: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
+pub static USERS: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
     InlineKeyboardButton::new(
-        "Add".to_owned(),
-        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&Action::AddAdmin).unwrap()),
+        "Users".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&Action::Users(0)).unwrap()),
     )
 });
 
-pub static ADMINS: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
+pub static FIND_USER: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
     InlineKeyboardButton::new(
-        "Admins".to_owned(),
-        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&Action::Admins).unwrap()),
+        "Find".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&Action::FindUser).unwrap()),
+    )
+});
+
+pub static AUDIT: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
+    InlineKeyboardButton::new(
+        "Audit log".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&Action::Audit).unwrap()),
+    )
+});
+
+pub static AUDIT_FILTER_USER: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
+    InlineKeyboardButton::new(
+        "Filter by user".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::AuditFilterUser).unwrap(),
+        ),
     )
 });
 
@@ -42,6 +61,110 @@ pub static CREATE_CONFIG: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
     )
 });
 
+pub static PROFILES: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
+    InlineKeyboardButton::new(
+        "Profiles".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&Action::Profiles).unwrap()),
+    )
+});
+
+pub static CREATE_PROFILE: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
+    InlineKeyboardButton::new(
+        "New".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::CreateProfile).unwrap(),
+        ),
+    )
+});
+
+pub static DEFAULT_PROFILE: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
+    InlineKeyboardButton::new(
+        "Default".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::UseProfile(None)).unwrap(),
+        ),
+    )
+});
+
+pub static EXITS: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
+    InlineKeyboardButton::new(
+        "Exits".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&Action::Exits).unwrap()),
+    )
+});
+
+pub static CONNECTED: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
+    InlineKeyboardButton::new(
+        "Connected peers".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&Action::Connected).unwrap()),
+    )
+});
+
+pub static SERVER: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
+    InlineKeyboardButton::new(
+        "Server".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&Action::Server).unwrap()),
+    )
+});
+
+pub static TOP_CONFIGS: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
+    InlineKeyboardButton::new(
+        "Top configs".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&Action::Top(false)).unwrap()),
+    )
+});
+
+pub static TOP_USERS: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
+    InlineKeyboardButton::new(
+        "Top users".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&Action::Top(true)).unwrap()),
+    )
+});
+
+pub static TOKENS: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
+    InlineKeyboardButton::new(
+        "API tokens".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&Action::Tokens).unwrap()),
+    )
+});
+
+pub static ROLES: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
+    InlineKeyboardButton::new(
+        "Roles".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&Action::Roles).unwrap()),
+    )
+});
+
+pub static CREATE_ROLE: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
+    InlineKeyboardButton::new(
+        "New".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&Action::CreateRole).unwrap()),
+    )
+});
+
+pub static CREATE_EXIT: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
+    InlineKeyboardButton::new(
+        "New".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&Action::CreateExit).unwrap()),
+    )
+});
+
+pub static DIRECT_EXIT: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
+    InlineKeyboardButton::new(
+        "Direct".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::UseExit(None)).unwrap(),
+        ),
+    )
+});
+
+pub static REQUESTS: LazyLock<InlineKeyboardButton> = LazyLock::new(|| {
+    InlineKeyboardButton::new(
+        "Requests".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&Action::Requests).unwrap()),
+    )
+});
+
 pub fn config(c: Config) -> InlineKeyboardButton {
     InlineKeyboardButton::new(
         c.name.to_string(),
@@ -51,6 +174,24 @@ pub fn config(c: Config) -> InlineKeyboardButton {
     )
 }
 
+pub fn connected_peer(p: &ConnectedPeer) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        format!("{} ({})", p.name, p.ip),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::Config(p.config_id)).unwrap(),
+        ),
+    )
+}
+
+pub fn token_revoke(t: &ApiToken) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        format!("Revoke {}", t.name),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::RevokeToken(t.id)).unwrap(),
+        ),
+    )
+}
+
 pub fn config_rename(c: &Config) -> InlineKeyboardButton {
     InlineKeyboardButton::new(
         "Rename".to_owned(),
@@ -77,3 +218,379 @@ pub fn config_file(c: &Config) -> InlineKeyboardButton {
         ),
     )
 }
+
+pub fn config_qr(c: &Config) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "QR code".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::GetConfigQr(c.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn config_pair_code(c: &Config) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "Pair code".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::PairCode(c.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn config_usage(c: &Config) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "Usage".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::ConfigUsage(c.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn user(u: &UserSummary) -> InlineKeyboardButton {
+    let name = match u.telegram_id {
+        Some(id) => id.to_string(),
+        None => u.id.to_string(),
+    };
+    InlineKeyboardButton::new(
+        format!("{name}, {} configs", u.configs),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::UserDetails(u.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn users_page(text: &str, page: u32) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        text.to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::Users(page)).unwrap(),
+        ),
+    )
+}
+
+/// Grants or revokes `r` to the user of [`State::UserDetails`](super::State::UserDetails)
+pub fn user_role(u: &UserSummary, r: &Role) -> InlineKeyboardButton {
+    let mark = if u.roles.contains(&r.id) {
+        "✓"
+    } else {
+        "✗"
+    };
+    InlineKeyboardButton::new(
+        format!("{mark} {}", r.name),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::ToggleUserRole(r.id)).unwrap(),
+        ),
+    )
+}
+
+/// Switches the window of [`State::Top`](super::State::Top), the current one is marked
+pub fn top_window(view: &TopView, window: TrafficWindow) -> InlineKeyboardButton {
+    let mark = if view.window == window { "• " } else { "" };
+    InlineKeyboardButton::new(
+        format!("{mark}{window}"),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::TopWindow(window)).unwrap(),
+        ),
+    )
+}
+
+/// Switches the order of [`State::Top`](super::State::Top), the current one is marked
+pub fn top_sort(view: &TopView, sort: TrafficSort) -> InlineKeyboardButton {
+    let mark = if view.sort == sort { "• " } else { "" };
+    InlineKeyboardButton::new(
+        format!("{mark}{sort}"),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::TopSort(sort)).unwrap(),
+        ),
+    )
+}
+
+pub fn end_sessions(u: &UserSummary) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "End API sessions".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::EndSessions(u.id)).unwrap(),
+        ),
+    )
+}
+
+/// Picks the interface of a new config, the current one is marked
+pub fn draft_interface(name: &str, current: bool) -> InlineKeyboardButton {
+    let mark = if current { "• " } else { "" };
+    InlineKeyboardButton::new(
+        format!("{mark}{name}"),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::DraftInterface(name.to_owned())).unwrap(),
+        ),
+    )
+}
+
+/// Toggles the preshared key of a new config, marked while it is on
+pub fn draft_psk(psk: bool) -> InlineKeyboardButton {
+    let mark = if psk { "• " } else { "" };
+    InlineKeyboardButton::new(
+        format!("{mark}Preshared key"),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::DraftPsk(!psk)).unwrap(),
+        ),
+    )
+}
+
+/// Button with any label for `action`
+pub fn action(text: String, action: &Action) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        text,
+        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(action).unwrap()),
+    )
+}
+
+pub fn config_audit(c: &Config) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "History".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::ConfigAudit(c.id)).unwrap(),
+        ),
+    )
+}
+
+/// Audit log of the owner of the config
+pub fn owner_audit(c: &Config) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "Owner history".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::OwnerAudit(c.id)).unwrap(),
+        ),
+    )
+}
+
+/// Page of the log with the filter of [`State::Audit`](super::State::Audit)
+pub fn audit_page(text: &str, page: u32) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        text.to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::AuditPage(page)).unwrap(),
+        ),
+    )
+}
+
+pub fn config_quota(c: &Config) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "Config quota".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::ConfigQuota(c.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn user_quota(c: &Config) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "User quota".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::UserQuota(c.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn config_extend(c: &Config) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "Extend".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::ExtendConfig(c.id)).unwrap(),
+        ),
+    )
+}
+
+/// Suspend or Resume depending on the current state of the config
+pub fn config_disable(c: &Config) -> InlineKeyboardButton {
+    let (text, action) = if c.disabled {
+        ("Resume", Action::ResumeConfig(c.id))
+    } else {
+        ("Suspend", Action::SuspendConfig(c.id))
+    };
+    InlineKeyboardButton::new(
+        text.to_owned(),
+        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&action).unwrap()),
+    )
+}
+
+pub fn user_suspend(c: &Config) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "Suspend user".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::SuspendUser(c.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn user_resume(c: &Config) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "Resume user".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::ResumeUser(c.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn config_exit(c: &Config) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "Exit".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::SelectExit(c.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn config_rotate_psk(c: &Config) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "Rotate PSK".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::RotatePsk(c.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn config_rotate_key(c: &Config) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "Rotate key".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::RotateKey(c.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn config_set_key(c: &Config) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "Set public key".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::SetKey(c.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn config_profile(c: &Config) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "Profile".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::SelectProfile(c.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn profile(p: &Profile) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        p.name.clone(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::Profile(p.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn use_profile(p: &Profile) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        p.name.clone(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::UseProfile(Some(p.id))).unwrap(),
+        ),
+    )
+}
+
+pub fn profile_remove(p: &Profile) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "Remove".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::RemoveProfile(p.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn role(r: &Role) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        r.name.clone(),
+        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&Action::Role(r.id)).unwrap()),
+    )
+}
+
+/// Toggles `p` on the role of [`State::Role`](super::State::Role)
+pub fn role_permission(r: &Role, p: Permission) -> InlineKeyboardButton {
+    let mark = if r.permissions.contains(&p) {
+        "✓"
+    } else {
+        "✗"
+    };
+    InlineKeyboardButton::new(
+        format!("{mark} {p}"),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::TogglePermission(p)).unwrap(),
+        ),
+    )
+}
+
+pub fn role_assign(r: &Role) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "Assign".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::AssignRole(r.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn role_unassign(r: &Role) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "Unassign".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::UnassignRole(r.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn role_remove(r: &Role) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "Remove".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::RemoveRole(r.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn exit(e: &Exit) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        e.name.clone(),
+        InlineKeyboardButtonKind::CallbackData(serde_json::to_string(&Action::Exit(e.id)).unwrap()),
+    )
+}
+
+pub fn use_exit(e: &Exit) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        e.name.clone(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::UseExit(Some(e.id))).unwrap(),
+        ),
+    )
+}
+
+pub fn exit_remove(e: &Exit) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "Remove".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::RemoveExit(e.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn request_approve(r: &Request) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        format!("Approve {}", r.name),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::ApproveRequest(r.id)).unwrap(),
+        ),
+    )
+}
+
+pub fn request_decline(r: &Request) -> InlineKeyboardButton {
+    InlineKeyboardButton::new(
+        "Decline".to_owned(),
+        InlineKeyboardButtonKind::CallbackData(
+            serde_json::to_string(&Action::DeclineRequest(r.id)).unwrap(),
+        ),
+    )
+}
@@ -7,28 +7,54 @@ use serde::{Deserialize, Serialize};
 use teloxide::{
     adaptors::DefaultParseMode,
     dispatching::{
-        dialogue::{Dialogue, InMemStorage},
+        dialogue::{Dialogue, ErasedStorage},
         DpHandlerDescription, HandlerExt, UpdateFilterExt,
     },
     dptree,
     payloads::SendMessageSetters,
     prelude::{DependencyMap, Endpoint},
     requests::Requester,
-    types::{CallbackQuery, InlineKeyboardMarkup, InputFile, Message, Update},
+    types::{CallbackQuery, ChatId, InlineKeyboardMarkup, InputFile, Message, Update},
     utils::markdown::escape,
     Bot,
 };
 use uuid::Uuid;
 
+use time::{Duration, OffsetDateTime};
+
 use crate::{
-    service::{User, Wgcfg},
+    database::{Period, TrafficPoint},
+    roles::Permission,
+    service::{
+        audit::AuditFilter,
+        expiry::parse_duration,
+        presence::Presence,
+        quotas::{parse_limit, QuotaTarget},
+        traffic::{TrafficSort, TrafficWindow},
+        Request, User, Wgcfg,
+    },
     traits::TelegramDb,
+    utils,
 };
 
+use super::{notifications::utc, Answer};
+
+/// Entries per page of the audit log
+const AUDIT_PAGE: u32 = 10;
+/// Users per page of the user list
+const USERS_PAGE: u32 = 10;
+/// Entries of the traffic leaderboard
+const TOP_SIZE: usize = 10;
+
+fn gb(bytes: u64) -> f64 {
+    bytes as f64 / (1024u64 * 1024 * 1024) as f64
+}
+
 type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
-type MyDialogue = Dialogue<State, InMemStorage<State>>;
+type MyDialogue = Dialogue<State, ErasedStorage<State>>;
 
-#[derive(Clone, Default)]
+/// Kept in the database between updates, see [`super::storage::DbStorage`]
+#[derive(Clone, Default, Deserialize, Serialize)]
 pub enum State {
     #[default]
     Start,
@@ -36,9 +62,65 @@ pub enum State {
     ConfigsMenu,
     Config(Uuid),
     RenameConfig(Uuid),
-    CreateConfig,
-    Admins,
-    AddAdmin,
+    /// Waits for a user-supplied public key to rotate the config to
+    RotateKey(Uuid),
+    CreateConfig(ConfigDraft),
+    /// Page of the user list
+    Users(u32),
+    UserDetails(Uuid),
+    FindUser,
+    Profiles,
+    Profile(Uuid),
+    CreateProfile,
+    SelectProfile(Uuid),
+    Exits,
+    Exit(Uuid),
+    CreateExit,
+    SelectExit(Uuid),
+    /// Cap of the config
+    SetConfigQuota(Uuid),
+    /// Cap of the owner of the config
+    SetUserQuota(Uuid),
+    ExtendConfig(Uuid),
+    Requests,
+    Connected,
+    Tokens,
+    Roles,
+    Role(Uuid),
+    CreateRole,
+    AssignRole(Uuid),
+    UnassignRole(Uuid),
+    /// Page of the audit log
+    Audit(AuditFilter, u32),
+    AuditFilterUser,
+    Server,
+    Top(TopView),
+}
+
+/// Choices made on [`State::CreateConfig`] before the name is entered
+#[derive(Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ConfigDraft {
+    /// Primary interface when `None`
+    pub interface: Option<String>,
+    /// Whether the config gets a preshared key
+    pub psk: bool,
+}
+
+impl Default for ConfigDraft {
+    fn default() -> Self {
+        Self {
+            interface: None,
+            psk: true,
+        }
+    }
+}
+
+/// Traffic leaderboard of configs or users
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TopView {
+    pub users: bool,
+    pub window: TrafficWindow,
+    pub sort: TrafficSort,
 }
 
 #[derive(Deserialize, Serialize, Clone)]
@@ -47,12 +129,85 @@ enum Action {
     Configs,
     Config(Uuid),
     CreateConfig,
+    /// Picks the interface of [`State::CreateConfig`]
+    DraftInterface(String),
+    /// Turns the preshared key of [`State::CreateConfig`] on or off
+    DraftPsk(bool),
     RenameConfig(Uuid),
     RemoveConfig(Uuid),
     GetConfigFile(Uuid),
-    Admins,
-    AddAdmin,
-    RmAdmin(Uuid),
+    GetConfigQr(Uuid),
+    RotatePsk(Uuid),
+    /// Generates a new keypair for the config
+    RotateKey(Uuid),
+    /// Asks for a public key to rotate the config to
+    SetKey(Uuid),
+    PairCode(Uuid),
+    ConfigUsage(Uuid),
+    ConfigQuota(Uuid),
+    UserQuota(Uuid),
+    ExtendConfig(Uuid),
+    SuspendConfig(Uuid),
+    ResumeConfig(Uuid),
+    /// Suspends all configs of the owner of the config
+    SuspendUser(Uuid),
+    ResumeUser(Uuid),
+    Users(u32),
+    UserDetails(Uuid),
+    FindUser,
+    /// Grants or revokes the role to the user from [`State::UserDetails`]
+    ToggleUserRole(Uuid),
+    /// Revokes the `/apitoken` tokens of the user
+    EndSessions(Uuid),
+    Profiles,
+    Profile(Uuid),
+    CreateProfile,
+    RemoveProfile(Uuid),
+    /// Opens profile selection for a config
+    SelectProfile(Uuid),
+    /// Applies profile to the config from [`State::SelectProfile`]
+    UseProfile(Option<Uuid>),
+    Exits,
+    Exit(Uuid),
+    CreateExit,
+    RemoveExit(Uuid),
+    /// Opens exit selection for a config
+    SelectExit(Uuid),
+    /// Routes the config from [`State::SelectExit`] through the exit
+    UseExit(Option<Uuid>),
+    Requests,
+    Connected,
+    Tokens,
+    RevokeToken(Uuid),
+    Roles,
+    Role(Uuid),
+    CreateRole,
+    RemoveRole(Uuid),
+    /// Grants or revokes the permission of the role from [`State::Role`]
+    TogglePermission(Permission),
+    AssignRole(Uuid),
+    UnassignRole(Uuid),
+    /// Unfiltered audit log
+    Audit,
+    /// Page of the audit log from [`State::Audit`]
+    AuditPage(u32),
+    AuditFilterUser,
+    ConfigAudit(Uuid),
+    OwnerAudit(Uuid),
+    Server,
+    /// Leaderboard of users when `true`, of configs otherwise
+    Top(bool),
+    /// Changes the window of [`State::Top`]
+    TopWindow(TrafficWindow),
+    /// Changes the order of [`State::Top`]
+    TopSort(TrafficSort),
+    ApproveRequest(Uuid),
+    DeclineRequest(Uuid),
+}
+
+/// Approve/Decline keyboard sent to admins with a new request
+pub fn request_keyboard(r: &Request) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([[buttons::request_approve(r), buttons::request_decline(r)]])
 }
 
 impl State {
@@ -66,13 +221,38 @@ impl State {
             State::Start => {
                 todo!()
             }
-            State::MainMenu => Ok((
-                "Main menu".to_owned(),
-                Some(InlineKeyboardMarkup::new([
-                    [buttons::CONFIGS.clone()],
-                    [buttons::ADMINS.clone()],
-                ])),
-            )),
+            State::MainMenu => {
+                let mut cap = "Main menu".to_owned();
+                for (name, usage, usage6) in service.pool_usage().await? {
+                    cap.push_str(&format!(
+                        "\n{} IP pool: {}",
+                        escape(&name),
+                        escape(&usage.to_string())
+                    ));
+                    if let Some(usage6) = usage6 {
+                        cap.push_str(&format!(
+                            "\n{} IPv6 pool: {}",
+                            escape(&name),
+                            escape(&usage6.to_string())
+                        ));
+                    }
+                }
+                Ok((
+                    cap,
+                    Some(InlineKeyboardMarkup::new([
+                        [buttons::CONFIGS.clone()],
+                        [buttons::REQUESTS.clone()],
+                        [buttons::PROFILES.clone()],
+                        [buttons::EXITS.clone()],
+                        [buttons::CONNECTED.clone()],
+                        [buttons::SERVER.clone()],
+                        [buttons::TOKENS.clone()],
+                        [buttons::USERS.clone()],
+                        [buttons::ROLES.clone()],
+                        [buttons::AUDIT.clone()],
+                    ])),
+                ))
+            }
             State::ConfigsMenu => {
                 let configs = service.configs(user.id).await?;
 
@@ -97,14 +277,54 @@ impl State {
             }
             State::Config(id) => {
                 let c = service.config(user, *id).await?;
-                let cap = format!(
-                    "Name: {}\nIP: {}\nKey: {}\nTx: {:.3} GB\nRx: {:.3} GB",
+                let profile = service.profile(c.config.profile_id).await?;
+                let quotas = service.quotas(c.config.id, c.config.user_id).await?;
+                let exit = match c.config.exit_id {
+                    Some(id) => service.exit(id).await?.name,
+                    None => "direct".to_owned(),
+                };
+                let presence = Presence::new(c.stats.last_handshake, OffsetDateTime::now_utc());
+                let mut cap = format!(
+                    "Name: {}\nStatus: {}\nInterface: {}\nProfile: {}\nExit: {}\nIP: {}\nIPv6: {}\nKey: {}\nTx: {:.3} GB\nRx: {:.3} GB",
                     escape(&c.config.name),
+                    escape(&presence.to_string()),
+                    escape(&c.config.interface),
+                    escape(&profile.name),
+                    escape(&exit),
                     escape(&c.config.ip.to_string()),
+                    escape(
+                        &c.config
+                            .ip6
+                            .map(|ip| ip.to_string())
+                            .unwrap_or("none".to_owned())
+                    ),
                     escape(&base64::engine::general_purpose::STANDARD.encode(c.config.pub_key)),
                     escape(&(c.stats.tx as f64 / (1024u64 * 1024 * 1024) as f64).to_string()),
                     escape(&(c.stats.rx as f64 / (1024u64 * 1024 * 1024) as f64).to_string()),
                 );
+                for q in quotas {
+                    let scope = match q.target {
+                        QuotaTarget::User(_) => "User quota",
+                        QuotaTarget::Config(_) => "Config quota",
+                    };
+                    cap.push_str(&format!("\n{scope}: {}", escape(&q.to_string())));
+                }
+                if let Some(t) = c
+                    .config
+                    .expires_at
+                    .and_then(|t| OffsetDateTime::from_unix_timestamp(t).ok())
+                {
+                    cap.push_str(&format!("\nExpires: {}", escape(&utc(&t))));
+                }
+                if c.config.suspended {
+                    cap.push_str("\n*Suspended, quota exhausted*");
+                }
+                if c.config.expired {
+                    cap.push_str("\n*Expired*");
+                }
+                if c.config.disabled {
+                    cap.push_str("\n*Suspended by an admin*");
+                }
                 Ok((
                     cap,
                     Some(InlineKeyboardMarkup::new([
@@ -112,6 +332,47 @@ impl State {
                             buttons::config_rename(&c.config),
                             buttons::config_remove(&c.config),
                             buttons::config_file(&c.config),
+                            buttons::config_qr(&c.config),
+                        ]
+                        .as_slice()
+                        .iter()
+                        .cloned(),
+                        [
+                            buttons::config_profile(&c.config),
+                            buttons::config_rotate_psk(&c.config),
+                            buttons::config_pair_code(&c.config),
+                        ]
+                        .as_slice()
+                        .iter()
+                        .cloned(),
+                        [
+                            buttons::config_rotate_key(&c.config),
+                            buttons::config_set_key(&c.config),
+                        ]
+                        .as_slice()
+                        .iter()
+                        .cloned(),
+                        [
+                            buttons::config_exit(&c.config),
+                            buttons::config_usage(&c.config),
+                            buttons::config_audit(&c.config),
+                            buttons::owner_audit(&c.config),
+                        ]
+                        .as_slice()
+                        .iter()
+                        .cloned(),
+                        [
+                            buttons::config_quota(&c.config),
+                            buttons::user_quota(&c.config),
+                            buttons::config_extend(&c.config),
+                        ]
+                        .as_slice()
+                        .iter()
+                        .cloned(),
+                        [
+                            buttons::config_disable(&c.config),
+                            buttons::user_suspend(&c.config),
+                            buttons::user_resume(&c.config),
                         ]
                         .as_slice()
                         .iter()
@@ -121,21 +382,411 @@ impl State {
                 ))
             }
             State::RenameConfig(_) => Ok(("Enter new name: ".to_owned(), None)),
-            State::CreateConfig => Ok(("Enter name: ".to_owned(), None)),
-            State::Admins => Ok((
-                "Admins:".to_string(),
-                Some(InlineKeyboardMarkup::new([
-                    [buttons::ADD_ADMIN.clone()],
-                    [buttons::MAIN_MENU.clone()],
-                ])),
+            State::RotateKey(_) => Ok(("Enter new public key: ".to_owned(), None)),
+            State::CreateConfig(draft) => {
+                let cap =
+                    escape("Enter name, optionally followed by a lifetime like 12h, 7d or 2w: ");
+                let mut rows = vec![vec![buttons::draft_psk(draft.psk)]];
+                let interfaces = service.interfaces();
+                if interfaces.len() > 1 {
+                    let current = draft
+                        .interface
+                        .as_deref()
+                        .unwrap_or(&service.primary_interface().name);
+                    rows.insert(
+                        0,
+                        interfaces
+                            .iter()
+                            .map(|i| buttons::draft_interface(&i.name, i.name == current))
+                            .collect(),
+                    );
+                }
+                Ok((cap, Some(InlineKeyboardMarkup::new(rows))))
+            }
+            State::ExtendConfig(_) => Ok((
+                escape("Enter the new lifetime from now like 12h, 7d or 2w, or never: "),
+                None,
             )),
-            State::AddAdmin => Ok(("Enter uid: ".to_owned(), None)),
+            State::Users(page) => {
+                let users = service.users(user).await?;
+                let start = (*page * USERS_PAGE) as usize;
+                let mut rows = users
+                    .iter()
+                    .skip(start)
+                    .take(USERS_PAGE as usize)
+                    .map(|u| vec![buttons::user(u)])
+                    .collect::<Vec<_>>();
+                let mut nav = Vec::new();
+                if *page > 0 {
+                    nav.push(buttons::users_page("Previous", page - 1));
+                }
+                if users.len() > start + USERS_PAGE as usize {
+                    nav.push(buttons::users_page("Next", page + 1));
+                }
+                if !nav.is_empty() {
+                    rows.push(nav);
+                }
+                rows.push(vec![buttons::FIND_USER.clone()]);
+                rows.push(vec![buttons::MAIN_MENU.clone()]);
+
+                Ok((
+                    format!("Users: {}", users.len()),
+                    Some(InlineKeyboardMarkup::new(rows)),
+                ))
+            }
+            State::UserDetails(id) => {
+                let u = service.user_summary(user, *id).await?;
+                let roles = service.roles(user).await?;
+                let configs = service.user_configs(user, *id).await?;
+                let identity = match u.telegram_id {
+                    Some(tg) => format!("[{tg}](tg://user?id={tg})"),
+                    None => format!("`{}`", u.id),
+                };
+                let role_names = roles
+                    .iter()
+                    .filter(|r| u.roles.contains(&r.id))
+                    .map(|r| r.name.as_str())
+                    .collect::<Vec<_>>();
+                let cap = format!(
+                    "User {identity}\nRoles: {}\nConfigs: {}\nTraffic: {}",
+                    escape(&if role_names.is_empty() {
+                        "none".to_owned()
+                    } else {
+                        role_names.join(", ")
+                    }),
+                    u.configs,
+                    escape(&format!("↑{:.3} GB ↓{:.3} GB", gb(u.tx), gb(u.rx))),
+                );
+
+                let mut rows = configs
+                    .into_iter()
+                    .map(|c| vec![buttons::config(c)])
+                    .collect::<Vec<_>>();
+                rows.extend(roles.iter().map(|r| vec![buttons::user_role(&u, r)]));
+                rows.push(vec![buttons::end_sessions(&u)]);
+                rows.push(vec![buttons::USERS.clone()]);
+
+                Ok((cap, Some(InlineKeyboardMarkup::new(rows))))
+            }
+            State::FindUser => Ok(("Enter uid: ".to_owned(), None)),
+            State::Server => {
+                let mut cap = "Server".to_owned();
+                for i in service.server_overview(user).await? {
+                    cap.push_str(&escape(&format!(
+                        "\n\n{name}\nPublic key: {key}\nEndpoint: {endpoint}, listening on {port}\nPeers: {peers}, {online} online\nIP pool: {pool}",
+                        name = i.name,
+                        key = i.pub_key,
+                        endpoint = i.endpoint,
+                        port = i.listen_port,
+                        peers = i.peers,
+                        online = i.online,
+                        pool = i.pool,
+                    )));
+                    if let Some(pool6) = i.pool6 {
+                        cap.push_str(&escape(&format!("\nIPv6 pool: {pool6}")));
+                    }
+                    cap.push_str(&escape(&format!(
+                        "\nTraffic: ↑{:.3} GB ↓{:.3} GB",
+                        gb(i.tx),
+                        gb(i.rx)
+                    )));
+                }
+
+                Ok((
+                    cap,
+                    Some(InlineKeyboardMarkup::new([
+                        [buttons::TOP_CONFIGS.clone()],
+                        [buttons::TOP_USERS.clone()],
+                        [buttons::MAIN_MENU.clone()],
+                    ])),
+                ))
+            }
+            State::Top(view) => {
+                let mut entries = if view.users {
+                    service
+                        .user_stats(user, view.window)
+                        .await?
+                        .into_iter()
+                        .map(|u| {
+                            let name = u
+                                .telegram_id
+                                .map_or_else(|| u.id.to_string(), |id| id.to_string());
+                            (Action::UserDetails(u.id), name, u.tx, u.rx)
+                        })
+                        .collect::<Vec<_>>()
+                } else {
+                    service
+                        .stats(user, view.window)
+                        .await?
+                        .into_iter()
+                        .map(|p| (Action::Config(p.config_id), p.name, p.tx, p.rx))
+                        .collect::<Vec<_>>()
+                };
+                entries.sort_by_key(|(_, _, tx, rx)| std::cmp::Reverse(view.sort.key(*tx, *rx)));
+                entries.truncate(TOP_SIZE);
+
+                let mut cap = escape(&format!(
+                    "Top {} by {} traffic, {}",
+                    if view.users { "users" } else { "configs" },
+                    view.sort,
+                    view.window
+                ));
+                let mut rows = Vec::with_capacity(entries.len() + 4);
+                for (n, (action, name, tx, rx)) in entries.into_iter().enumerate() {
+                    cap.push_str(&escape(&format!(
+                        "\n{}. {name} ↑{:.3} GB ↓{:.3} GB",
+                        n + 1,
+                        gb(tx),
+                        gb(rx)
+                    )));
+                    rows.push(vec![buttons::action(format!("{}. {name}", n + 1), &action)]);
+                }
+                rows.push(
+                    TrafficWindow::ALL
+                        .into_iter()
+                        .map(|w| buttons::top_window(view, w))
+                        .collect(),
+                );
+                rows.push(
+                    TrafficSort::ALL
+                        .into_iter()
+                        .map(|s| buttons::top_sort(view, s))
+                        .collect(),
+                );
+                rows.push(vec![if view.users {
+                    buttons::TOP_CONFIGS.clone()
+                } else {
+                    buttons::TOP_USERS.clone()
+                }]);
+                rows.push(vec![buttons::SERVER.clone()]);
+
+                Ok((cap, Some(InlineKeyboardMarkup::new(rows))))
+            }
+            State::Audit(filter, page) => {
+                let entries = service
+                    .audit_log(user, *filter, page * AUDIT_PAGE, AUDIT_PAGE)
+                    .await?;
+                let mut nav = Vec::new();
+                if *page > 0 {
+                    nav.push(buttons::audit_page("Newer", page - 1));
+                }
+                if entries.len() == AUDIT_PAGE as usize {
+                    nav.push(buttons::audit_page("Older", page + 1));
+                }
+                let mut rows = Vec::new();
+                if !nav.is_empty() {
+                    rows.push(nav);
+                }
+                rows.push(vec![buttons::AUDIT_FILTER_USER.clone()]);
+                if *filter != AuditFilter::default() {
+                    rows.push(vec![buttons::AUDIT.clone()]);
+                }
+                rows.push(vec![buttons::MAIN_MENU.clone()]);
+
+                Ok((
+                    Answer::Audit(entries).to_msg(),
+                    Some(InlineKeyboardMarkup::new(rows)),
+                ))
+            }
+            State::AuditFilterUser => Ok(("Enter uid: ".to_owned(), None)),
+            State::Requests => {
+                let requests = service.requests(user).await?;
+                let mut rows = requests
+                    .iter()
+                    .map(|r| vec![buttons::request_approve(r), buttons::request_decline(r)])
+                    .collect::<Vec<_>>();
+                rows.push(vec![buttons::MAIN_MENU.clone()]);
+
+                Ok((
+                    Answer::Requests(requests).to_msg(),
+                    Some(InlineKeyboardMarkup::new(rows)),
+                ))
+            }
+            State::Connected => {
+                let peers = service.connected_peers(user).await?;
+                let now = OffsetDateTime::now_utc().unix_timestamp();
+                let mut cap = format!("Connected peers: {}", peers.len());
+                for p in &peers {
+                    cap.push_str(&escape(&format!(
+                        "\n{} {} {} from {}, {}s ago",
+                        p.name,
+                        p.ip,
+                        p.interface,
+                        p.endpoint.as_deref().unwrap_or("unknown"),
+                        now - p.last_handshake
+                    )));
+                }
+                let mut rows = peers
+                    .iter()
+                    .map(|p| vec![buttons::connected_peer(p)])
+                    .collect::<Vec<_>>();
+                rows.push(vec![buttons::MAIN_MENU.clone()]);
+
+                Ok((cap, Some(InlineKeyboardMarkup::new(rows))))
+            }
+            State::Tokens => {
+                let tokens = service.all_api_tokens(user).await?;
+                let mut rows = tokens
+                    .iter()
+                    .map(|t| vec![buttons::token_revoke(t)])
+                    .collect::<Vec<_>>();
+                rows.push(vec![buttons::MAIN_MENU.clone()]);
+
+                Ok((
+                    Answer::Tokens(tokens).to_msg(),
+                    Some(InlineKeyboardMarkup::new(rows)),
+                ))
+            }
+            State::Roles => {
+                let mut rows = service
+                    .roles(user)
+                    .await?
+                    .iter()
+                    .map(|r| vec![buttons::role(r)])
+                    .collect::<Vec<_>>();
+                rows.push(vec![buttons::CREATE_ROLE.clone()]);
+                rows.push(vec![buttons::MAIN_MENU.clone()]);
+
+                Ok(("Roles".to_owned(), Some(InlineKeyboardMarkup::new(rows))))
+            }
+            State::Role(id) => {
+                let r = service.role(user, *id).await?;
+                let mut rows = Permission::ALL
+                    .into_iter()
+                    .map(|p| vec![buttons::role_permission(&r, p)])
+                    .collect::<Vec<_>>();
+                rows.push(vec![buttons::role_assign(&r), buttons::role_unassign(&r)]);
+                rows.push(vec![buttons::role_remove(&r)]);
+                rows.push(vec![buttons::ROLES.clone()]);
+
+                Ok((
+                    format!("Role {}", escape(&r.name)),
+                    Some(InlineKeyboardMarkup::new(rows)),
+                ))
+            }
+            State::CreateRole => Ok((
+                escape(&format!(
+                    "Enter role: name [permission,...], permissions are {}",
+                    Permission::ALL.map(|p| p.name()).join(", ")
+                )),
+                None,
+            )),
+            State::AssignRole(_) | State::UnassignRole(_) => Ok(("Enter uid: ".to_owned(), None)),
+            State::Profiles => {
+                let mut rows = service
+                    .profiles()
+                    .await?
+                    .iter()
+                    .map(|p| vec![buttons::profile(p)])
+                    .collect::<Vec<_>>();
+                rows.push(vec![buttons::CREATE_PROFILE.clone()]);
+                rows.push(vec![buttons::MAIN_MENU.clone()]);
+
+                Ok(("Profiles".to_owned(), Some(InlineKeyboardMarkup::new(rows))))
+            }
+            State::Profile(id) => {
+                let p = service.profile(Some(*id)).await?;
+                Ok((
+                    format!("```\n{}\n```", escape(&p.to_string())),
+                    Some(InlineKeyboardMarkup::new([
+                        [buttons::profile_remove(&p)],
+                        [buttons::PROFILES.clone()],
+                    ])),
+                ))
+            }
+            State::CreateProfile => Ok((
+                escape(
+                    "Enter profile: name full|internal|cidr,... [dns=ip,...] [mtu=n] [keepalive=n]",
+                ),
+                None,
+            )),
+            State::Exits => {
+                let mut rows = service
+                    .exits()
+                    .await?
+                    .iter()
+                    .map(|e| vec![buttons::exit(e)])
+                    .collect::<Vec<_>>();
+                rows.push(vec![buttons::CREATE_EXIT.clone()]);
+                rows.push(vec![buttons::MAIN_MENU.clone()]);
+
+                Ok(("Exits".to_owned(), Some(InlineKeyboardMarkup::new(rows))))
+            }
+            State::Exit(id) => {
+                let e = service.exit(*id).await?;
+                Ok((
+                    format!("```\n{}\n```", escape(&e.to_string())),
+                    Some(InlineKeyboardMarkup::new([
+                        [buttons::exit_remove(&e)],
+                        [buttons::EXITS.clone()],
+                    ])),
+                ))
+            }
+            State::CreateExit => Ok((
+                escape("Enter exit: name table=n [fwmark=n] [priority=n]"),
+                None,
+            )),
+            State::SelectExit(_) => {
+                let mut rows = service
+                    .exits()
+                    .await?
+                    .iter()
+                    .map(|e| vec![buttons::use_exit(e)])
+                    .collect::<Vec<_>>();
+                rows.push(vec![buttons::DIRECT_EXIT.clone()]);
+
+                Ok((
+                    "Select exit".to_owned(),
+                    Some(InlineKeyboardMarkup::new(rows)),
+                ))
+            }
+            State::SetConfigQuota(_) | State::SetUserQuota(_) => {
+                Ok((escape("Enter cap: <GB> [day|month], 0 removes it"), None))
+            }
+            State::SelectProfile(_) => {
+                let mut rows = service
+                    .profiles()
+                    .await?
+                    .iter()
+                    .map(|p| vec![buttons::use_profile(p)])
+                    .collect::<Vec<_>>();
+                rows.push(vec![buttons::DEFAULT_PROFILE.clone()]);
+
+                Ok((
+                    "Select profile".to_owned(),
+                    Some(InlineKeyboardMarkup::new(rows)),
+                ))
+            }
         }
     }
 }
 
+/// Monospace table of daily or monthly traffic buckets
+fn usage_table(points: &[TrafficPoint], period: Period) -> String {
+    let mut res = String::from("```\n");
+    for p in points {
+        let start = OffsetDateTime::from_unix_timestamp(p.start)
+            .map(|t| match period {
+                Period::Month => format!("{}-{:02}", t.year(), t.month() as u8),
+                _ => t.date().to_string(),
+            })
+            .unwrap_or_default();
+        res.push_str(&format!(
+            "{start} tx {:.3} GB rx {:.3} GB\n",
+            p.tx as f64 / (1024u64 * 1024 * 1024) as f64,
+            p.rx as f64 / (1024u64 * 1024 * 1024) as f64,
+        ));
+    }
+    if points.is_empty() {
+        res.push_str("no traffic\n");
+    }
+    res.push_str("```");
+    res
+}
+
+/// Anyone with a permission gets the menu, the service checks each action
 async fn is_admin(user: User) -> bool {
-    user.is_admin()
+    !user.permissions.is_empty()
 }
 
 pub fn entry<T: TelegramDb + 'static>() -> Endpoint<
@@ -148,15 +799,25 @@ pub fn entry<T: TelegramDb + 'static>() -> Endpoint<
         .filter_async(is_admin)
         .branch(
             Update::filter_message()
-                .enter_dialogue::<Update, InMemStorage<State>, State>()
+                .enter_dialogue::<Update, ErasedStorage<State>, State>()
                 .branch(dptree::case![State::RenameConfig(config_id)].endpoint(config_rename))
-                .branch(dptree::case![State::CreateConfig].endpoint(config_create))
-                .branch(dptree::case![State::AddAdmin].endpoint(add_admin))
+                .branch(dptree::case![State::RotateKey(config_id)].endpoint(config_rotate_key))
+                .branch(dptree::case![State::CreateConfig(draft)].endpoint(config_create))
+                .branch(dptree::case![State::FindUser].endpoint(find_user))
+                .branch(dptree::case![State::CreateRole].endpoint(role_create))
+                .branch(dptree::case![State::AuditFilterUser].endpoint(audit_filter_user))
+                .branch(dptree::case![State::AssignRole(role_id)].endpoint(role_assign))
+                .branch(dptree::case![State::UnassignRole(role_id)].endpoint(role_assign))
+                .branch(dptree::case![State::CreateProfile].endpoint(profile_create))
+                .branch(dptree::case![State::CreateExit].endpoint(exit_create))
+                .branch(dptree::case![State::SetConfigQuota(config_id)].endpoint(quota_set))
+                .branch(dptree::case![State::SetUserQuota(config_id)].endpoint(quota_set))
+                .branch(dptree::case![State::ExtendConfig(config_id)].endpoint(config_extend))
                 .branch(dptree::endpoint(start)),
         )
         .branch(
             Update::filter_callback_query()
-                .enter_dialogue::<Update, InMemStorage<State>, State>()
+                .enter_dialogue::<Update, ErasedStorage<State>, State>()
                 .endpoint(callback_handler),
         )
 }
@@ -208,11 +869,88 @@ async fn config_rename(
     Ok(())
 }
 
+async fn config_rotate_key(
+    bot: DefaultParseMode<Bot>,
+    service: Arc<Wgcfg>,
+    dialogue: MyDialogue,
+    msg: Message,
+    config_id: Uuid,
+    user: User,
+) -> HandlerResult {
+    if let Some(key) = msg.text() {
+        let config = service
+            .rotate_key(&user, config_id, Some(key.trim().to_owned()))
+            .await?;
+        let file = service.config_file(&config).await?;
+        let mut name = config.name;
+        name.push_str(".conf");
+        bot.send_document(msg.chat.id, InputFile::memory(file).file_name(name))
+            .await?;
+    } else {
+        bot.send_message(msg.chat.id, "Unexpected msg").await?;
+    }
+
+    let next_state = State::Config(config_id);
+    if let Ok((cap, kb)) = next_state.msg(&user, &service).await {
+        let mut t = bot.send_message(msg.chat.id, cap);
+        if let Some(kb) = kb {
+            t = t.reply_markup(kb);
+        }
+        t.await?;
+    }
+
+    dialogue.update(next_state).await?;
+    Ok(())
+}
+
 async fn config_create(
     bot: DefaultParseMode<Bot>,
     service: Arc<Wgcfg>,
     dialogue: MyDialogue,
     msg: Message,
+    draft: ConfigDraft,
+    user: User,
+) -> HandlerResult {
+    let Some(n) = msg.text() else {
+        bot.send_message(msg.chat.id, "Unexpected msg").await?;
+        return Ok(())
+    };
+
+    // "name 7d" expires in a week
+    let (name, lifetime) = match n.rsplit_once(' ') {
+        Some((name, d)) if parse_duration(d).is_some() => (name, parse_duration(d)),
+        _ => (n, None),
+    };
+    let config_id = service
+        .new_config(
+            &user,
+            name.to_owned(),
+            None,
+            draft.psk,
+            draft.interface.as_deref(),
+            lifetime.map(|l| OffsetDateTime::now_utc() + l),
+        )
+        .await?;
+
+    let next_state = State::Config(config_id);
+    if let Ok((cap, kb)) = next_state.msg(&user, &service).await {
+        let mut t = bot.send_message(msg.chat.id, cap);
+        if let Some(kb) = kb {
+            t = t.reply_markup(kb);
+        }
+        t.await?;
+    }
+
+    dialogue.update(next_state).await?;
+    Ok(())
+}
+
+async fn config_extend(
+    bot: DefaultParseMode<Bot>,
+    service: Arc<Wgcfg>,
+    dialogue: MyDialogue,
+    msg: Message,
+    config_id: Uuid,
     user: User,
 ) -> HandlerResult {
     let Some(n) = msg.text() else {
@@ -220,7 +958,16 @@ async fn config_create(
         return Ok(())
     };
 
-    let config_id = service.new_config(&user, n.to_owned(), None).await?;
+    let expires_at = match n.trim() {
+        "never" => Some(None),
+        d => parse_duration(d).map(|d| Some(OffsetDateTime::now_utc() + d)),
+    };
+    match expires_at {
+        Some(expires_at) => service.set_expiry(&user, config_id, expires_at).await?,
+        None => {
+            bot.send_message(msg.chat.id, "Invalid lifetime").await?;
+        }
+    }
 
     let next_state = State::Config(config_id);
     if let Ok((cap, kb)) = next_state.msg(&user, &service).await {
@@ -235,7 +982,7 @@ async fn config_create(
     Ok(())
 }
 
-async fn add_admin(
+async fn find_user(
     bot: DefaultParseMode<Bot>,
     service: Arc<Wgcfg>,
     dialogue: MyDialogue,
@@ -250,14 +997,232 @@ async fn add_admin(
         bot.send_message(msg.chat.id, "Invalid user id").await?;
         return Ok(())
     };
-    let Ok(new_admin) = service.user(crate::service::Association::Telegram(n)).await else {
+    let Ok(found) = service.user(crate::service::Association::Telegram(n)).await else {
         bot.send_message(msg.chat.id, "Unknown user id").await?;
         return Ok(())
     };
 
-    service.add_admin(&user, new_admin.id).await?;
+    let next_state = State::UserDetails(found.id);
+    if let Ok((cap, kb)) = next_state.msg(&user, &service).await {
+        let mut t = bot.send_message(msg.chat.id, cap);
+        if let Some(kb) = kb {
+            t = t.reply_markup(kb);
+        }
+        t.await?;
+    }
+
+    dialogue.update(next_state).await?;
+    Ok(())
+}
+
+async fn role_create(
+    bot: DefaultParseMode<Bot>,
+    service: Arc<Wgcfg>,
+    dialogue: MyDialogue,
+    msg: Message,
+    user: User,
+) -> HandlerResult {
+    let Some(n) = msg.text() else {
+        bot.send_message(msg.chat.id, "Unexpected msg").await?;
+        return Ok(())
+    };
 
-    let next_state = State::Admins;
+    let next_state = match service.create_role(&user, n).await {
+        Ok(id) => State::Role(id),
+        Err(e) => {
+            bot.send_message(msg.chat.id, escape(&e.to_string()))
+                .await?;
+            State::Roles
+        }
+    };
+    if let Ok((cap, kb)) = next_state.msg(&user, &service).await {
+        let mut t = bot.send_message(msg.chat.id, cap);
+        if let Some(kb) = kb {
+            t = t.reply_markup(kb);
+        }
+        t.await?;
+    }
+
+    dialogue.update(next_state).await?;
+    Ok(())
+}
+
+async fn audit_filter_user(
+    bot: DefaultParseMode<Bot>,
+    service: Arc<Wgcfg>,
+    dialogue: MyDialogue,
+    msg: Message,
+    user: User,
+) -> HandlerResult {
+    let Some(n) = msg.text() else {
+        bot.send_message(msg.chat.id, "Unexpected msg").await?;
+        return Ok(())
+    };
+    let Ok(n) = str::parse::<i64>(n) else {
+        bot.send_message(msg.chat.id, "Invalid user id").await?;
+        return Ok(())
+    };
+    let Ok(target) = service.user(crate::service::Association::Telegram(n)).await else {
+        bot.send_message(msg.chat.id, "Unknown user id").await?;
+        return Ok(())
+    };
+
+    let next_state = State::Audit(
+        AuditFilter {
+            user_id: Some(target.id),
+            ..Default::default()
+        },
+        0,
+    );
+    if let Ok((cap, kb)) = next_state.msg(&user, &service).await {
+        let mut t = bot.send_message(msg.chat.id, cap);
+        if let Some(kb) = kb {
+            t = t.reply_markup(kb);
+        }
+        t.await?;
+    }
+
+    dialogue.update(next_state).await?;
+    Ok(())
+}
+
+/// Assigns or unassigns, depending on the state, the role to a telegram user
+async fn role_assign(
+    bot: DefaultParseMode<Bot>,
+    service: Arc<Wgcfg>,
+    dialogue: MyDialogue,
+    msg: Message,
+    role_id: Uuid,
+    user: User,
+) -> HandlerResult {
+    let Some(n) = msg.text() else {
+        bot.send_message(msg.chat.id, "Unexpected msg").await?;
+        return Ok(())
+    };
+    let Ok(n) = str::parse::<i64>(n) else {
+        bot.send_message(msg.chat.id, "Invalid user id").await?;
+        return Ok(())
+    };
+    let Ok(target) = service.user(crate::service::Association::Telegram(n)).await else {
+        bot.send_message(msg.chat.id, "Unknown user id").await?;
+        return Ok(())
+    };
+
+    let res = match dialogue.get().await? {
+        Some(State::UnassignRole(_)) => service.unassign_role(&user, target.id, role_id).await,
+        _ => service.assign_role(&user, target.id, role_id).await,
+    };
+    if let Err(e) = res {
+        bot.send_message(msg.chat.id, escape(&e.to_string()))
+            .await?;
+    }
+
+    let next_state = State::Role(role_id);
+    if let Ok((cap, kb)) = next_state.msg(&user, &service).await {
+        let mut t = bot.send_message(msg.chat.id, cap);
+        if let Some(kb) = kb {
+            t = t.reply_markup(kb);
+        }
+        t.await?;
+    }
+
+    dialogue.update(next_state).await?;
+    Ok(())
+}
+
+async fn profile_create(
+    bot: DefaultParseMode<Bot>,
+    service: Arc<Wgcfg>,
+    dialogue: MyDialogue,
+    msg: Message,
+    user: User,
+) -> HandlerResult {
+    let Some(n) = msg.text() else {
+        bot.send_message(msg.chat.id, "Unexpected msg").await?;
+        return Ok(())
+    };
+
+    let next_state = match service.create_profile(&user, n).await {
+        Ok(id) => State::Profile(id),
+        Err(e) => {
+            bot.send_message(msg.chat.id, escape(&e.to_string()))
+                .await?;
+            State::Profiles
+        }
+    };
+    if let Ok((cap, kb)) = next_state.msg(&user, &service).await {
+        let mut t = bot.send_message(msg.chat.id, cap);
+        if let Some(kb) = kb {
+            t = t.reply_markup(kb);
+        }
+        t.await?;
+    }
+
+    dialogue.update(next_state).await?;
+    Ok(())
+}
+
+async fn exit_create(
+    bot: DefaultParseMode<Bot>,
+    service: Arc<Wgcfg>,
+    dialogue: MyDialogue,
+    msg: Message,
+    user: User,
+) -> HandlerResult {
+    let Some(n) = msg.text() else {
+        bot.send_message(msg.chat.id, "Unexpected msg").await?;
+        return Ok(())
+    };
+
+    let next_state = match service.create_exit(&user, n).await {
+        Ok(id) => State::Exit(id),
+        Err(e) => {
+            bot.send_message(msg.chat.id, escape(&e.to_string()))
+                .await?;
+            State::Exits
+        }
+    };
+    if let Ok((cap, kb)) = next_state.msg(&user, &service).await {
+        let mut t = bot.send_message(msg.chat.id, cap);
+        if let Some(kb) = kb {
+            t = t.reply_markup(kb);
+        }
+        t.await?;
+    }
+
+    dialogue.update(next_state).await?;
+    Ok(())
+}
+
+async fn quota_set(
+    bot: DefaultParseMode<Bot>,
+    service: Arc<Wgcfg>,
+    dialogue: MyDialogue,
+    msg: Message,
+    config_id: Uuid,
+    user: User,
+) -> HandlerResult {
+    let Some(n) = msg.text() else {
+        bot.send_message(msg.chat.id, "Unexpected msg").await?;
+        return Ok(())
+    };
+
+    let target = match dialogue.get().await? {
+        Some(State::SetUserQuota(_)) => {
+            QuotaTarget::User(service.config(&user, config_id).await?.config.user_id)
+        }
+        _ => QuotaTarget::Config(config_id),
+    };
+    let res = match parse_limit(n) {
+        Ok(limit) => service.set_quota(&user, target, limit).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = res {
+        bot.send_message(msg.chat.id, escape(&e.to_string()))
+            .await?;
+    }
+
+    let next_state = State::Config(config_id);
     if let Ok((cap, kb)) = next_state.msg(&user, &service).await {
         let mut t = bot.send_message(msg.chat.id, cap);
         if let Some(kb) = kb {
@@ -281,19 +1246,141 @@ async fn callback_handler(
         let a = serde_json::from_str(&action)?;
         bot.answer_callback_query(q.id).await?;
 
-        if let Action::GetConfigFile(id) = a {
+        if let Action::RotatePsk(id) = a {
+            service.rotate_psk(&user, id).await?;
+        };
+        if let Action::RotateKey(id) = a {
+            service.rotate_key(&user, id, None).await?;
+        }
+        if let Action::GetConfigFile(id) | Action::RotatePsk(id) | Action::RotateKey(id) = a {
             let config = service.config(&user, id).await?;
-            let file = config.config.config_file(service.server_info().await?);
+            let file = service.config_file(&config.config).await?;
             let mut name = config.config.name;
             name.push_str(".conf");
             bot.send_document(dialogue.chat_id(), InputFile::memory(file).file_name(name))
                 .await?;
         };
+        if let Action::GetConfigQr(id) = a {
+            let config = service.config(&user, id).await?;
+            if config.config.priv_key.is_none() {
+                bot.send_message(
+                    dialogue.chat_id(),
+                    escape("The private key of this config is not stored, a QR code would not work. Use the config file and insert the key manually."),
+                )
+                .await?;
+            } else {
+                let file = service.config_file(&config.config).await?;
+                let png = utils::qr_code_png(file.as_bytes())?;
+                let mut name = config.config.name;
+                name.push_str(".png");
+                bot.send_photo(dialogue.chat_id(), InputFile::memory(png).file_name(name))
+                    .await?;
+            }
+        };
+        if let Action::PairCode(id) = a {
+            let code = service.pair_code(&user, id).await?;
+            bot.send_message(
+                dialogue.chat_id(),
+                format!(
+                    "Send this to the bot from the account to pair, the code works once:\n`/pair {}`",
+                    escape(&code)
+                ),
+            )
+            .await?;
+        };
+        if let Action::ConfigUsage(id) = a {
+            let now = OffsetDateTime::now_utc();
+            let days = service
+                .config_traffic(&user, id, Period::Day, now - Duration::days(7), now)
+                .await?;
+            let months = service
+                .config_traffic(&user, id, Period::Month, now - Duration::days(183), now)
+                .await?;
+            bot.send_message(
+                dialogue.chat_id(),
+                format!(
+                    "Last 7 days:\n{}\n\nLast 6 months:\n{}",
+                    usage_table(&days, Period::Day),
+                    usage_table(&months, Period::Month),
+                ),
+            )
+            .await?;
+        };
+        if let Action::SuspendConfig(id) | Action::ResumeConfig(id) = a {
+            service
+                .set_disabled(&user, id, matches!(a, Action::SuspendConfig(_)))
+                .await?;
+        };
+        if let Action::SuspendUser(id) | Action::ResumeUser(id) = a {
+            let owner = service.config(&user, id).await?.config.user_id;
+            service
+                .set_user_disabled(&user, owner, matches!(a, Action::SuspendUser(_)))
+                .await?;
+        };
         if let Action::RemoveConfig(id) = a {
             service.rm_config(&user, id).await?;
         };
-        if let Action::RmAdmin(id) = a {
-            service.rm_admin(&user, id).await?;
+        if let Action::ApproveRequest(id) = a {
+            let request = service.approve_request(&user, id).await?;
+            if let (Some(chat), Some(config_id)) = (request.telegram_id, request.config_id) {
+                let config = service.config(&user, config_id).await?;
+                let file = service.config_file(&config.config).await?;
+                let mut name = config.config.name;
+                name.push_str(".conf");
+                bot.send_message(ChatId(chat), "Your config request was approved\\!")
+                    .await?;
+                bot.send_document(ChatId(chat), InputFile::memory(file).file_name(name))
+                    .await?;
+            }
+        };
+        if let Action::DeclineRequest(id) = a {
+            let request = service.decline_request(&user, id).await?;
+            if let Some(chat) = request.telegram_id {
+                bot.send_message(ChatId(chat), "Your config request was declined")
+                    .await?;
+            }
+        };
+        if let Action::RemoveProfile(id) = a {
+            service.rm_profile(&user, id).await?;
+        };
+        if let Action::RevokeToken(id) = a {
+            service.revoke_api_token(&user, id).await?;
+        };
+        if let Action::RemoveRole(id) = a {
+            service.rm_role(&user, id).await?;
+        };
+        if let Action::RemoveExit(id) = a {
+            service.rm_exit(&user, id).await?;
+        };
+        let current = dialogue.get().await?.unwrap_or_default();
+        if let (Action::UseProfile(profile_id), State::SelectProfile(config_id)) = (&a, &current) {
+            service
+                .set_config_profile(&user, *config_id, *profile_id)
+                .await?;
+        };
+        if let (Action::ToggleUserRole(role_id), State::UserDetails(user_id)) = (&a, &current) {
+            if service
+                .user_summary(&user, *user_id)
+                .await?
+                .roles
+                .contains(role_id)
+            {
+                service.unassign_role(&user, *user_id, *role_id).await?;
+            } else {
+                service.assign_role(&user, *user_id, *role_id).await?;
+            }
+        };
+        if let Action::EndSessions(id) = a {
+            service.end_api_sessions(&user, id).await?;
+        }
+        if let (Action::TogglePermission(p), State::Role(role_id)) = (&a, &current) {
+            let role = service.role(&user, *role_id).await?;
+            service
+                .set_role_permission(&user, *role_id, *p, !role.permissions.contains(p))
+                .await?;
+        };
+        if let (Action::UseExit(exit_id), State::SelectExit(config_id)) = (&a, &current) {
+            service.set_config_exit(&user, *config_id, *exit_id).await?;
         };
         let next_state = match a {
             Action::OpenMain => State::MainMenu,
@@ -301,11 +1388,105 @@ async fn callback_handler(
             Action::Config(id) => State::Config(id),
             Action::RenameConfig(id) => State::RenameConfig(id),
             Action::GetConfigFile(id) => State::Config(id),
-            Action::CreateConfig => State::CreateConfig,
+            Action::GetConfigQr(id) => State::Config(id),
+            Action::RotatePsk(id) => State::Config(id),
+            Action::RotateKey(id) => State::Config(id),
+            Action::SetKey(id) => State::RotateKey(id),
+            Action::PairCode(id) => State::Config(id),
+            Action::ConfigUsage(id) => State::Config(id),
+            Action::ConfigQuota(id) => State::SetConfigQuota(id),
+            Action::UserQuota(id) => State::SetUserQuota(id),
+            Action::ExtendConfig(id) => State::ExtendConfig(id),
+            Action::SuspendConfig(id) | Action::ResumeConfig(id) => State::Config(id),
+            Action::SuspendUser(id) | Action::ResumeUser(id) => State::Config(id),
+            Action::CreateConfig => State::CreateConfig(ConfigDraft::default()),
+            Action::DraftInterface(interface) => match current {
+                State::CreateConfig(draft) => State::CreateConfig(ConfigDraft {
+                    interface: Some(interface),
+                    ..draft
+                }),
+                _ => State::MainMenu,
+            },
+            Action::DraftPsk(psk) => match current {
+                State::CreateConfig(draft) => State::CreateConfig(ConfigDraft { psk, ..draft }),
+                _ => State::MainMenu,
+            },
             Action::RemoveConfig(_) => State::MainMenu,
-            Action::Admins => State::Admins,
-            Action::AddAdmin => State::AddAdmin,
-            Action::RmAdmin(_) => State::Admins,
+            Action::Users(page) => State::Users(page),
+            Action::UserDetails(id) => State::UserDetails(id),
+            Action::FindUser => State::FindUser,
+            Action::ToggleUserRole(_) => match current {
+                State::UserDetails(id) => State::UserDetails(id),
+                _ => State::Users(0),
+            },
+            Action::EndSessions(id) => State::UserDetails(id),
+            Action::Profiles => State::Profiles,
+            Action::Profile(id) => State::Profile(id),
+            Action::CreateProfile => State::CreateProfile,
+            Action::RemoveProfile(_) => State::Profiles,
+            Action::SelectProfile(id) => State::SelectProfile(id),
+            Action::Exits => State::Exits,
+            Action::Exit(id) => State::Exit(id),
+            Action::CreateExit => State::CreateExit,
+            Action::RemoveExit(_) => State::Exits,
+            Action::SelectExit(id) => State::SelectExit(id),
+            Action::Requests => State::Requests,
+            Action::Connected => State::Connected,
+            Action::Tokens => State::Tokens,
+            Action::Roles => State::Roles,
+            Action::Role(id) => State::Role(id),
+            Action::CreateRole => State::CreateRole,
+            Action::RemoveRole(_) => State::Roles,
+            Action::AssignRole(id) => State::AssignRole(id),
+            Action::UnassignRole(id) => State::UnassignRole(id),
+            Action::TogglePermission(_) => match current {
+                State::Role(id) => State::Role(id),
+                _ => State::MainMenu,
+            },
+            Action::RevokeToken(_) => State::Tokens,
+            Action::Audit => State::Audit(AuditFilter::default(), 0),
+            Action::Server => State::Server,
+            Action::Top(users) => State::Top(TopView {
+                users,
+                ..Default::default()
+            }),
+            Action::TopWindow(window) => match current {
+                State::Top(view) => State::Top(TopView { window, ..view }),
+                _ => State::Server,
+            },
+            Action::TopSort(sort) => match current {
+                State::Top(view) => State::Top(TopView { sort, ..view }),
+                _ => State::Server,
+            },
+            Action::AuditPage(page) => match current {
+                State::Audit(filter, _) => State::Audit(filter, page),
+                _ => State::Audit(AuditFilter::default(), page),
+            },
+            Action::AuditFilterUser => State::AuditFilterUser,
+            Action::ConfigAudit(id) => State::Audit(
+                AuditFilter {
+                    config_id: Some(id),
+                    ..Default::default()
+                },
+                0,
+            ),
+            Action::OwnerAudit(id) => State::Audit(
+                AuditFilter {
+                    user_id: Some(service.config(&user, id).await?.config.user_id),
+                    ..Default::default()
+                },
+                0,
+            ),
+            Action::ApproveRequest(_) => State::Requests,
+            Action::DeclineRequest(_) => State::Requests,
+            Action::UseProfile(_) => match current {
+                State::SelectProfile(id) => State::Config(id),
+                _ => State::MainMenu,
+            },
+            Action::UseExit(_) => match current {
+                State::SelectExit(id) => State::Config(id),
+                _ => State::MainMenu,
+            },
         };
 
         if let Ok((cap, kb)) = next_state.msg(&user, &service).await {
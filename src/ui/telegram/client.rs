@@ -17,10 +17,11 @@ use crate::{
 
 use super::Answer;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum VpnMode {
-    Double,
-    Single,
+    Direct,
+    /// Name of the exit
+    Exit(String),
 }
 
 impl FromStr for VpnMode {
@@ -28,9 +29,10 @@ impl FromStr for VpnMode {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
-            "double" => Ok(Self::Double),
-            "single" => Ok(Self::Single),
-            _unsupported => Err("unsupported".to_string()),
+            "" => Err("exit name is missing".to_string()),
+            // `single` is the spelling from before exits existed
+            "direct" | "single" => Ok(Self::Direct),
+            name => Ok(Self::Exit(name.to_owned())),
         }
     }
 }
@@ -41,7 +43,7 @@ pub enum Command {
     #[command(description = "rename peer", parse_with = "split")]
     Rename(Ipv4Addr, String),
     #[command(
-        description = "change vpn mode (supported: double, single)",
+        description = "change vpn exit (exit name, double or direct)",
         parse_with = "split"
     )]
     VpnMode(Ipv4Addr, VpnMode),
@@ -60,11 +62,14 @@ async fn handler<T: TelegramDb + 'static>(
             //bot.send_message(message.chat.id, answer.to_msg()).await?;
         }
         Command::VpnMode(ip, mode) => {
-            let double = match mode {
-                VpnMode::Double => true,
-                VpnMode::Single => false,
+            let exit = match &mode {
+                VpnMode::Direct => None,
+                VpnMode::Exit(name) => Some(name.as_str()),
             };
-            let answer: Answer = service.change_settings(ip, double).await.into();
+            let user = service
+                .user(Association::Telegram(message.chat.id.0))
+                .await?;
+            let answer: Answer = service.change_settings(&user, ip.into(), exit).await.into();
             bot.send_message(message.chat.id, answer.to_msg()).await?;
         }
     };
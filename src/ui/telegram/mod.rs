@@ -1,40 +1,50 @@
 mod admin;
 mod client;
 mod help;
+mod notifications;
+mod storage;
 mod user;
 
 use clap::Parser;
 use teloxide::{
-    dispatching::dialogue::InMemStorage, prelude::*, types::UpdateKind, utils::markdown::escape,
+    dispatching::dialogue::Storage, prelude::*, types::UpdateKind, utils::markdown::escape,
 };
 
 use std::{error::Error, fmt::Write, sync::Arc};
 
+use time::OffsetDateTime;
+
 use crate::{
-    service::{ClientInfo, ConfigInfo, PeerInfo, Request, ServerInfo, ServiceError, User, Wgcfg},
+    service::{
+        audit::AuditEntry,
+        auth::{join_scopes, ApiToken},
+        ClientInfo, PeerInfo, Request, ServiceError, User, Wgcfg,
+    },
     traits::TelegramDb,
-    utils,
 };
 
-pub enum Answer<'a> {
+pub enum Answer {
     Success,
-    Config(ConfigInfo, &'a ServerInfo),
+    /// Rendered config file
+    Config(String),
     PairList(Vec<ClientInfo>),
     Requests(Vec<Request>),
     Peers(Vec<PeerInfo>),
+    Tokens(Vec<ApiToken>),
+    Audit(Vec<AuditEntry>),
     Error(String),
 }
 
-impl<'a> From<Result<(ConfigInfo, &'a ServerInfo), ServiceError>> for Answer<'a> {
-    fn from(r: Result<(ConfigInfo, &'a ServerInfo), ServiceError>) -> Self {
+impl From<Result<String, ServiceError>> for Answer {
+    fn from(r: Result<String, ServiceError>) -> Self {
         match r {
-            Ok((client, server)) => Self::Config(client, server),
+            Ok(config) => Self::Config(config),
             Err(e) => Self::Error(e.to_string()),
         }
     }
 }
 
-impl From<Result<Vec<PeerInfo>, ServiceError>> for Answer<'static> {
+impl From<Result<Vec<PeerInfo>, ServiceError>> for Answer {
     fn from(r: Result<Vec<PeerInfo>, ServiceError>) -> Self {
         match r {
             Ok(i) => Self::Peers(i),
@@ -43,7 +53,7 @@ impl From<Result<Vec<PeerInfo>, ServiceError>> for Answer<'static> {
     }
 }
 
-impl From<Result<Vec<ClientInfo>, ServiceError>> for Answer<'static> {
+impl From<Result<Vec<ClientInfo>, ServiceError>> for Answer {
     fn from(r: Result<Vec<ClientInfo>, ServiceError>) -> Self {
         match r {
             Ok(i) => Self::PairList(i),
@@ -52,7 +62,7 @@ impl From<Result<Vec<ClientInfo>, ServiceError>> for Answer<'static> {
     }
 }
 
-impl From<Result<Vec<Request>, ServiceError>> for Answer<'static> {
+impl From<Result<Vec<Request>, ServiceError>> for Answer {
     fn from(r: Result<Vec<Request>, ServiceError>) -> Self {
         match r {
             Ok(i) => Self::Requests(i),
@@ -61,7 +71,16 @@ impl From<Result<Vec<Request>, ServiceError>> for Answer<'static> {
     }
 }
 
-impl From<Result<(), ServiceError>> for Answer<'static> {
+impl From<Result<Vec<ApiToken>, ServiceError>> for Answer {
+    fn from(r: Result<Vec<ApiToken>, ServiceError>) -> Self {
+        match r {
+            Ok(i) => Self::Tokens(i),
+            Err(e) => Self::Error(e.to_string()),
+        }
+    }
+}
+
+impl From<Result<(), ServiceError>> for Answer {
     fn from(r: Result<(), ServiceError>) -> Self {
         match r {
             Ok(_) => Self::Success,
@@ -70,7 +89,7 @@ impl From<Result<(), ServiceError>> for Answer<'static> {
     }
 }
 
-impl From<Result<(), Box<dyn Error + Send + Sync>>> for Answer<'static> {
+impl From<Result<(), Box<dyn Error + Send + Sync>>> for Answer {
     fn from(r: Result<(), Box<(dyn Error + Send + Sync)>>) -> Self {
         match r {
             Ok(_) => Self::Success,
@@ -79,14 +98,11 @@ impl From<Result<(), Box<dyn Error + Send + Sync>>> for Answer<'static> {
     }
 }
 
-impl<'a> Answer<'a> {
+impl Answer {
     pub fn to_msg(&self) -> String {
         match self {
-            Answer::Config(c, s) => {
-                format!(
-                    "Your config:\n ```\n{conf}\n```",
-                    conf = escape(&utils::format_config(c, s))
-                )
+            Answer::Config(c) => {
+                format!("Your config:\n ```\n{conf}\n```", conf = escape(c))
             }
             Answer::Error(e) => format!("Error: {e}", e = escape(&e.to_string())),
             Answer::Success => "Success\\!".to_owned(),
@@ -107,8 +123,8 @@ impl<'a> Answer<'a> {
                 for request in requests {
                     let _ = writeln!(
                         res,
-                        "\t```{id}``` \\([author](tg://user?id={uid})\\) \\- {status}",
-                        id = escape(&request.id.to_string()),
+                        "\t{name} \\([author](tg://user?id={uid})\\) \\- {status}",
+                        name = escape(&request.name),
                         status = escape(&request.status.to_string()),
                         uid = request.telegram_id.unwrap_or_default()
                     );
@@ -126,7 +142,8 @@ impl<'a> Answer<'a> {
                 for p in s {
                     let _ = writeln!(
                         msg,
-                        "\t↑{tx} GB, ↓{rx} GB",
+                        "\t{name} ↑{tx} GB, ↓{rx} GB",
+                        name = escape(&p.name),
                         tx = fmt_bytes(p.tx),
                         rx = fmt_bytes(p.rx),
                     );
@@ -134,6 +151,63 @@ impl<'a> Answer<'a> {
                 msg.push_str("```");
                 msg
             }
+            Answer::Tokens(tokens) => {
+                let mut res = "Tokens:\n".to_string();
+                for t in tokens {
+                    let expires = t
+                        .expires_at
+                        .and_then(|t| OffsetDateTime::from_unix_timestamp(t).ok())
+                        .map(|t| format!(", expires {}", notifications::utc(&t)))
+                        .unwrap_or_default();
+                    let scopes = join_scopes(&t.scopes);
+                    let _ = writeln!(
+                        res,
+                        "\t{name} `{id}` \\- {details}",
+                        name = escape(&t.name),
+                        id = t.id,
+                        details = escape(&format!("{scopes}{expires}")),
+                    );
+                }
+                res
+            }
+            Answer::Audit(entries) => {
+                let short = |id: &uuid::Uuid| id.simple().to_string()[..8].to_owned();
+                let mut res = "Audit log:\n".to_string();
+                if entries.is_empty() {
+                    res.push_str("\tno entries");
+                }
+                for e in entries {
+                    let at = OffsetDateTime::from_unix_timestamp(e.created_at)
+                        .map(|t| notifications::utc(&t))
+                        .unwrap_or_default();
+                    let actor = e.actor_id.as_ref().map_or("system".to_owned(), short);
+                    let mut targets = Vec::new();
+                    if let Some(id) = &e.user_id {
+                        targets.push(format!("user {}", short(id)));
+                    }
+                    if let Some(id) = &e.config_id {
+                        targets.push(format!("config {}", short(id)));
+                    }
+                    let change = match (&e.before, &e.after) {
+                        (Some(b), Some(a)) => format!(": {b} -> {a}"),
+                        (Some(b), None) => format!(": {b}"),
+                        (None, Some(a)) => format!(": {a}"),
+                        (None, None) => String::new(),
+                    };
+                    let _ = writeln!(
+                        res,
+                        "\t{at} *{action}* by `{actor}` {details}",
+                        at = escape(&at),
+                        action = escape(&e.action),
+                        details = escape(&format!(
+                            "({frontend}) {targets}{change}",
+                            frontend = e.frontend,
+                            targets = targets.join(", ")
+                        )),
+                    );
+                }
+                res
+            }
         }
     }
 }
@@ -164,6 +238,9 @@ pub struct Config {
     admin_uid: i64,
     #[clap(long, short, env = "TELEGRAM_TOKEN", value_parser)]
     token: String,
+    /// Admin dialogues idle for longer start over from the main menu
+    #[clap(long, env = "DIALOGUE_TTL_MINUTES", default_value = "60", value_parser)]
+    dialogue_ttl_minutes: u32,
 }
 
 pub async fn start<T: TelegramDb + 'static>(
@@ -172,11 +249,12 @@ pub async fn start<T: TelegramDb + 'static>(
     db: T,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
     db.add_admin(config.admin_uid).await?;
-    let server_info = service.server_info().await?;
     tracing::info!("Starting command bot...");
 
     let bot = Bot::new(config.token).parse_mode(teloxide::types::ParseMode::MarkdownV2);
 
+    tokio::spawn(notifications::forward(bot.clone(), service.clone()));
+
     let b = bot.clone();
     let ignore_update = move |upd: Arc<Update>| {
         let b = b.clone();
@@ -189,19 +267,23 @@ pub async fn start<T: TelegramDb + 'static>(
         })
     };
 
+    let db = Arc::new(db);
+    let dialogues = storage::DbStorage::<T, admin::State>::new(
+        db.clone(),
+        time::Duration::minutes(config.dialogue_ttl_minutes as _),
+    );
+
     Dispatcher::builder(
         bot,
         dptree::entry()
             .chain(dptree::filter_async(register_user::<T>))
             .filter_map_async(get_user)
+            .branch(help::entry::<T>())
+            .branch(user::entry::<T>())
+            .branch(client::entry::<T>())
             .branch(admin::entry::<T>()),
     )
-    .dependencies(dptree::deps![
-        InMemStorage::<admin::State>::new(),
-        Arc::new(service),
-        Arc::new(db),
-        Arc::new(server_info)
-    ])
+    .dependencies(dptree::deps![dialogues.erase(), Arc::new(service), db])
     .default_handler(ignore_update)
     .build()
     .dispatch()
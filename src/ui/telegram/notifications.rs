@@ -0,0 +1,75 @@
+use teloxide::{
+    adaptors::DefaultParseMode, requests::Requester, types::ChatId, utils::markdown::escape, Bot,
+};
+use time::OffsetDateTime;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+use crate::service::{notifications::Notification, Wgcfg};
+
+fn text(n: &Notification) -> String {
+    match n {
+        Notification::QuotaExceeded { name, quota, .. } => escape(&format!(
+            "Config {name} used up its quota of {quota} and is suspended until the period resets"
+        )),
+        Notification::QuotaRestored { name, .. } => escape(&format!(
+            "Config {name} is within its quota again and works"
+        )),
+        Notification::ExpiryWarning {
+            name, expires_at, ..
+        } => escape(&format!(
+            "Config {name} expires at {}, ask an admin to extend it",
+            utc(expires_at)
+        )),
+        Notification::ConfigExpired { name, .. } => {
+            escape(&format!("Config {name} has expired and no longer works"))
+        }
+        Notification::ConfigExtended {
+            name,
+            expires_at: Some(t),
+            ..
+        } => escape(&format!("Config {name} was extended until {}", utc(t))),
+        Notification::ConfigExtended { name, .. } => {
+            escape(&format!("Config {name} no longer expires"))
+        }
+        Notification::ConfigDisabled { name, .. } => {
+            escape(&format!("Config {name} was suspended by an admin"))
+        }
+        Notification::ConfigEnabled {
+            name, active: true, ..
+        } => escape(&format!("Config {name} was resumed and works again")),
+        Notification::ConfigEnabled { name, .. } => escape(&format!(
+            "Config {name} was resumed by an admin, but stays off until its quota resets or it is extended"
+        )),
+    }
+}
+
+pub(super) fn utc(t: &OffsetDateTime) -> String {
+    format!("{} {:02}:{:02} UTC", t.date(), t.hour(), t.minute())
+}
+
+/// Sends service notifications to the telegram account of the affected user
+pub async fn forward(bot: DefaultParseMode<Bot>, service: Wgcfg) {
+    let mut rx = service.subscribe();
+    loop {
+        let n = match rx.recv().await {
+            Ok(n) => n,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("skipped {skipped} notifications");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        let chat = match service.telegram_id(n.user_id()).await {
+            Ok(Some(chat)) => chat,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!("lookup of telegram id failed: {e}");
+                continue;
+            }
+        };
+        if let Err(e) = bot.send_message(ChatId(chat), text(&n)).await {
+            warn!("sending notification failed: {e}");
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::{error::Error, marker::PhantomData, sync::Arc};
+
+use futures::future::BoxFuture;
+use serde::{de::DeserializeOwned, Serialize};
+use teloxide::{dispatching::dialogue::Storage, types::ChatId};
+use thiserror::Error;
+use time::{Duration, OffsetDateTime};
+
+use crate::traits::TelegramDb;
+
+#[derive(Debug, Error)]
+pub enum DbStorageError {
+    #[error("database error: {0}")]
+    Database(Box<dyn Error + Send + Sync>),
+    #[error("invalid dialogue state: {0}")]
+    Serde(#[from] serde_json::Error),
+}
+
+/// Dialogue states stored as JSON in the database, so they survive
+/// restarts. A dialogue untouched for longer than `ttl` is dropped and starts
+/// over from the default state.
+pub struct DbStorage<T, D> {
+    db: Arc<T>,
+    ttl: Duration,
+    state: PhantomData<fn() -> D>,
+}
+
+impl<T, D> DbStorage<T, D> {
+    pub fn new(db: Arc<T>, ttl: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            db,
+            ttl,
+            state: PhantomData,
+        })
+    }
+}
+
+impl<T, D> Storage<D> for DbStorage<T, D>
+where
+    T: TelegramDb + 'static,
+    D: Serialize + DeserializeOwned + Send + 'static,
+{
+    type Error = DbStorageError;
+
+    fn remove_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            self.db
+                .rm_dialogue(chat_id.0)
+                .await
+                .map_err(DbStorageError::Database)
+        })
+    }
+
+    fn update_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+        dialogue: D,
+    ) -> BoxFuture<'static, Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            let state = serde_json::to_string(&dialogue)?;
+            let now = OffsetDateTime::now_utc().unix_timestamp();
+            self.db
+                .set_dialogue(chat_id.0, &state, now)
+                .await
+                .map_err(DbStorageError::Database)
+        })
+    }
+
+    fn get_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<Option<D>, Self::Error>> {
+        Box::pin(async move {
+            let Some((state, updated_at)) = self
+                .db
+                .dialogue(chat_id.0)
+                .await
+                .map_err(DbStorageError::Database)?
+            else {
+                return Ok(None);
+            };
+            let expired =
+                OffsetDateTime::now_utc().unix_timestamp() - updated_at > self.ttl.whole_seconds();
+            // a state that no longer parses, e.g. after an upgrade, is dropped
+            // the same way as an abandoned one
+            match serde_json::from_str(&state) {
+                Ok(state) if !expired => Ok(Some(state)),
+                _ => {
+                    self.db
+                        .rm_dialogue(chat_id.0)
+                        .await
+                        .map_err(DbStorageError::Database)?;
+                    Ok(None)
+                }
+            }
+        })
+    }
+}
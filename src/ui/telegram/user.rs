@@ -1,21 +1,29 @@
 use std::{error::Error, net::Ipv4Addr, sync::Arc};
 
+use time::OffsetDateTime;
+use uuid::Uuid;
+
 use teloxide::{
     adaptors::DefaultParseMode,
     dispatching::{DpHandlerDescription, HandlerExt, UpdateFilterExt},
+    payloads::SendMessageSetters,
     prelude::{DependencyMap, Endpoint},
     requests::Requester,
-    types::{Message, Update},
-    utils::command::BotCommands,
+    types::{ChatId, Message, Update},
+    utils::{command::BotCommands, markdown::escape},
     Bot,
 };
 
 use crate::{
-    service::{Association, Wgcfg},
+    service::{
+        auth::{parse_scopes, Scope},
+        expiry::parse_duration,
+        Association, Request, ServiceError, User, Wgcfg,
+    },
     traits::TelegramDb,
 };
 
-use super::Answer;
+use super::{admin, Answer};
 
 #[derive(BotCommands, Clone)]
 #[command(rename_rule = "lowercase", description = "User:")]
@@ -32,6 +40,18 @@ pub enum Command {
     Unpair(Ipv4Addr),
     #[command(description = "currently paired clients")]
     Pairs,
+    #[command(description = "token for the HTTP API")]
+    ApiToken,
+    #[command(description = "revoke all tokens from /apitoken")]
+    EndSessions,
+    #[command(
+        description = "personal API token: name [read,configs:write,admin] [lifetime like 30d]"
+    )]
+    NewToken(String),
+    #[command(description = "your personal API tokens")]
+    Tokens,
+    #[command(description = "revoke a personal API token by id")]
+    RevokeToken(Uuid),
 }
 
 async fn handler<T: TelegramDb + 'static>(
@@ -39,13 +59,33 @@ async fn handler<T: TelegramDb + 'static>(
     message: Message,
     command: Command,
     service: Arc<Wgcfg>,
+    user: User,
 ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
     match command {
-        Command::Request => {
-            let answer: Answer = service.request_config(message.chat.id.0).await.into();
-            bot.send_message(message.chat.id, answer.to_msg()).await?;
+        Command::Request | Command::RequestWithKey(_) => {
+            let key = match command {
+                Command::RequestWithKey(key) => Some(key),
+                _ => None,
+            };
+            let name = message
+                .chat
+                .username()
+                .or(message.chat.first_name())
+                .unwrap_or("telegram")
+                .to_owned();
+
+            match service.request_config(&user, name, key).await {
+                Ok(request) => {
+                    bot.send_message(message.chat.id, "Request sent, wait for approval")
+                        .await?;
+                    notify_admins(&bot, &service, request).await;
+                }
+                Err(e) => {
+                    let answer: Answer = Err::<(), _>(e).into();
+                    bot.send_message(message.chat.id, answer.to_msg()).await?;
+                }
+            }
         }
-        Command::RequestWithKey(_) => {}
         Command::Pair(token) => {
             let answer: Answer = service
                 .create_association(token, Association::Telegram(message.chat.id.0))
@@ -70,8 +110,67 @@ async fn handler<T: TelegramDb + 'static>(
 
             bot.send_message(message.chat.id, answer.to_msg()).await?;
         }
+        Command::ApiToken => match service.api_token(&user).await {
+            Ok(token) => {
+                bot.send_message(
+                    message.chat.id,
+                    format!(
+                        "Send it as `Authorization: Bearer <token>`:\n`{}`",
+                        escape(&token)
+                    ),
+                )
+                .await?;
+            }
+            Err(e) => {
+                let answer: Answer = Err::<(), _>(e).into();
+                bot.send_message(message.chat.id, answer.to_msg()).await?;
+            }
+        },
+        Command::EndSessions => {
+            let answer: Answer = service.end_api_sessions(&user, user.id).await.into();
+
+            bot.send_message(message.chat.id, answer.to_msg()).await?;
+        }
+        Command::NewToken(args) => {
+            let res = match parse_new_token(&args) {
+                Ok((name, scopes, lifetime)) => {
+                    let expires_at = lifetime.map(|d| OffsetDateTime::now_utc() + d);
+                    service
+                        .create_api_token(&user, name, scopes, expires_at)
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+            match res {
+                Ok((id, secret)) => {
+                    bot.send_message(
+                        message.chat.id,
+                        format!(
+                            "Token {} created, it is shown only once:\n`{}`",
+                            escape(&id.to_string()),
+                            escape(&secret)
+                        ),
+                    )
+                    .await?;
+                }
+                Err(e) => {
+                    let answer: Answer = Err::<(), _>(e).into();
+                    bot.send_message(message.chat.id, answer.to_msg()).await?;
+                }
+            }
+        }
+        Command::Tokens => {
+            let answer: Answer = service.api_tokens(&user).await.into();
+
+            bot.send_message(message.chat.id, answer.to_msg()).await?;
+        }
+        Command::RevokeToken(id) => {
+            let answer: Answer = service.revoke_api_token(&user, id).await.into();
+
+            bot.send_message(message.chat.id, answer.to_msg()).await?;
+        }
         Command::MyRequests => {
-            let answer: Answer = service.requests_by_uid(message.chat.id.0).await.into();
+            let answer: Answer = service.requests_by_uid(&user).await.into();
 
             bot.send_message(message.chat.id, answer.to_msg()).await?;
         }
@@ -80,6 +179,52 @@ async fn handler<T: TelegramDb + 'static>(
     Ok(())
 }
 
+/// `name [scopes] [lifetime]`, only `read` when the scopes are missing
+fn parse_new_token(
+    args: &str,
+) -> Result<(String, Vec<Scope>, Option<time::Duration>), ServiceError> {
+    let mut parts = args.split_whitespace();
+    let Some(name) = parts.next() else {
+        return Err(ServiceError::InvalidScope(String::new()));
+    };
+    let mut scopes = vec![Scope::Read];
+    let mut lifetime = None;
+    for part in parts {
+        match parse_duration(part) {
+            Some(d) => lifetime = Some(d),
+            None => scopes = parse_scopes(part)?,
+        }
+    }
+    Ok((name.to_owned(), scopes, lifetime))
+}
+
+async fn notify_admins(bot: &DefaultParseMode<Bot>, service: &Wgcfg, request: Request) {
+    let admins = match service.admin_telegram_ids().await {
+        Ok(admins) => admins,
+        Err(e) => {
+            tracing::warn!("can't notify admins about request {}: {e}", request.id);
+            return;
+        }
+    };
+    let text = format!(
+        "New config request from [{name}](tg://user?id={uid})",
+        name = escape(&request.name),
+        uid = request.telegram_id.unwrap_or_default()
+    );
+    for admin in admins {
+        if let Err(e) = bot
+            .send_message(ChatId(admin), &text)
+            .reply_markup(admin::request_keyboard(&request))
+            .await
+        {
+            tracing::warn!(
+                "can't notify admin {admin} about request {}: {e}",
+                request.id
+            );
+        }
+    }
+}
+
 pub fn entry<T: TelegramDb + 'static>() -> Endpoint<
     'static,
     DependencyMap,
@@ -1,30 +1,27 @@
 use axum::{
     extract::{ConnectInfo, Extension},
+    http::StatusCode,
     response::IntoResponse,
     routing::{get, put},
     Json, Router,
 };
 use clap::Parser;
-use std::{
-    net::{IpAddr, SocketAddr},
-    sync::Arc,
-};
+use std::{net::SocketAddr, sync::Arc};
 
-use super::response::*;
+use super::{metrics::MetricsToken, response::*};
 use crate::service::Wgcfg;
 
 async fn status(
     Extension(service): Extension<Arc<Wgcfg>>,
     ConnectInfo(info): ConnectInfo<SocketAddr>,
 ) -> impl IntoResponse {
-    let _ip = match info.ip() {
-        IpAddr::V4(ip) => ip,
-        _ => unimplemented!(),
-    };
+    let iface = service
+        .interface_by_addr(info.ip())
+        .unwrap_or_else(|| service.primary_interface());
 
     Json(
         service
-            .server_info()
+            .server_info(&iface.name)
             .await
             .map(|info| ServiceInfo {
                 addr: info.addr,
@@ -39,53 +36,28 @@ async fn set_routing(
     Extension(service): Extension<Arc<Wgcfg>>,
     ConnectInfo(info): ConnectInfo<SocketAddr>,
 ) -> impl IntoResponse {
-    let ip = match info.ip() {
-        IpAddr::V4(ip) => ip,
-        _ => unimplemented!(),
-    };
-
-    Json(
-        service
-            .change_settings(ip, payload.double_vpn)
-            .await
-            .map_err(|e| e.to_string()),
-    )
-}
-
-async fn new_client(
-    Json(payload): Json<NewClient>,
-    Extension(service): Extension<Arc<Wgcfg>>,
-    ConnectInfo(info): ConnectInfo<SocketAddr>,
-) -> impl IntoResponse {
-    let _ip = match info.ip() {
-        IpAddr::V4(ip) => ip,
-        _ => unimplemented!(),
+    let exit = match payload.exit() {
+        Ok(exit) => exit,
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, Json(Err::<(), _>(e.to_owned())));
+        }
     };
-
-    todo!()
-    /*
-    let client = service.new_config("test".to_string(), payload.key).await;
-    Json(
-        client
-            .map(|c| NewClientResponse {
-                ip: c.ip,
-                priv_key: c.priv_key,
-            })
-            .map_err(|e| e.to_string()),
+    (
+        StatusCode::OK,
+        Json(
+            service
+                .change_settings_by_addr(info.ip(), exit.as_deref())
+                .await
+                .map_err(|e| e.to_string()),
+        ),
     )
-    */
 }
 
 async fn pair_token(
     Extension(service): Extension<Arc<Wgcfg>>,
     ConnectInfo(info): ConnectInfo<SocketAddr>,
 ) -> impl IntoResponse {
-    let ip = match info.ip() {
-        IpAddr::V4(ip) => ip,
-        _ => unimplemented!(),
-    };
-
-    let code = service.pair_code(ip).await;
+    let code = service.pair_code_by_addr(info.ip()).await;
 
     Json(code.map_err(|e| e.to_string()))
 }
@@ -94,16 +66,25 @@ async fn pair_token(
 pub struct Config {
     #[clap(long, short, env = "LISTEN_ADDR", value_parser)]
     listen_addr: SocketAddr,
+    /// Bearer token for `/metrics`, the endpoint is off without one
+    #[clap(long, env = "METRICS_TOKEN", value_parser)]
+    metrics_token: Option<String>,
 }
 
 pub async fn start(
     config: Config,
     service: Wgcfg,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let app = Router::new()
+    let mut app = Router::new()
         .route("/settings", put(set_routing))
         .route("/pair", get(pair_token))
-        .layer(Extension(service));
+        .nest("/api", super::rest::routes());
+    if let Some(token) = config.metrics_token {
+        app = app
+            .route("/metrics", get(super::metrics::metrics))
+            .layer(Extension(MetricsToken(token.into())));
+    }
+    let app = app.layer(Extension(Arc::new(service)));
 
     axum::Server::bind(&config.listen_addr)
         .serve(app.into_make_service_with_connect_info::<SocketAddr, _>())
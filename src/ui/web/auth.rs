@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    extract::{FromRequest, RequestParts},
+    http::header::AUTHORIZATION,
+};
+
+use super::error::ApiError;
+use crate::service::{auth::Scope, ServiceError, User, Wgcfg};
+
+/// User and scopes of the `Authorization: Bearer` token of the request
+pub struct Auth {
+    user: User,
+    scopes: Vec<Scope>,
+}
+
+impl Auth {
+    /// The user if the token has `scope`
+    pub fn require(&self, scope: Scope) -> Result<&User, ApiError> {
+        if !self.scopes.contains(&scope) {
+            return Err(ServiceError::AccessDenied.into());
+        }
+        Ok(&self.user)
+    }
+
+    /// The user for requests any valid token may make
+    pub fn user(&self) -> &User {
+        &self.user
+    }
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Auth {
+    type Rejection = ApiError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let token = req
+            .headers()
+            .and_then(|h| h.get(AUTHORIZATION))
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or(ServiceError::InvalidToken)?
+            .to_owned();
+        let service = req
+            .extensions()
+            .and_then(|e| e.get::<Arc<Wgcfg>>())
+            .cloned()
+            .ok_or_else(|| ServiceError::Unexpected("service is not set up".to_owned()))?;
+
+        let (user, scopes) = service.authenticate(&token).await?;
+        Ok(Self { user, scopes })
+    }
+}
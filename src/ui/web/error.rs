@@ -0,0 +1,51 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+
+use crate::service::ServiceError;
+
+/// [`ServiceError`] answered with the matching status and `{"error": ...}`
+pub struct ApiError(pub ServiceError);
+
+impl From<ServiceError> for ApiError {
+    fn from(e: ServiceError) -> Self {
+        Self(e)
+    }
+}
+
+impl ApiError {
+    fn status(&self) -> StatusCode {
+        match self.0 {
+            ServiceError::NotFound => StatusCode::NOT_FOUND,
+            ServiceError::AccessDenied => StatusCode::FORBIDDEN,
+            ServiceError::InvalidToken | ServiceError::InvalidJwt(_) => StatusCode::UNAUTHORIZED,
+            ServiceError::InvalidKey
+            | ServiceError::InvalidProfile(_)
+            | ServiceError::InvalidPairCode
+            | ServiceError::InvalidExit(_)
+            | ServiceError::UnknownInterface(_)
+            | ServiceError::InvalidQuota(_)
+            | ServiceError::InvalidScope(_)
+            | ServiceError::InvalidRole(_)
+            | ServiceError::InvalidExpiry(_)
+            | ServiceError::InvalidQuery(_) => StatusCode::BAD_REQUEST,
+            ServiceError::ClientAlreadyExists
+            | ServiceError::RequestPending
+            | ServiceError::RequestProcessed => StatusCode::CONFLICT,
+            ServiceError::IpPoolExhausted => StatusCode::SERVICE_UNAVAILABLE,
+            ServiceError::RulesError(_)
+            | ServiceError::Database(_)
+            | ServiceError::Unexpected(_)
+            | ServiceError::InvalidJwtSecret(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> axum::response::Response {
+        let status = self.status();
+        if status == StatusCode::INTERNAL_SERVER_ERROR {
+            tracing::warn!("api request failed with error: {}", self.0);
+        }
+        (status, Json(json!({ "error": self.0.to_string() }))).into_response()
+    }
+}
@@ -0,0 +1,227 @@
+use std::{fmt::Write, sync::Arc};
+
+use axum::{
+    extract::Extension,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::IntoResponse,
+};
+use time::OffsetDateTime;
+
+use crate::{
+    metrics,
+    service::{ServiceError, Wgcfg},
+};
+
+/// Escapes a label value for the Prometheus text format
+fn label(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn help(out: &mut String, name: &str, kind: &str, text: &str) {
+    let _ = writeln!(out, "# HELP {name} {text}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+async fn render(service: &Wgcfg) -> Result<String, ServiceError> {
+    let mut out = String::new();
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    let stats = service.config_stats().await?;
+    let labels = |s: &crate::database::ConfigStats| {
+        format!(
+            "config=\"{}\",config_id=\"{}\",user_id=\"{}\",interface=\"{}\"",
+            label(&s.name),
+            s.config_id,
+            s.user_id,
+            label(&s.interface)
+        )
+    };
+    help(
+        &mut out,
+        "vpn_config_tx_bytes",
+        "counter",
+        "Bytes sent to the peer",
+    );
+    for s in &stats {
+        let _ = writeln!(out, "vpn_config_tx_bytes{{{}}} {}", labels(s), s.tx);
+    }
+    help(
+        &mut out,
+        "vpn_config_rx_bytes",
+        "counter",
+        "Bytes received from the peer",
+    );
+    for s in &stats {
+        let _ = writeln!(out, "vpn_config_rx_bytes{{{}}} {}", labels(s), s.rx);
+    }
+    help(
+        &mut out,
+        "vpn_config_last_handshake_age_seconds",
+        "gauge",
+        "Seconds since the latest handshake, missing for peers that never connected",
+    );
+    for s in &stats {
+        if let Some(t) = s.last_handshake {
+            let _ = writeln!(
+                out,
+                "vpn_config_last_handshake_age_seconds{{{}}} {}",
+                labels(s),
+                now - t
+            );
+        }
+    }
+
+    help(
+        &mut out,
+        "vpn_configs",
+        "gauge",
+        "Number of configs per state",
+    );
+    for (state, n) in service.config_states().await? {
+        let _ = writeln!(out, "vpn_configs{{state=\"{state}\"}} {n}");
+    }
+
+    let pools = service.pool_usage().await?;
+    help(
+        &mut out,
+        "vpn_pool_used_addresses",
+        "gauge",
+        "Addresses handed out from the pool",
+    );
+    help(
+        &mut out,
+        "vpn_pool_capacity_addresses",
+        "gauge",
+        "Addresses the pool can hand out",
+    );
+    for (name, usage, usage6) in pools {
+        let name = label(&name);
+        for (family, usage) in [("ipv4", Some(usage)), ("ipv6", usage6)] {
+            let Some(usage) = usage else {
+                continue;
+            };
+            let _ = writeln!(
+                out,
+                "vpn_pool_used_addresses{{interface=\"{name}\",family=\"{family}\"}} {}",
+                usage.used
+            );
+            let _ = writeln!(
+                out,
+                "vpn_pool_capacity_addresses{{interface=\"{name}\",family=\"{family}\"}} {}",
+                usage.capacity
+            );
+        }
+    }
+
+    let ops = metrics::netlink_ops();
+    help(
+        &mut out,
+        "vpn_netlink_operations_total",
+        "counter",
+        "Netlink requests per operation",
+    );
+    for (op, m) in &ops {
+        let _ = writeln!(
+            out,
+            "vpn_netlink_operations_total{{op=\"{op}\"}} {}",
+            m.count
+        );
+    }
+    help(
+        &mut out,
+        "vpn_netlink_errors_total",
+        "counter",
+        "Failed netlink requests per operation",
+    );
+    for (op, m) in &ops {
+        let _ = writeln!(out, "vpn_netlink_errors_total{{op=\"{op}\"}} {}", m.errors);
+    }
+    help(
+        &mut out,
+        "vpn_netlink_duration_seconds",
+        "summary",
+        "Latency of netlink requests per operation",
+    );
+    for (op, m) in &ops {
+        let _ = writeln!(
+            out,
+            "vpn_netlink_duration_seconds_sum{{op=\"{op}\"}} {}",
+            m.seconds
+        );
+        let _ = writeln!(
+            out,
+            "vpn_netlink_duration_seconds_count{{op=\"{op}\"}} {}",
+            m.count
+        );
+    }
+
+    let (cycles, last) = metrics::stats_cycles();
+    help(
+        &mut out,
+        "vpn_stats_cycle_duration_seconds",
+        "summary",
+        "Duration of stats worker cycles",
+    );
+    let _ = writeln!(
+        out,
+        "vpn_stats_cycle_duration_seconds_sum {}",
+        cycles.seconds
+    );
+    let _ = writeln!(
+        out,
+        "vpn_stats_cycle_duration_seconds_count {}",
+        cycles.count
+    );
+    help(
+        &mut out,
+        "vpn_stats_last_cycle_duration_seconds",
+        "gauge",
+        "Duration of the latest stats worker cycle",
+    );
+    let _ = writeln!(
+        out,
+        "vpn_stats_last_cycle_duration_seconds {}",
+        last.as_secs_f64()
+    );
+    help(
+        &mut out,
+        "vpn_stats_errors_total",
+        "counter",
+        "Failed steps of stats worker cycles",
+    );
+    let _ = writeln!(out, "vpn_stats_errors_total {}", cycles.errors);
+
+    Ok(out)
+}
+
+/// Bearer token scrapers have to send, series are labelled with config
+/// names and user ids
+#[derive(Clone)]
+pub struct MetricsToken(pub Arc<str>);
+
+/// Prometheus text exposition of the service
+pub async fn metrics(
+    Extension(service): Extension<Arc<Wgcfg>>,
+    Extension(token): Extension<MetricsToken>,
+    request: HeaderMap,
+) -> impl IntoResponse {
+    let authorized = request
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|t| t == &*token.0);
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; version=0.0.4"),
+    );
+    if !authorized {
+        return (StatusCode::UNAUTHORIZED, headers, String::new());
+    }
+    match render(&service).await {
+        Ok(body) => (StatusCode::OK, headers, body),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, headers, e.to_string()),
+    }
+}
@@ -1,3 +1,7 @@
 mod api;
+mod auth;
+mod error;
+mod metrics;
 mod response;
+mod rest;
 pub use api::*;
@@ -1,6 +1,18 @@
-use std::net::{IpAddr, SocketAddr};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
 
+use base64::{engine::general_purpose::STANDARD, Engine};
 use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    database::{FullConfig, UserSummary},
+    roles::Role,
+    service::{
+        audit::AuditEntry, auth::ApiToken, configs::Config, exits::Exit, presence::Presence,
+        profiles::Profile, InterfaceOverview, PeerInfo, Request,
+    },
+};
 
 #[derive(Serialize)]
 pub struct Status {
@@ -9,18 +21,146 @@ pub struct Status {
 
 #[derive(Deserialize)]
 pub struct ChangeRouting {
-    pub double_vpn: bool,
+    /// Name of the exit, direct when missing
+    pub exit: Option<String>,
+    /// Body of clients from before exits existed, `true` is the `double` exit
+    pub double_vpn: Option<bool>,
+}
+
+impl ChangeRouting {
+    /// Name of the exit, `None` for direct
+    pub fn exit(self) -> Result<Option<String>, &'static str> {
+        match (self.exit, self.double_vpn) {
+            (Some(_), Some(_)) => Err("exit and double_vpn are mutually exclusive"),
+            (exit, None) => Ok(exit),
+            (None, Some(double)) => Ok(double.then(|| "double".to_owned())),
+        }
+    }
 }
 
 #[derive(Deserialize)]
-pub struct NewClient {
+pub struct NewConfig {
+    pub name: String,
+    /// Public key of the client, a keypair is generated when missing
     pub key: Option<String>,
+    /// Primary interface when missing
+    pub interface: Option<String>,
+    #[serde(default = "default_psk")]
+    pub psk: bool,
+}
+
+fn default_psk() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+pub struct RenameConfig {
+    pub name: String,
+}
+
+/// Without a key a new keypair is generated
+#[derive(Deserialize)]
+pub struct RotateKey {
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrafficPeriod {
+    Hour,
+    Day,
+    Month,
+}
+
+#[derive(Deserialize)]
+pub struct TrafficQuery {
+    pub period: TrafficPeriod,
+    /// Buckets of this many days back from now
+    #[serde(default = "default_days")]
+    pub days: i64,
+}
+
+fn default_days() -> i64 {
+    30
+}
+
+#[derive(Serialize)]
+pub struct UserInfo {
+    pub id: Uuid,
+    pub permissions: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct ConfigInfo {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub interface: String,
+    pub ip: Ipv4Addr,
+    pub ip6: Option<Ipv6Addr>,
+    pub pub_key: String,
+    pub profile_id: Option<Uuid>,
+    pub exit_id: Option<Uuid>,
+    /// See [`Config::state`]
+    pub state: &'static str,
+    pub expires_at: Option<i64>,
+}
+
+impl From<&Config> for ConfigInfo {
+    fn from(c: &Config) -> Self {
+        Self {
+            id: c.id,
+            user_id: c.user_id,
+            name: c.name.clone(),
+            interface: c.interface.clone(),
+            ip: c.ip,
+            ip6: c.ip6,
+            pub_key: STANDARD.encode(c.pub_key),
+            profile_id: c.profile_id,
+            exit_id: c.exit_id,
+            state: c.state(),
+            expires_at: c.expires_at,
+        }
+    }
 }
 
 #[derive(Serialize)]
-pub struct NewClientResponse {
-    pub ip: IpAddr,
-    pub priv_key: Option<String>,
+pub struct ConfigDetails {
+    #[serde(flatten)]
+    pub config: ConfigInfo,
+    pub tx: u64,
+    pub rx: u64,
+    pub last_handshake: Option<i64>,
+    pub endpoint: Option<String>,
+    /// `online`, `last seen ...` or `never connected`
+    pub status: String,
+}
+
+impl From<&FullConfig> for ConfigDetails {
+    fn from(c: &FullConfig) -> Self {
+        Self {
+            config: (&c.config).into(),
+            tx: c.stats.tx,
+            rx: c.stats.rx,
+            last_handshake: c.stats.last_handshake,
+            endpoint: c.stats.endpoint.clone(),
+            status: Presence::new(c.stats.last_handshake, OffsetDateTime::now_utc()).to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct KeyInfo {
+    pub key: String,
+    pub name: String,
+}
+
+#[derive(Serialize)]
+pub struct Traffic {
+    pub start: i64,
+    pub tx: u64,
+    pub rx: u64,
 }
 
 #[derive(Serialize)]
@@ -28,3 +168,288 @@ pub struct ServiceInfo {
     pub addr: SocketAddr,
     pub pub_key: String,
 }
+
+#[derive(Serialize)]
+pub struct TokenInfo {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+}
+
+impl From<&ApiToken> for TokenInfo {
+    fn from(t: &ApiToken) -> Self {
+        Self {
+            id: t.id,
+            user_id: t.user_id,
+            name: t.name.clone(),
+            scopes: t.scopes.iter().map(|s| s.to_string()).collect(),
+            created_at: t.created_at,
+            expires_at: t.expires_at,
+        }
+    }
+}
+
+/// Body creating a profile, exit or role in the format the bot uses
+#[derive(Deserialize)]
+pub struct Spec {
+    pub spec: String,
+}
+
+#[derive(Deserialize)]
+pub struct SetProfile {
+    /// The default profile when missing
+    pub profile_id: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct SetExit {
+    /// Direct when missing
+    pub exit_id: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct SetQuota {
+    /// `<GB> [day|month]` like in the bot, the cap is removed when missing
+    pub limit: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SetExpiry {
+    /// Unix timestamp, the config never expires when missing
+    pub expires_at: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct SetDisabled {
+    pub disabled: bool,
+}
+
+#[derive(Deserialize)]
+pub struct NewRequest {
+    pub name: String,
+    /// Public key of the client, a keypair is generated on approval when missing
+    pub key: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    pub user_id: Option<Uuid>,
+    pub config_id: Option<Uuid>,
+    #[serde(default)]
+    pub offset: u32,
+    #[serde(default = "default_limit")]
+    pub limit: u32,
+}
+
+fn default_limit() -> u32 {
+    50
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsWindow {
+    Today,
+    #[default]
+    Week,
+    Month,
+    All,
+}
+
+#[derive(Deserialize)]
+pub struct StatsQuery {
+    #[serde(default)]
+    pub window: StatsWindow,
+}
+
+#[derive(Serialize)]
+pub struct PairCode {
+    pub code: String,
+}
+
+#[derive(Serialize)]
+pub struct ProfileInfo {
+    pub id: Uuid,
+    pub name: String,
+    /// The profile in the format of [`Spec`]
+    pub spec: String,
+}
+
+impl From<&Profile> for ProfileInfo {
+    fn from(p: &Profile) -> Self {
+        Self {
+            id: p.id,
+            name: p.name.clone(),
+            spec: p.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ExitInfo {
+    pub id: Uuid,
+    pub name: String,
+    pub table: u32,
+    pub fwmark: Option<u32>,
+    pub priority: u32,
+}
+
+impl From<&Exit> for ExitInfo {
+    fn from(e: &Exit) -> Self {
+        Self {
+            id: e.id,
+            name: e.name.clone(),
+            table: e.table,
+            fwmark: e.fwmark,
+            priority: e.priority,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct RequestInfo {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub status: String,
+    pub name: String,
+    pub config_id: Option<Uuid>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl From<&Request> for RequestInfo {
+    fn from(r: &Request) -> Self {
+        Self {
+            id: r.id,
+            user_id: r.user_id,
+            status: r.status.to_string(),
+            name: r.name.clone(),
+            config_id: r.config_id,
+            created_at: r.created_at.unix_timestamp(),
+            updated_at: r.updated_at.unix_timestamp(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct UserSummaryInfo {
+    pub id: Uuid,
+    pub telegram_id: Option<i64>,
+    pub roles: Vec<Uuid>,
+    pub configs: usize,
+    pub tx: u64,
+    pub rx: u64,
+}
+
+impl From<&UserSummary> for UserSummaryInfo {
+    fn from(u: &UserSummary) -> Self {
+        Self {
+            id: u.id,
+            telegram_id: u.telegram_id,
+            roles: u.roles.clone(),
+            configs: u.configs,
+            tx: u.tx,
+            rx: u.rx,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct RoleInfo {
+    pub id: Uuid,
+    pub name: String,
+    pub permissions: Vec<String>,
+}
+
+impl From<&Role> for RoleInfo {
+    fn from(r: &Role) -> Self {
+        Self {
+            id: r.id,
+            name: r.name.clone(),
+            permissions: r.permissions.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AuditInfo {
+    pub id: Uuid,
+    pub created_at: i64,
+    pub actor_id: Option<Uuid>,
+    pub frontend: String,
+    pub action: String,
+    pub user_id: Option<Uuid>,
+    pub config_id: Option<Uuid>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl From<AuditEntry> for AuditInfo {
+    fn from(e: AuditEntry) -> Self {
+        Self {
+            id: e.id,
+            created_at: e.created_at,
+            actor_id: e.actor_id,
+            frontend: e.frontend.to_string(),
+            action: e.action,
+            user_id: e.user_id,
+            config_id: e.config_id,
+            before: e.before,
+            after: e.after,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct InterfaceInfo {
+    pub name: String,
+    pub pub_key: String,
+    pub endpoint: SocketAddr,
+    pub listen_port: u16,
+    pub peers: usize,
+    pub online: usize,
+    pub pool: String,
+    pub pool6: Option<String>,
+    pub tx: u64,
+    pub rx: u64,
+}
+
+impl From<InterfaceOverview> for InterfaceInfo {
+    fn from(i: InterfaceOverview) -> Self {
+        Self {
+            name: i.name,
+            pub_key: i.pub_key,
+            endpoint: i.endpoint,
+            listen_port: i.listen_port,
+            peers: i.peers,
+            online: i.online,
+            pool: i.pool.to_string(),
+            pool6: i.pool6.map(|p| p.to_string()),
+            tx: i.tx,
+            rx: i.rx,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PeerTraffic {
+    pub config_id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub tx: u64,
+    pub rx: u64,
+}
+
+impl From<PeerInfo> for PeerTraffic {
+    fn from(p: PeerInfo) -> Self {
+        Self {
+            config_id: p.config_id,
+            user_id: p.user_id,
+            name: p.name,
+            tx: p.tx,
+            rx: p.rx,
+        }
+    }
+}
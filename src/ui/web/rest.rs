@@ -0,0 +1,688 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Extension, Path, Query},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use super::{auth::Auth, error::ApiError, response::*};
+use crate::{
+    database::{Period, TrafficPoint},
+    roles,
+    service::{
+        audit::AuditFilter,
+        auth::Scope,
+        quotas::{parse_limit, QuotaTarget},
+        traffic::TrafficWindow,
+        ServiceError, Wgcfg,
+    },
+    utils,
+};
+
+type ApiResult<T> = Result<T, ApiError>;
+
+async fn me(auth: Auth) -> Json<UserInfo> {
+    let user = auth.user();
+    Json(UserInfo {
+        id: user.id,
+        permissions: user.permissions.iter().map(|p| p.to_string()).collect(),
+    })
+}
+
+async fn configs(
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<Vec<ConfigInfo>>> {
+    let user = auth.require(Scope::Read)?;
+    let configs = service.configs(user.id).await?;
+    Ok(Json(configs.iter().map(ConfigInfo::from).collect()))
+}
+
+async fn create_config(
+    Json(payload): Json<NewConfig>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<(StatusCode, Json<ConfigDetails>)> {
+    let user = auth.require(Scope::ConfigsWrite)?;
+    let id = service
+        .new_config(
+            user,
+            payload.name,
+            payload.key,
+            payload.psk,
+            payload.interface.as_deref(),
+            None,
+        )
+        .await?;
+    let config = service.config(user, id).await?;
+    Ok((StatusCode::CREATED, Json((&config).into())))
+}
+
+async fn config(
+    Path(id): Path<Uuid>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<ConfigDetails>> {
+    let user = auth.require(Scope::Read)?;
+    let config = service.config(user, id).await?;
+    Ok(Json((&config).into()))
+}
+
+async fn rename_config(
+    Path(id): Path<Uuid>,
+    Json(payload): Json<RenameConfig>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<ConfigDetails>> {
+    let user = auth.require(Scope::ConfigsWrite)?;
+    service.rename_config(user, id, &payload.name).await?;
+    let config = service.config(user, id).await?;
+    Ok(Json((&config).into()))
+}
+
+async fn remove_config(
+    Path(id): Path<Uuid>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<StatusCode> {
+    let user = auth.require(Scope::ConfigsWrite)?;
+    service.rm_config(user, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The client config file as an attachment
+async fn config_file(
+    Path(id): Path<Uuid>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<(HeaderMap, String)> {
+    let user = auth.require(Scope::Read)?;
+    let config = service.config(user, id).await?;
+    let file = service.config_file(&config.config).await?;
+    Ok((attachment(&config.config.name), file))
+}
+
+/// Replaces the keypair of the config, responds with the new config file
+async fn rotate_key(
+    Path(id): Path<Uuid>,
+    Json(payload): Json<RotateKey>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<(HeaderMap, String)> {
+    let user = auth.require(Scope::ConfigsWrite)?;
+    let config = service.rotate_key(user, id, payload.key).await?;
+    let file = service.config_file(&config).await?;
+    Ok((attachment(&config.name), file))
+}
+
+fn attachment(name: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+    // the name is user input, leave it out when it does not fit in the header
+    if !name.contains('"') {
+        let disposition = format!("attachment; filename=\"{}.conf\"", name);
+        if let Ok(v) = HeaderValue::from_str(&disposition) {
+            headers.insert(header::CONTENT_DISPOSITION, v);
+        }
+    }
+    headers
+}
+
+/// Rejects a `days` too large to count back from now
+fn traffic_range(q: &TrafficQuery) -> Result<(Period, OffsetDateTime, OffsetDateTime), ApiError> {
+    let period = match q.period {
+        TrafficPeriod::Hour => Period::Hour,
+        TrafficPeriod::Day => Period::Day,
+        TrafficPeriod::Month => Period::Month,
+    };
+    let now = OffsetDateTime::now_utc();
+    let from = Duration::DAY
+        .checked_mul(q.days.max(0).try_into().unwrap_or(i32::MAX))
+        .and_then(|d| now.checked_sub(d))
+        .ok_or_else(|| ServiceError::InvalidQuery(format!("days={}", q.days)))?;
+    Ok((period, from, now))
+}
+
+fn traffic(points: Vec<TrafficPoint>) -> Json<Vec<Traffic>> {
+    Json(
+        points
+            .into_iter()
+            .map(|p| Traffic {
+                start: p.start,
+                tx: p.tx,
+                rx: p.rx,
+            })
+            .collect(),
+    )
+}
+
+async fn config_traffic(
+    Path(id): Path<Uuid>,
+    Query(q): Query<TrafficQuery>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<Vec<Traffic>>> {
+    let user = auth.require(Scope::Read)?;
+    let (period, from, to) = traffic_range(&q)?;
+    let points = service.config_traffic(user, id, period, from, to).await?;
+    Ok(traffic(points))
+}
+
+/// Traffic of all configs of the caller
+async fn user_traffic(
+    Query(q): Query<TrafficQuery>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<Vec<Traffic>>> {
+    let user = auth.require(Scope::Read)?;
+    let (period, from, to) = traffic_range(&q)?;
+    let points = service
+        .user_traffic(user, user.id, period, from, to)
+        .await?;
+    Ok(traffic(points))
+}
+
+async fn keys(
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<Vec<KeyInfo>>> {
+    let user = auth.require(Scope::Read)?;
+    let keys = service.keys(user).await?;
+    Ok(Json(
+        keys.into_iter()
+            .map(|k| KeyInfo {
+                key: STANDARD.encode(k.key),
+                name: k.name,
+            })
+            .collect(),
+    ))
+}
+
+async fn add_admin(
+    Path(id): Path<Uuid>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<StatusCode> {
+    let user = auth.require(Scope::Admin)?;
+    service.add_admin(user, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn remove_admin(
+    Path(id): Path<Uuid>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<StatusCode> {
+    let user = auth.require(Scope::Admin)?;
+    service.rm_admin(user, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn tokens(
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<Vec<TokenInfo>>> {
+    let user = auth.require(Scope::Read)?;
+    let tokens = service.api_tokens(user).await?;
+    Ok(Json(tokens.iter().map(TokenInfo::from).collect()))
+}
+
+/// Tokens of all users, for admins
+async fn all_tokens(
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<Vec<TokenInfo>>> {
+    let user = auth.require(Scope::Admin)?;
+    let tokens = service.all_api_tokens(user).await?;
+    Ok(Json(tokens.iter().map(TokenInfo::from).collect()))
+}
+
+/// Own tokens need `configs:write`, those of other users `admin`
+async fn revoke_token(
+    Path(id): Path<Uuid>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<StatusCode> {
+    let user = auth.require(Scope::ConfigsWrite)?;
+    if !service.api_tokens(user).await?.iter().any(|t| t.id == id) {
+        auth.require(Scope::Admin)?;
+    }
+    service.revoke_api_token(user, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn config_qr(
+    Path(id): Path<Uuid>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<(HeaderMap, Vec<u8>)> {
+    let user = auth.require(Scope::Read)?;
+    let config = service.config(user, id).await?;
+    let file = service.config_file(&config.config).await?;
+    let png =
+        utils::qr_code_png(file.as_bytes()).map_err(|e| ServiceError::Unexpected(e.to_string()))?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("image/png"));
+    Ok((headers, png))
+}
+
+async fn rotate_psk(
+    Path(id): Path<Uuid>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<StatusCode> {
+    let user = auth.require(Scope::ConfigsWrite)?;
+    service.rotate_psk(user, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn pair_code(
+    Path(id): Path<Uuid>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<PairCode>> {
+    let user = auth.require(Scope::ConfigsWrite)?;
+    let code = service.pair_code(user, id).await?;
+    Ok(Json(PairCode { code }))
+}
+
+async fn set_config_profile(
+    Path(id): Path<Uuid>,
+    Json(payload): Json<SetProfile>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<StatusCode> {
+    let user = auth.require(Scope::ConfigsWrite)?;
+    service
+        .set_config_profile(user, id, payload.profile_id)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_config_exit(
+    Path(id): Path<Uuid>,
+    Json(payload): Json<SetExit>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<StatusCode> {
+    let user = auth.require(Scope::ConfigsWrite)?;
+    service.set_config_exit(user, id, payload.exit_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_quota(
+    service: &Wgcfg,
+    auth: &Auth,
+    target: QuotaTarget,
+    limit: Option<&str>,
+) -> ApiResult<StatusCode> {
+    let user = auth.require(Scope::Admin)?;
+    let limit = match limit {
+        Some(l) => parse_limit(l)?,
+        None => None,
+    };
+    service.set_quota(user, target, limit).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn config_quota(
+    Path(id): Path<Uuid>,
+    Json(payload): Json<SetQuota>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<StatusCode> {
+    let target = QuotaTarget::Config(id);
+    set_quota(&service, &auth, target, payload.limit.as_deref()).await
+}
+
+async fn user_quota(
+    Path(id): Path<Uuid>,
+    Json(payload): Json<SetQuota>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<StatusCode> {
+    let target = QuotaTarget::User(id);
+    set_quota(&service, &auth, target, payload.limit.as_deref()).await
+}
+
+async fn set_expiry(
+    Path(id): Path<Uuid>,
+    Json(payload): Json<SetExpiry>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<StatusCode> {
+    let user = auth.require(Scope::Admin)?;
+    let expires_at = payload
+        .expires_at
+        .map(|t| OffsetDateTime::from_unix_timestamp(t).map_err(|_| ServiceError::InvalidExpiry(t)))
+        .transpose()?;
+    service.set_expiry(user, id, expires_at).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_disabled(
+    Path(id): Path<Uuid>,
+    Json(payload): Json<SetDisabled>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<StatusCode> {
+    let user = auth.require(Scope::Admin)?;
+    service.set_disabled(user, id, payload.disabled).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Suspends or resumes every config of the user
+async fn set_user_disabled(
+    Path(id): Path<Uuid>,
+    Json(payload): Json<SetDisabled>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<StatusCode> {
+    let user = auth.require(Scope::Admin)?;
+    service
+        .set_user_disabled(user, id, payload.disabled)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn profiles(
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<Vec<ProfileInfo>>> {
+    auth.require(Scope::Read)?;
+    let profiles = service.profiles().await?;
+    Ok(Json(profiles.iter().map(ProfileInfo::from).collect()))
+}
+
+async fn create_profile(
+    Json(payload): Json<Spec>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<(StatusCode, Json<ProfileInfo>)> {
+    let user = auth.require(Scope::Admin)?;
+    let id = service.create_profile(user, &payload.spec).await?;
+    let profile = service.profile(Some(id)).await?;
+    Ok((StatusCode::CREATED, Json((&profile).into())))
+}
+
+async fn remove_profile(
+    Path(id): Path<Uuid>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<StatusCode> {
+    let user = auth.require(Scope::Admin)?;
+    service.rm_profile(user, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn exits(
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<Vec<ExitInfo>>> {
+    auth.require(Scope::Read)?;
+    let exits = service.exits().await?;
+    Ok(Json(exits.iter().map(ExitInfo::from).collect()))
+}
+
+async fn create_exit(
+    Json(payload): Json<Spec>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<(StatusCode, Json<ExitInfo>)> {
+    let user = auth.require(Scope::Admin)?;
+    let id = service.create_exit(user, &payload.spec).await?;
+    let exit = service.exit(id).await?;
+    Ok((StatusCode::CREATED, Json((&exit).into())))
+}
+
+async fn remove_exit(
+    Path(id): Path<Uuid>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<StatusCode> {
+    let user = auth.require(Scope::Admin)?;
+    service.rm_exit(user, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Requests of the caller
+async fn requests(
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<Vec<RequestInfo>>> {
+    let user = auth.require(Scope::Read)?;
+    let requests = service.requests_by_uid(user).await?;
+    Ok(Json(requests.iter().map(RequestInfo::from).collect()))
+}
+
+async fn create_request(
+    Json(payload): Json<NewRequest>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<(StatusCode, Json<RequestInfo>)> {
+    let user = auth.require(Scope::ConfigsWrite)?;
+    let request = service
+        .request_config(user, payload.name, payload.key)
+        .await?;
+    Ok((StatusCode::CREATED, Json((&request).into())))
+}
+
+/// Pending requests of everyone
+async fn pending_requests(
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<Vec<RequestInfo>>> {
+    let user = auth.require(Scope::Admin)?;
+    let requests = service.requests(user).await?;
+    Ok(Json(requests.iter().map(RequestInfo::from).collect()))
+}
+
+async fn approve_request(
+    Path(id): Path<Uuid>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<RequestInfo>> {
+    let user = auth.require(Scope::Admin)?;
+    let request = service.approve_request(user, id).await?;
+    Ok(Json((&request).into()))
+}
+
+async fn decline_request(
+    Path(id): Path<Uuid>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<RequestInfo>> {
+    let user = auth.require(Scope::Admin)?;
+    let request = service.decline_request(user, id).await?;
+    Ok(Json((&request).into()))
+}
+
+async fn users(
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<Vec<UserSummaryInfo>>> {
+    let user = auth.require(Scope::Admin)?;
+    let users = service.users(user).await?;
+    Ok(Json(users.iter().map(UserSummaryInfo::from).collect()))
+}
+
+/// Users with the admin role
+async fn admins(
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<Vec<UserSummaryInfo>>> {
+    let user = auth.require(Scope::Admin)?;
+    let users = service.users(user).await?;
+    Ok(Json(
+        users
+            .iter()
+            .filter(|u| u.roles.contains(&roles::ADMIN))
+            .map(UserSummaryInfo::from)
+            .collect(),
+    ))
+}
+
+async fn user_summary(
+    Path(id): Path<Uuid>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<UserSummaryInfo>> {
+    let user = auth.require(Scope::Admin)?;
+    let summary = service.user_summary(user, id).await?;
+    Ok(Json((&summary).into()))
+}
+
+/// Revokes the tokens from `/apitoken` of the user, the caller's own ones
+/// included when it is the same user
+async fn end_sessions(
+    Path(id): Path<Uuid>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<StatusCode> {
+    let user = auth.require(Scope::Admin)?;
+    service.end_api_sessions(user, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn roles(
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<Vec<RoleInfo>>> {
+    let user = auth.require(Scope::Admin)?;
+    let roles = service.roles(user).await?;
+    Ok(Json(roles.iter().map(RoleInfo::from).collect()))
+}
+
+async fn assign_role(
+    Path((id, role_id)): Path<(Uuid, Uuid)>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<StatusCode> {
+    let user = auth.require(Scope::Admin)?;
+    service.assign_role(user, id, role_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn unassign_role(
+    Path((id, role_id)): Path<(Uuid, Uuid)>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<StatusCode> {
+    let user = auth.require(Scope::Admin)?;
+    service.unassign_role(user, id, role_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn audit_log(
+    Query(q): Query<AuditQuery>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<Vec<AuditInfo>>> {
+    let user = auth.require(Scope::Admin)?;
+    let filter = AuditFilter {
+        user_id: q.user_id,
+        config_id: q.config_id,
+    };
+    let entries = service.audit_log(user, filter, q.offset, q.limit).await?;
+    Ok(Json(entries.into_iter().map(AuditInfo::from).collect()))
+}
+
+async fn server(
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<Vec<InterfaceInfo>>> {
+    let user = auth.require(Scope::Admin)?;
+    let interfaces = service.server_overview(user).await?;
+    Ok(Json(
+        interfaces.into_iter().map(InterfaceInfo::from).collect(),
+    ))
+}
+
+fn traffic_window(w: &StatsWindow) -> TrafficWindow {
+    match w {
+        StatsWindow::Today => TrafficWindow::Today,
+        StatsWindow::Week => TrafficWindow::Week,
+        StatsWindow::Month => TrafficWindow::Month,
+        StatsWindow::All => TrafficWindow::AllTime,
+    }
+}
+
+/// Traffic of every config, the busiest first
+async fn stats(
+    Query(q): Query<StatsQuery>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<Vec<PeerTraffic>>> {
+    let user = auth.require(Scope::Admin)?;
+    let peers = service.stats(user, traffic_window(&q.window)).await?;
+    Ok(Json(peers.into_iter().map(PeerTraffic::from).collect()))
+}
+
+/// Traffic of every user, the busiest first
+async fn user_stats(
+    Query(q): Query<StatsQuery>,
+    Extension(service): Extension<Arc<Wgcfg>>,
+    auth: Auth,
+) -> ApiResult<Json<Vec<UserSummaryInfo>>> {
+    let user = auth.require(Scope::Admin)?;
+    let users = service.user_stats(user, traffic_window(&q.window)).await?;
+    Ok(Json(users.iter().map(UserSummaryInfo::from).collect()))
+}
+
+/// Routes authenticated with a token from [`Wgcfg::api_token`] or a personal
+/// token from [`Wgcfg::create_api_token`]
+pub fn routes() -> Router {
+    Router::new()
+        .route("/me", get(me))
+        .route("/configs", get(configs).post(create_config))
+        .route(
+            "/configs/:id",
+            get(config).patch(rename_config).delete(remove_config),
+        )
+        .route("/configs/:id/file", get(config_file))
+        .route("/configs/:id/qr", get(config_qr))
+        .route("/configs/:id/rotate", post(rotate_key))
+        .route("/configs/:id/psk", post(rotate_psk))
+        .route("/configs/:id/pair", post(pair_code))
+        .route("/configs/:id/profile", put(set_config_profile))
+        .route("/configs/:id/exit", put(set_config_exit))
+        .route("/configs/:id/quota", put(config_quota))
+        .route("/configs/:id/expiry", put(set_expiry))
+        .route("/configs/:id/disabled", put(set_disabled))
+        .route("/configs/:id/traffic", get(config_traffic))
+        .route("/traffic", get(user_traffic))
+        .route("/keys", get(keys))
+        .route("/profiles", get(profiles).post(create_profile))
+        .route("/profiles/:id", delete(remove_profile))
+        .route("/exits", get(exits).post(create_exit))
+        .route("/exits/:id", delete(remove_exit))
+        .route("/requests", get(requests).post(create_request))
+        .route("/requests/pending", get(pending_requests))
+        .route("/requests/:id/approve", post(approve_request))
+        .route("/requests/:id/decline", post(decline_request))
+        .route("/users", get(users))
+        .route("/users/:id", get(user_summary))
+        .route("/users/:id/quota", put(user_quota))
+        .route("/users/:id/disabled", put(set_user_disabled))
+        .route("/users/:id/sessions", delete(end_sessions))
+        .route(
+            "/users/:id/roles/:role_id",
+            put(assign_role).delete(unassign_role),
+        )
+        .route("/admins", get(admins))
+        .route("/admins/:id", put(add_admin).delete(remove_admin))
+        .route("/roles", get(roles))
+        .route("/audit", get(audit_log))
+        .route("/server", get(server))
+        .route("/stats", get(stats))
+        .route("/stats/users", get(user_stats))
+        .route("/tokens", get(tokens))
+        .route("/tokens/all", get(all_tokens))
+        .route("/tokens/:id", delete(revoke_token))
+}
@@ -1,19 +1,91 @@
-use crate::service::{ConfigInfo, ServerInfo};
-
-pub fn format_config(config: &ConfigInfo, server_info: &ServerInfo) -> String {
-    format!(
-        "[Interface]
-Address = {ip}
-PrivateKey = {priv_key}
-ListenPort = 51820
-
-[Peer]
-PublicKey = {pub_key}
-Endpoint = {endpoint}
-AllowedIPs = 0.0.0.0/0, ::/0",
-        ip = config.ip,
-        priv_key = config.priv_key.as_deref().unwrap_or("<INSERT PRIVATE KEY>"),
-        pub_key = server_info.pub_key,
-        endpoint = server_info.addr
-    )
+use std::fmt::Write;
+
+use base64::Engine;
+
+use crate::service::{
+    configs::Config,
+    profiles::{join, Profile, Routing},
+    ServerInfo,
+};
+
+pub fn format_config(
+    config: &Config,
+    profile: &Profile,
+    server_info: Option<&ServerInfo>,
+) -> String {
+    let allowed_ips = match (&profile.routing, server_info) {
+        (Routing::Full, _) => "0.0.0.0/0, ::/0".to_owned(),
+        (Routing::Internal, Some(server_info)) => join(&server_info.ranges, ", "),
+        (Routing::Internal, None) => "<INSERT SERVER RANGES>".to_owned(),
+        (Routing::Custom(ips), _) => join(ips, ", "),
+    };
+
+    let mut res = String::new();
+    let _ = writeln!(res, "[Interface]");
+    let _ = writeln!(res, "Address = {}", join(&config.addrs(), ", "));
+    let _ = writeln!(
+        res,
+        "PrivateKey = {}",
+        config
+            .priv_key
+            .map(|k| base64::engine::general_purpose::STANDARD.encode(k))
+            .as_deref()
+            .unwrap_or("<INSERT PRIVATE KEY>")
+    );
+    if !profile.dns.is_empty() {
+        let _ = writeln!(res, "DNS = {}", join(&profile.dns, ", "));
+    }
+    if let Some(mtu) = profile.mtu {
+        let _ = writeln!(res, "MTU = {mtu}");
+    }
+
+    let _ = writeln!(res, "\n[Peer]");
+    let _ = writeln!(
+        res,
+        "PublicKey = {}",
+        server_info.map_or("<INSERT SERVER PUBLIC KEY>", |s| s.pub_key.as_str())
+    );
+    if let Some(psk) = config.psk {
+        let _ = writeln!(
+            res,
+            "PresharedKey = {}",
+            base64::engine::general_purpose::STANDARD.encode(psk)
+        );
+    }
+    let endpoint = server_info.map_or("<INSERT ENDPOINT>".to_owned(), |s| s.addr.to_string());
+    let _ = writeln!(res, "Endpoint = {endpoint}");
+    let _ = writeln!(res, "AllowedIPs = {allowed_ips}");
+    if let Some(keepalive) = profile.keepalive {
+        let _ = writeln!(res, "PersistentKeepalive = {keepalive}");
+    }
+    res
+}
+
+/// Renders `data` as a black on white PNG QR code.
+pub fn qr_code_png(data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    const SCALE: usize = 8;
+    const QUIET_ZONE: usize = 4;
+
+    let code = qrcode::QrCode::new(data)?;
+    let modules = code.width();
+    let colors = code.to_colors();
+    let size = (modules + 2 * QUIET_ZONE) * SCALE;
+
+    let mut pixels = vec![u8::MAX; size * size];
+    for (i, color) in colors.iter().enumerate() {
+        if *color != qrcode::Color::Dark {
+            continue;
+        }
+        let (x, y) = (i % modules + QUIET_ZONE, i / modules + QUIET_ZONE);
+        for row in y * SCALE..(y + 1) * SCALE {
+            pixels[row * size + x * SCALE..row * size + (x + 1) * SCALE].fill(0);
+        }
+    }
+
+    let mut res = Vec::new();
+    let mut encoder = png::Encoder::new(&mut res, size as u32, size as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)?;
+    Ok(res)
 }
@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::service::Wgcfg;
+
+/// Periodically disables expired configs and warns about expiring ones
+pub struct Expiry {
+    service: Wgcfg,
+    interval: Duration,
+}
+
+impl Expiry {
+    pub fn new(service: Wgcfg, interval: Duration) -> Self {
+        Self { service, interval }
+    }
+
+    pub async fn run(self) {
+        loop {
+            if let Err(e) = self.service.enforce_expiry().await {
+                warn!("expiry enforcement failed: {e}");
+            }
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+}
@@ -1,3 +1,6 @@
 
+pub mod expiry;
+pub mod quota;
+pub mod reconcile;
 pub mod stats;
 
@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::service::Wgcfg;
+
+/// Periodically suspends and restores configs according to their quotas
+pub struct Quota {
+    service: Wgcfg,
+    interval: Duration,
+}
+
+impl Quota {
+    pub fn new(service: Wgcfg, interval: Duration) -> Self {
+        Self { service, interval }
+    }
+
+    pub async fn run(self) {
+        loop {
+            if let Err(e) = self.service.enforce_quotas().await {
+                warn!("quota enforcement failed: {e}");
+            }
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use tracing::{debug, warn};
+
+use crate::service::Wgcfg;
+
+pub struct Reconcile {
+    service: Wgcfg,
+    interval: Duration,
+}
+
+impl Reconcile {
+    pub fn new(service: Wgcfg, interval: Duration) -> Self {
+        Self { service, interval }
+    }
+
+    pub async fn run(self) {
+        loop {
+            // Wgcfg::init has just synced everything, no need to start right away
+            tokio::time::sleep(self.interval).await;
+
+            match self.service.reconcile().await {
+                Ok(report) => {
+                    for correction in &report.corrections {
+                        warn!(?correction, "kernel state drifted from database, fixed");
+                    }
+                    if report.failures > 0 {
+                        warn!(
+                            failures = report.failures,
+                            "some checks failed during reconciliation"
+                        );
+                    }
+                    debug!(
+                        corrections = report.corrections.len(),
+                        failures = report.failures,
+                        "reconciliation finished"
+                    );
+                }
+                Err(e) => warn!("reconciliation failed: {e}"),
+            }
+        }
+    }
+}
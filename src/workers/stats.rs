@@ -1,43 +1,142 @@
 use std::{
     collections::{hash_map::Entry, HashMap},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+use clap::Parser;
 use netlink_packet_wireguard::constants::WG_KEY_LEN;
+use time::{Date, Month, OffsetDateTime};
 
 use crate::{
-    database::Database,
+    database::{Database, Period},
+    metrics,
     netlink::{error::NetlinkError, wireguard::WireguardInterfaceId, Netlink},
 };
 
+/// How long traffic history is kept, 0 keeps it forever
+#[derive(Debug, Clone, Parser)]
+pub struct Retention {
+    #[clap(
+        long,
+        env = "SAMPLES_RETENTION_HOURS",
+        default_value = "48",
+        value_parser
+    )]
+    samples_retention_hours: u64,
+    #[clap(
+        long,
+        env = "HOURLY_RETENTION_DAYS",
+        default_value = "14",
+        value_parser
+    )]
+    hourly_retention_days: u64,
+    #[clap(
+        long,
+        env = "DAILY_RETENTION_DAYS",
+        default_value = "400",
+        value_parser
+    )]
+    daily_retention_days: u64,
+    #[clap(
+        long,
+        env = "MONTHLY_RETENTION_MONTHS",
+        default_value = "0",
+        value_parser
+    )]
+    monthly_retention_months: u64,
+}
+
+impl Retention {
+    /// Cutoffs for [`Database::prune_traffic`]
+    fn cutoffs(&self, now: OffsetDateTime) -> (Option<i64>, Vec<(Period, i64)>) {
+        let ago = |d: time::Duration| (now - d).unix_timestamp();
+        let samples = (self.samples_retention_hours > 0)
+            .then(|| ago(time::Duration::hours(self.samples_retention_hours as _)));
+
+        let mut rollups = Vec::new();
+        if self.hourly_retention_days > 0 {
+            rollups.push((
+                Period::Hour,
+                ago(time::Duration::days(self.hourly_retention_days as _)),
+            ));
+        }
+        if self.daily_retention_days > 0 {
+            rollups.push((
+                Period::Day,
+                ago(time::Duration::days(self.daily_retention_days as _)),
+            ));
+        }
+        if self.monthly_retention_months > 0 {
+            // first day of the month `monthly_retention_months` ago
+            let months = now.year() as i64 * 12 + now.month() as i64
+                - 1
+                - self.monthly_retention_months as i64;
+            let month = Month::try_from((months.rem_euclid(12) + 1) as u8).unwrap();
+            if let Ok(date) = Date::from_calendar_date(months.div_euclid(12) as i32, month, 1) {
+                rollups.push((Period::Month, date.midnight().assume_utc().unix_timestamp()));
+            }
+        }
+        (samples, rollups)
+    }
+}
+
 pub struct Stats {
     prev: HashMap<[u8; WG_KEY_LEN], (u64, u64)>,
+    /// Last written handshake per key, to skip unchanged ones
+    handshakes: HashMap<[u8; WG_KEY_LEN], OffsetDateTime>,
     netlink: Netlink,
     db: Database,
-    id: String,
+    ids: Vec<String>,
+    retention: Retention,
 }
 
 impl Stats {
-    pub async fn new(id: String, db: Database) -> Result<Self, NetlinkError> {
+    pub async fn new(
+        ids: Vec<String>,
+        db: Database,
+        retention: Retention,
+    ) -> Result<Self, NetlinkError> {
         Ok(Self {
             prev: Default::default(),
+            handshakes: Default::default(),
             netlink: Netlink::new()?,
             db,
-            id,
+            ids,
+            retention,
         })
     }
 
     pub async fn run(mut self) {
         loop {
-            let info = self
-                .netlink
-                .wg_interface(WireguardInterfaceId::Name(self.id.clone()))
-                .await
-                .expect("test")
-                .peers;
+            let started = Instant::now();
+            let mut errors = 0;
+            let mut info = Vec::new();
+            for id in &self.ids {
+                match self
+                    .netlink
+                    .wg_interface(WireguardInterfaceId::Name(id.clone()))
+                    .await
+                {
+                    Ok(iface) => info.extend(iface.peers),
+                    Err(e) => {
+                        errors += 1;
+                        tracing::warn!("read peers of {id} failed with error: {e}");
+                    }
+                }
+            }
             let mut changes = Vec::new();
+            let mut handshakes = Vec::new();
 
             for i in info {
+                if let Some(t) = i.last_handshake {
+                    if self.handshakes.insert(i.public_key, t) != Some(t) {
+                        handshakes.push((
+                            i.public_key,
+                            t.unix_timestamp(),
+                            i.endpoint.map(|e| e.to_string()),
+                        ));
+                    }
+                }
                 let old = match self.prev.entry(i.public_key) {
                     Entry::Occupied(mut data) => data.insert((i.tx, i.rx)),
                     Entry::Vacant(entry) => {
@@ -51,7 +150,26 @@ impl Stats {
                     changes.push((i.public_key, i.tx - old.0, i.rx - old.1));
                 }
             }
-            self.db.update_peers_stats(changes).await.expect("test");
+            let now = OffsetDateTime::now_utc();
+            if let Err(e) = self.db.update_peers_stats(changes, now).await {
+                errors += 1;
+                tracing::warn!("update peers stats failed with error: {e}");
+            }
+            if let Err(e) = self.db.update_handshakes(handshakes).await {
+                errors += 1;
+                tracing::warn!("update handshakes failed with error: {e}");
+            }
+
+            let (samples, rollups) = self.retention.cutoffs(now);
+            match self.db.prune_traffic(samples, &rollups).await {
+                Ok(0) => {}
+                Ok(n) => tracing::debug!("pruned {n} traffic history rows"),
+                Err(e) => {
+                    errors += 1;
+                    tracing::warn!("prune traffic history failed with error: {e}");
+                }
+            }
+            metrics::observe_stats_cycle(started.elapsed(), errors);
 
             tokio::time::sleep(Duration::from_secs(60)).await;
         }